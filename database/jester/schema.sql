@@ -1,3 +1,19 @@
+DROP TABLE IF EXISTS chronicle_jobs;
+DROP TABLE IF EXISTS chronicle_transcript_fts;
+DROP TABLE IF EXISTS campaign_entity_mentions;
+DROP TABLE IF EXISTS campaign_entities;
+DROP TABLE IF EXISTS chronicle_deletions;
+DROP TABLE IF EXISTS chronicle_consent_log;
+DROP TABLE IF EXISTS chronicle_consent;
+DROP TABLE IF EXISTS chronicle_participants;
+DROP TABLE IF EXISTS chronicle_sessions;
+DROP TABLE IF EXISTS campaign_glossary;
+DROP TABLE IF EXISTS campaign_characters;
+DROP TABLE IF EXISTS campaigns;
+DROP TABLE IF EXISTS guild_track_tags;
+DROP TABLE IF EXISTS guild_track_overrides;
+DROP TABLE IF EXISTS guild_tracks;
+DROP TABLE IF EXISTS guild_settings;
 DROP TABLE IF EXISTS tags;
 DROP TABLE IF EXISTS track_tags;
 DROP TABLE IF EXISTS artists;
@@ -12,6 +28,8 @@ CREATE TABLE tracks (
     track_title TEXT NOT NULL,
     artist_id INTEGER NOT NULL,
     origin_id INTEGER NOT NULL,
+    shared INTEGER NOT NULL DEFAULT 1,    -- Whether the track sits in the global pool every guild can see
+    owner_guild_id INTEGER,               -- The guild that added it, the only one that can change `shared`; NULL for the base library
     FOREIGN KEY (artist_id) REFERENCES artists (id) ON DELETE CASCADE,
     FOREIGN KEY (origin_id) REFERENCES origins (id) ON DELETE CASCADE
 );
@@ -39,10 +57,181 @@ CREATE TABLE track_tags (
     FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
 );
 
+CREATE TABLE guild_settings (
+    guild_id INTEGER PRIMARY KEY,
+    share_global INTEGER NOT NULL DEFAULT 1 -- Whether the guild sees shared tracks it doesn't own
+);
+
+-- Tracks in a guild's own library, which it sees whether they're shared or not
+CREATE TABLE guild_tracks (
+    guild_id INTEGER NOT NULL,
+    track_id TEXT NOT NULL,
+    PRIMARY KEY (guild_id, track_id),
+    FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE
+);
+
+-- A guild's own view of a track, which never changes whether it can see it
+CREATE TABLE guild_track_overrides (
+    guild_id INTEGER NOT NULL,
+    track_id TEXT NOT NULL,
+    track_title TEXT,                     -- NULL inherits from `tracks`
+    artist_id INTEGER,
+    origin_id INTEGER,
+    tags_overridden INTEGER NOT NULL DEFAULT 0, -- Ignore the track's global tags in this guild
+    hidden INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, track_id),
+    FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE,
+    FOREIGN KEY (artist_id) REFERENCES artists (id) ON DELETE SET NULL,
+    FOREIGN KEY (origin_id) REFERENCES origins (id) ON DELETE SET NULL
+);
+
+CREATE TABLE guild_track_tags (
+    guild_id INTEGER NOT NULL,
+    track_id TEXT NOT NULL,
+    tag_id INTEGER NOT NULL,
+    PRIMARY KEY (guild_id, track_id, tag_id),
+    FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+);
+
+CREATE TABLE campaigns (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    name TEXT NOT NULL COLLATE NOCASE,    -- Unique per guild ignoring case, like lookups
+    obsidian_folder TEXT NOT NULL,        -- Relative to the configured vault
+    channel_id INTEGER,                   -- Text or voice channel the campaign is played in
+    archived INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,          -- Unix seconds
+    audio_retention_days INTEGER,         -- Overrides the server rule; NULL inherits it, -1 keeps forever
+    transcript_retention_days INTEGER,
+    UNIQUE (guild_id, name)
+);
+
+CREATE TABLE campaign_characters (
+    campaign_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,             -- The player's Discord ID
+    character_name TEXT NOT NULL,
+    character_class TEXT,                 -- e.g. "bard", rendered as "Thalia the bard"
+    PRIMARY KEY (campaign_id, user_id),
+    FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+);
+
+CREATE TABLE campaign_glossary (
+    campaign_id INTEGER NOT NULL,
+    term TEXT NOT NULL COLLATE NOCASE,    -- As it should be spelled, e.g. "Xanathar"
+    kind TEXT NOT NULL,                   -- npc, place, spell, item or other
+    created_at INTEGER NOT NULL,          -- Unix seconds
+    PRIMARY KEY (campaign_id, term),
+    FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+);
+
+CREATE TABLE chronicle_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    voice_channel_id INTEGER NOT NULL,
+    text_channel_id INTEGER NOT NULL,     -- Where start/stop announcements are posted
+    campaign_id INTEGER,
+    session_number INTEGER,               -- Position within the campaign, from 1
+    started_at INTEGER NOT NULL,          -- Unix seconds
+    ended_at INTEGER,
+    state TEXT NOT NULL,                  -- recording, paused, stopped or interrupted
+    storage_dir TEXT NOT NULL,            -- Per-speaker audio and manifest
+    approved_at INTEGER                   -- When the summary was approved; retention counts from here
+);
+
+CREATE TABLE chronicle_participants (
+    session_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (session_id, user_id),
+    FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+);
+
+CREATE TABLE chronicle_consent (
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    consented INTEGER NOT NULL,           -- 1 to be recorded, 0 opted out; no row means not asked yet
+    updated_at INTEGER NOT NULL,          -- Unix seconds
+    PRIMARY KEY (guild_id, user_id)
+);
+
+CREATE TABLE chronicle_consent_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    consented INTEGER NOT NULL,
+    source TEXT NOT NULL,                 -- prompt or command
+    session_id INTEGER,                   -- The recording the change was made during, if any
+    changed_at INTEGER NOT NULL           -- Unix seconds
+);
+
+CREATE TABLE chronicle_deletions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    guild_id INTEGER NOT NULL,
+    session_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,                   -- raw_audio or transcript
+    path TEXT NOT NULL,
+    bytes INTEGER NOT NULL,
+    rule TEXT NOT NULL,                   -- The retention rule that expired it
+    deleted_at INTEGER NOT NULL           -- Unix seconds
+);
+
+CREATE TABLE campaign_entities (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    campaign_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,                   -- npc, location, item or quest
+    name TEXT NOT NULL COLLATE NOCASE,
+    status TEXT,                          -- As of the latest session that gave one, e.g. "dead" or "completed"
+    description TEXT,                     -- Likewise
+    details TEXT,                         -- An NPC's attitude, an item's holder or a quest's giver
+    updated_at INTEGER NOT NULL,          -- Unix seconds
+    UNIQUE (campaign_id, kind, name),
+    FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+);
+
+CREATE TABLE campaign_entity_mentions (
+    entity_id INTEGER NOT NULL,
+    session_id INTEGER NOT NULL,
+    status TEXT,                          -- What the session said, kept to track changes over time
+    description TEXT,
+    details TEXT,
+    PRIMARY KEY (entity_id, session_id),
+    FOREIGN KEY (entity_id) REFERENCES campaign_entities (id) ON DELETE CASCADE,
+    FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+);
+
+-- Full-text index of transcript lines; removed with the transcript by retention
+CREATE VIRTUAL TABLE chronicle_transcript_fts USING fts5(
+    text,
+    speaker_name,
+    session_id UNINDEXED,
+    campaign_id UNINDEXED,
+    speaker UNINDEXED,                    -- The speaker's Discord ID, if known
+    start_ms UNINDEXED,                   -- On the session timeline
+    tokenize = 'porter unicode61'
+);
+
+-- One row per processing stage of a session, so a run can resume or restart part-way
+CREATE TABLE chronicle_jobs (
+    session_id INTEGER NOT NULL,
+    stage TEXT NOT NULL,                  -- vad, transcribe, correct, summarise, entities or export
+    status TEXT NOT NULL,                 -- pending, running, done, skipped or failed
+    inputs TEXT,                          -- What the stage ran with, e.g. prompt versions and model
+    input_hash TEXT,                      -- Hash of the inputs and the previous stage's output
+    output_hash TEXT,                     -- Hash of what the stage wrote
+    error TEXT,
+    started_at INTEGER,
+    finished_at INTEGER,
+    PRIMARY KEY (session_id, stage),
+    FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+);
+
 CREATE INDEX IF NOT EXISTS idx_artists_lower ON artists(LOWER(artist));
 CREATE INDEX IF NOT EXISTS idx_origins_lower ON origins(LOWER(origin));
 CREATE INDEX IF NOT EXISTS idx_tags_lower ON tags(LOWER(tag));
 CREATE INDEX IF NOT EXISTS idx_tracks_lower_title ON tracks(LOWER(track_title));
+CREATE INDEX IF NOT EXISTS idx_guild_tracks_track ON guild_tracks(track_id);
+CREATE INDEX IF NOT EXISTS idx_chronicle_consent_log_user ON chronicle_consent_log(guild_id, user_id);
+CREATE INDEX IF NOT EXISTS idx_campaign_entity_mentions_session ON campaign_entity_mentions(session_id);
 
 INSERT INTO artists (artist) VALUES ("No artist provided");
 INSERT INTO origins (origin) VALUES ("No origin provided");
@@ -0,0 +1,379 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::StreamExt;
+use poise::serenity_prelude::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, warn};
+
+use crate::chronicle::config::LlamaConfig;
+use crate::definitions::Error;
+
+/// Tokens kept free on top of the prompt and completion, for the chat template's
+/// own markup which the tokenizer endpoint doesn't see.
+const TEMPLATE_OVERHEAD_TOKENS: usize = 64;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CompletionRequest {
+    pub system: Option<String>,
+    pub prompt: String,
+    /// Falls back to the backend's configured limit
+    pub max_tokens: Option<usize>,
+    pub temperature: Option<f32>,
+}
+
+impl CompletionRequest {
+    pub fn new(system: Option<String>, prompt: String) -> Self {
+        Self { system, prompt, ..Default::default() }
+    }
+}
+
+/// Text-generation backend.
+#[async_trait]
+pub trait Llm: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Tokens the model accepts, prompt and completion together.
+    fn context_window(&self) -> usize;
+
+    /// Completion length used when a request doesn't set one.
+    fn default_max_tokens(&self) -> usize;
+
+    async fn count_tokens(&self, text: &str) -> Result<usize, Error>;
+
+    /// Generates a completion, passing each piece to `on_token` as it arrives.
+    async fn complete_streaming(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String, Error>;
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<String, Error> {
+        self.complete_streaming(request, &mut |_: &str| {}).await
+    }
+
+    /// Tokens left for prompt text once the completion and template are reserved.
+    fn prompt_budget(&self, max_tokens: Option<usize>) -> usize {
+        self.context_window()
+            .saturating_sub(max_tokens.unwrap_or(self.default_max_tokens()))
+            .saturating_sub(TEMPLATE_OVERHEAD_TOKENS)
+    }
+
+    /// Errors if the request's prompt would push the completion out of the context window.
+    async fn check_fits(&self, request: &CompletionRequest) -> Result<(), Error> {
+        let mut tokens = self.count_tokens(&request.prompt).await?;
+        if let Some(system) = &request.system {
+            tokens += self.count_tokens(system).await?;
+        }
+
+        let budget = self.prompt_budget(request.max_tokens);
+        if tokens > budget {
+            return Err(format!(
+                "Prompt is {} tokens but only {} fit in {}'s {}-token context window",
+                tokens,
+                budget,
+                self.name(),
+                self.context_window(),
+            ).into());
+        }
+        Ok(())
+    }
+}
+
+/// llama.cpp's `llama-server`, through its OpenAI-compatible chat endpoint.
+pub struct LlamaCpp {
+    config: LlamaConfig,
+    http: reqwest::Client,
+}
+
+impl LlamaCpp {
+    pub fn new(config: LlamaConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .unwrap_or_default();
+        Self { config, http }
+    }
+
+    fn body(&self, request: &CompletionRequest) -> serde_json::Value {
+        let mut messages = Vec::new();
+        if let Some(system) = &request.system {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+        messages.push(json!({ "role": "user", "content": request.prompt }));
+
+        let mut body = json!({
+            "messages": messages,
+            "max_tokens": request.max_tokens.unwrap_or(self.config.max_tokens),
+            "temperature": request.temperature.unwrap_or(self.config.temperature),
+            "stream": true,
+            "cache_prompt": true,
+        });
+        if let Some(model) = &self.config.model {
+            body["model"] = json!(model);
+        }
+        body
+    }
+
+    /// One attempt. `Err((retryable, error))` tells the caller whether to try again;
+    /// once any of the completion has reached `on_token` it never is, since a retry
+    /// would stream the same text to the listener twice.
+    async fn attempt(
+        &self,
+        body: &serde_json::Value,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String, (bool, Error)> {
+        let response = self.http
+            .post(format!("{}/v1/chat/completions", self.config.server_url))
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| (true, format!("Failed to reach llama.cpp at {}: {}", self.config.server_url, e).into()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let retryable = status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+            return Err((retryable, format!("llama.cpp returned {}: {}", status, text.trim()).into()));
+        }
+
+        // Server-sent events: `data: {json}` lines, ending with `data: [DONE]`
+        let mut completion = String::new();
+        let mut buffer = String::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| (completion.is_empty(), format!("llama.cpp stream broke off: {}", e).into()))?;
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some(end) = buffer.find('\n') {
+                let line = buffer[..end].trim().to_string();
+                buffer.drain(..=end);
+
+                let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    return Ok(completion);
+                }
+
+                let event: StreamEvent = serde_json::from_str(data)
+                    .map_err(|e| (false, format!("Unexpected llama.cpp stream event: {}", e).into()))?;
+                for choice in event.choices {
+                    if let Some(content) = choice.delta.content {
+                        on_token(&content);
+                        completion.push_str(&content);
+                    }
+                }
+            }
+        }
+
+        Ok(completion)
+    }
+}
+
+#[async_trait]
+impl Llm for LlamaCpp {
+    fn name(&self) -> &'static str {
+        "llama.cpp"
+    }
+
+    fn context_window(&self) -> usize {
+        self.config.context_window
+    }
+
+    fn default_max_tokens(&self) -> usize {
+        self.config.max_tokens
+    }
+
+    async fn count_tokens(&self, text: &str) -> Result<usize, Error> {
+        let response = self.http
+            .post(format!("{}/tokenize", self.config.server_url))
+            .json(&json!({ "content": text }))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach llama.cpp at {}: {}", self.config.server_url, e))?
+            .error_for_status()
+            .map_err(|e| format!("llama.cpp tokenize failed: {}", e))?;
+
+        let tokens: TokenizeResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Unexpected llama.cpp tokenize response: {}", e))?;
+        Ok(tokens.tokens.len())
+    }
+
+    async fn complete_streaming(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String, Error> {
+        self.check_fits(request).await?;
+        let body = self.body(request);
+
+        let mut attempt = 0;
+        loop {
+            match self.attempt(&body, &mut *on_token).await {
+                Ok(completion) => {
+                    debug!(chars = completion.len(), "llama.cpp completion finished");
+                    return Ok(completion);
+                }
+                Err((true, e)) if attempt < self.config.retries => {
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt);
+                    warn!(attempt = attempt + 1, error = %e, "llama.cpp request failed; retrying in {:?}", delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err((_, e)) => return Err(e),
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenizeResponse {
+    tokens: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct StreamEvent {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    delta: StreamDelta,
+}
+
+#[derive(Deserialize)]
+struct StreamDelta {
+    content: Option<String>,
+}
+
+/// Deterministic stand-in for a model: returns queued responses in order, then a
+/// fixed digest of the prompt. Counts one token per whitespace-separated word.
+pub struct MockLlm {
+    context_window: usize,
+    max_tokens: usize,
+    responses: Mutex<VecDeque<String>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl MockLlm {
+    pub fn new(context_window: usize, max_tokens: usize) -> Self {
+        Self {
+            context_window,
+            max_tokens,
+            responses: Mutex::new(VecDeque::new()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn push_response(&self, response: impl Into<String>) {
+        self.responses.lock().unwrap().push_back(response.into());
+    }
+
+    /// Every request received so far, oldest first.
+    #[cfg(test)]
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Llm for MockLlm {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn context_window(&self) -> usize {
+        self.context_window
+    }
+
+    fn default_max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    async fn count_tokens(&self, text: &str) -> Result<usize, Error> {
+        Ok(text.split_whitespace().count())
+    }
+
+    async fn complete_streaming(
+        &self,
+        request: &CompletionRequest,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String, Error> {
+        self.check_fits(request).await?;
+        self.requests.lock().unwrap().push(request.clone());
+
+        let response = self.responses.lock().unwrap().pop_front().unwrap_or_else(|| {
+            let words: Vec<&str> = request.prompt.split_whitespace().collect();
+            format!(
+                "[mock] {} words; begins \"{}\"",
+                words.len(),
+                words.iter().take(12).copied().collect::<Vec<_>>().join(" "),
+            )
+        });
+
+        let words: Vec<String> = response.split(' ').map(str::to_string).collect();
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                on_token(" ");
+            }
+            on_token(word);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_streams_queued_responses_in_order() {
+        let llm = MockLlm::new(1_000, 100);
+        llm.push_response("first answer");
+        llm.push_response("second");
+
+        let mut streamed = String::new();
+        let first = llm
+            .complete_streaming(&CompletionRequest::new(None, "one".into()), &mut |token: &str| streamed.push_str(token))
+            .await
+            .unwrap();
+        assert_eq!(first, "first answer");
+        assert_eq!(streamed, first);
+
+        let second = llm.complete(&CompletionRequest::new(None, "two".into())).await.unwrap();
+        assert_eq!(second, "second");
+
+        let prompts: Vec<String> = llm.requests().into_iter().map(|r| r.prompt).collect();
+        assert_eq!(prompts, ["one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn mock_digests_the_prompt_once_the_queue_is_empty() {
+        let llm = MockLlm::new(1_000, 100);
+        let request = CompletionRequest::new(None, "the party enters the crypt".into());
+
+        let first = llm.complete(&request).await.unwrap();
+        assert_eq!(first, "[mock] 5 words; begins \"the party enters the crypt\"");
+        assert_eq!(llm.complete(&request).await.unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn prompts_that_crowd_out_the_completion_are_rejected() {
+        let llm = MockLlm::new(TEMPLATE_OVERHEAD_TOKENS + 10, 5);
+
+        let fits = CompletionRequest::new(None, "word ".repeat(5));
+        assert!(llm.complete(&fits).await.is_ok());
+
+        let too_long = CompletionRequest::new(Some("system words".into()), "word ".repeat(4));
+        assert!(llm.complete(&too_long).await.is_err());
+        assert_eq!(llm.requests().len(), 1);
+    }
+}
@@ -0,0 +1,32 @@
+pub mod llm;
+pub mod prompts;
+pub mod tts;
+pub mod whisper;
+
+use std::sync::Arc;
+
+use crate::chronicle::config::{AiConfig, LlmKind, TranscriberKind};
+use crate::ai::llm::{LlamaCpp, Llm, MockLlm};
+use crate::ai::tts::{Piper, Synthesizer};
+use crate::ai::whisper::{Transcriber, WhisperCpp};
+
+/// Builds the speech-to-text backend named by `chronicle.ai.transcriber`.
+pub fn transcriber(config: &AiConfig) -> Arc<dyn Transcriber> {
+    match config.transcriber {
+        TranscriberKind::Whisper => Arc::new(WhisperCpp::new(config.whisper.clone())),
+    }
+}
+
+/// Builds the text-generation backend named by `chronicle.ai.llm`.
+pub fn llm(config: &AiConfig) -> Arc<dyn Llm> {
+    match config.llm {
+        LlmKind::LlamaCpp => Arc::new(LlamaCpp::new(config.llama.clone())),
+        LlmKind::Mock => Arc::new(MockLlm::new(config.llama.context_window, config.llama.max_tokens)),
+    }
+}
+
+/// Builds the text-to-speech backend, if a voice is configured.
+pub fn synthesizer(config: &AiConfig) -> Option<Arc<dyn Synthesizer>> {
+    let model = config.tts.model.clone()?;
+    Some(Arc::new(Piper::new(config.tts.binary.clone(), model, config.tts.speaker)))
+}
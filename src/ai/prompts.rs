@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::definitions::Error;
+
+/// Campaign overrides live in this folder inside the campaign's Obsidian folder.
+pub const OVERRIDE_DIR: &str = "prompts";
+
+const SYSTEM_MARKER: &str = "=== system ===";
+const USER_MARKER: &str = "=== user ===";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PromptKind {
+    /// Notes on one chunk of a long transcript
+    ChunkSummary,
+    /// Combines chunk notes into the final session summary
+    SessionSummary,
+    /// The turning points of a session, from its summary
+    KeyEvents,
+    Npcs,
+    Locations,
+    Loot,
+    Quests,
+    /// "Previously on..." from earlier session summaries
+    Recap,
+}
+
+impl PromptKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PromptKind::ChunkSummary   => "chunk_summary",
+            PromptKind::SessionSummary => "session_summary",
+            PromptKind::KeyEvents      => "key_events",
+            PromptKind::Npcs           => "npcs",
+            PromptKind::Locations      => "locations",
+            PromptKind::Loot           => "loot",
+            PromptKind::Quests         => "quests",
+            PromptKind::Recap          => "recap",
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}.md", self.name())
+    }
+
+    /// The template compiled into the binary.
+    fn default_source(&self) -> &'static str {
+        match self {
+            PromptKind::ChunkSummary   => include_str!("../../config/prompts/chunk_summary.md"),
+            PromptKind::SessionSummary => include_str!("../../config/prompts/session_summary.md"),
+            PromptKind::KeyEvents      => include_str!("../../config/prompts/key_events.md"),
+            PromptKind::Npcs           => include_str!("../../config/prompts/npcs.md"),
+            PromptKind::Locations      => include_str!("../../config/prompts/locations.md"),
+            PromptKind::Loot           => include_str!("../../config/prompts/loot.md"),
+            PromptKind::Quests         => include_str!("../../config/prompts/quests.md"),
+            PromptKind::Recap          => include_str!("../../config/prompts/recap.md"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PromptSource {
+    Default,
+    Override(PathBuf),
+}
+
+/// A parsed template:
+///
+/// ```text
+/// version: 1
+/// === system ===
+/// You are the chronicler for "{{campaign}}"...
+/// === user ===
+/// Summarise {{transcript}}
+/// ```
+#[derive(Clone, Debug)]
+pub struct PromptTemplate {
+    pub kind: PromptKind,
+    pub version: u32,
+    pub source: PromptSource,
+    pub system: String,
+    pub user: String,
+}
+
+/// A template with its variables filled in.
+#[derive(Clone, Debug)]
+pub struct RenderedPrompt {
+    pub system: String,
+    pub user: String,
+}
+
+/// Values for `{{name}}` placeholders.
+#[derive(Clone, Debug, Default)]
+pub struct PromptVars {
+    values: HashMap<&'static str, String>,
+}
+
+impl PromptVars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.values.insert(name, value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+impl PromptTemplate {
+    /// The campaign's override if it has one, otherwise the built-in default.
+    pub fn load(kind: PromptKind, override_dir: Option<&Path>) -> Result<Self, Error> {
+        if let Some(dir) = override_dir {
+            let path = dir.join(kind.file_name());
+            if path.is_file() {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read prompt {}: {}", path.display(), e))?;
+                return Self::parse(kind, &content, PromptSource::Override(path.clone()))
+                    .map_err(|e| format!("Invalid prompt {}: {}", path.display(), e).into());
+            }
+        }
+
+        Self::parse(kind, kind.default_source(), PromptSource::Default)
+            .map_err(|e| format!("Invalid built-in prompt `{}`: {}", kind.name(), e).into())
+    }
+
+    pub fn parse(kind: PromptKind, content: &str, source: PromptSource) -> Result<Self, String> {
+        let content = content.replace("\r\n", "\n");
+        let (header, body) = content
+            .split_once(SYSTEM_MARKER)
+            .ok_or_else(|| format!("missing `{}` line", SYSTEM_MARKER))?;
+        let (system, user) = body
+            .split_once(USER_MARKER)
+            .ok_or_else(|| format!("missing `{}` line after the system section", USER_MARKER))?;
+
+        let version = header
+            .lines()
+            .map(str::trim)
+            .find_map(|line| line.strip_prefix("version:"))
+            .ok_or("missing `version: N` line before the system section")?
+            .trim()
+            .parse()
+            .map_err(|_| "`version` must be a whole number".to_string())?;
+
+        let template = Self {
+            kind,
+            version,
+            source,
+            system: system.trim().to_string(),
+            user: user.trim().to_string(),
+        };
+        if template.user.is_empty() {
+            return Err("the user section is empty".to_string());
+        }
+        for name in template.placeholders() {
+            if placeholder_name_invalid(&name) {
+                return Err(format!("`{{{{{}}}}}` is not a valid placeholder", name));
+            }
+        }
+        Ok(template)
+    }
+
+    /// Every `{{name}}` the template uses.
+    pub fn placeholders(&self) -> Vec<String> {
+        let mut names: Vec<String> = [&self.system, &self.user]
+            .into_iter()
+            .flat_map(|text| scan_placeholders(text))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Fills every placeholder; a placeholder without a value is an error so an
+    /// override with a typo doesn't send `{{campain}}` to the model.
+    pub fn render(&self, vars: &PromptVars) -> Result<RenderedPrompt, Error> {
+        Ok(RenderedPrompt {
+            system: self.fill(&self.system, vars)?,
+            user: self.fill(&self.user, vars)?,
+        })
+    }
+
+    fn fill(&self, text: &str, vars: &PromptVars) -> Result<String, Error> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find("}}") else {
+                out.push_str(&rest[start..]);
+                return Ok(out);
+            };
+
+            let name = after[..end].trim();
+            let value = vars.get(name).ok_or_else(|| {
+                let origin = match &self.source {
+                    PromptSource::Default => "built-in".to_string(),
+                    PromptSource::Override(path) => path.display().to_string(),
+                };
+                format!(
+                    "Prompt `{}` ({}) uses `{{{{{}}}}}`, which isn't available here",
+                    self.kind.name(),
+                    origin,
+                    name,
+                )
+            })?;
+            out.push_str(value);
+            rest = &after[end + 2..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Changes whenever the template text does, so cached model output made with an
+    /// older or different prompt is recognised as stale.
+    pub fn fingerprint(&self) -> String {
+        format!("{}-v{}-{:016x}", self.kind.name(), self.version, fnv1a(&[&self.system, &self.user]))
+    }
+}
+
+/// The folder a campaign's prompt overrides are read from.
+pub fn override_dir(vault: Option<&Path>, campaign_folder: &str) -> Option<PathBuf> {
+    vault.map(|vault| vault.join(campaign_folder).join(OVERRIDE_DIR))
+}
+
+fn scan_placeholders(text: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else { break };
+        names.push(after[..end].trim().to_string());
+        rest = &after[end + 2..];
+    }
+    names
+}
+
+fn placeholder_name_invalid(name: &str) -> bool {
+    name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// 64-bit FNV-1a; stable across builds, unlike `DefaultHasher`.
+pub fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
@@ -0,0 +1,71 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use poise::serenity_prelude::async_trait;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::definitions::Error;
+
+/// Text-to-speech backend.
+#[async_trait]
+pub trait Synthesizer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Reads `text` aloud into a WAV file at `output`.
+    async fn synthesize(&self, text: &str, output: &Path) -> Result<(), Error>;
+}
+
+/// Runs Piper locally; the text goes in on stdin.
+pub struct Piper {
+    binary: PathBuf,
+    model: PathBuf,
+    speaker: Option<u32>,
+}
+
+impl Piper {
+    pub fn new(binary: PathBuf, model: PathBuf, speaker: Option<u32>) -> Self {
+        Self { binary, model, speaker }
+    }
+}
+
+#[async_trait]
+impl Synthesizer for Piper {
+    fn name(&self) -> &'static str {
+        "piper"
+    }
+
+    async fn synthesize(&self, text: &str, output: &Path) -> Result<(), Error> {
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("--model").arg(&self.model)
+            .arg("--output_file").arg(output)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(speaker) = self.speaker {
+            command.arg("--speaker").arg(speaker.to_string());
+        }
+
+        debug!(output = %output.display(), "Running Piper");
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", self.binary.display(), e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // Piper reads one utterance per line
+            stdin.write_all(text.replace('\n', " ").as_bytes()).await?;
+        }
+
+        let result = child.wait_with_output().await?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(format!("Piper failed ({}): {}", result.status, stderr.trim()).into());
+        }
+        if !output.is_file() {
+            return Err(format!("Piper finished without writing {}", output.display()).into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use poise::serenity_prelude::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::chronicle::config::WhisperConfig;
+use crate::definitions::Error;
+
+/// whisper.cpp only accepts 16kHz mono 16-bit WAV input.
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Non-speech markers whisper emits for silence or noise.
+const NON_SPEECH: &[&str] = &["[BLANK_AUDIO]", "[SILENCE]", "[MUSIC]", "[NOISE]", "(silence)", "[ Silence ]"];
+
+/// One stretch of recognised speech. Times are milliseconds on the session timeline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+    /// Mean token probability, 0.0 to 1.0
+    pub confidence: f32,
+    /// Discord user ID of the speaker, when known
+    pub speaker: Option<u64>,
+}
+
+/// One speaker's audio to transcribe.
+#[derive(Clone, Debug)]
+pub struct TranscriptionRequest {
+    /// 16kHz mono WAV
+    pub audio: PathBuf,
+    pub speaker: Option<u64>,
+    /// Added to every segment time, for audio that starts part-way into the session
+    pub offset_ms: u64,
+    /// Text to prime the decoder with, e.g. names it should expect
+    pub prompt: Option<String>,
+}
+
+/// Speech-to-text backend.
+#[async_trait]
+pub trait Transcriber: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn transcribe(&self, request: &TranscriptionRequest) -> Result<Vec<Segment>, Error>;
+}
+
+/// Runs whisper.cpp locally, either by invoking its CLI per file or by posting to a
+/// `whisper-server` on this machine or the local network.
+pub struct WhisperCpp {
+    config: WhisperConfig,
+    http: reqwest::Client,
+}
+
+impl WhisperCpp {
+    pub fn new(config: WhisperConfig) -> Self {
+        Self { config, http: reqwest::Client::new() }
+    }
+
+    async fn transcribe_with_binary(&self, request: &TranscriptionRequest) -> Result<Vec<Segment>, Error> {
+        let output_prefix = request.audio.with_extension("whisper");
+        let output_json = output_prefix.with_extension("whisper.json");
+
+        let mut command = Command::new(&self.config.binary);
+        command
+            .arg("--model").arg(&self.config.model)
+            .arg("--file").arg(&request.audio)
+            .arg("--language").arg(&self.config.language)
+            .arg("--threads").arg(self.config.threads.to_string())
+            .arg("--output-json-full")
+            .arg("--output-file").arg(&output_prefix)
+            .arg("--no-prints")
+            .kill_on_drop(true);
+        if let Some(prompt) = &request.prompt {
+            command.arg("--prompt").arg(prompt);
+        }
+
+        debug!(audio = %request.audio.display(), "Running whisper.cpp");
+        let output = command
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run {}: {}", self.config.binary.display(), e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!(
+                "whisper.cpp failed on {} ({}): {}",
+                request.audio.display(),
+                output.status,
+                stderr.trim(),
+            ).into());
+        }
+
+        let content = tokio::fs::read_to_string(&output_json)
+            .await
+            .map_err(|e| format!("Failed to read whisper output {}: {}", output_json.display(), e))?;
+        tokio::fs::remove_file(&output_json).await.ok();
+
+        let parsed: CliOutput = serde_json::from_str(&content)
+            .map_err(|e| format!("Unexpected whisper output in {}: {}", output_json.display(), e))?;
+
+        Ok(parsed.transcription
+            .into_iter()
+            .filter_map(|s| {
+                let confidence = mean_token_probability(&s.tokens);
+                segment(request, s.offsets.from, s.offsets.to, &s.text, confidence)
+            })
+            .collect())
+    }
+
+    async fn transcribe_with_server(&self, url: &str, request: &TranscriptionRequest) -> Result<Vec<Segment>, Error> {
+        let audio = tokio::fs::read(&request.audio)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", request.audio.display(), e))?;
+        let file_name = request.audio
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "audio.wav".to_string());
+
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", reqwest::multipart::Part::bytes(audio).file_name(file_name))
+            .text("response_format", "verbose_json")
+            .text("temperature", "0.0")
+            .text("language", self.config.language.clone());
+        if let Some(prompt) = &request.prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+
+        let response = self.http
+            .post(format!("{url}/inference"))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach whisper server at {}: {}", url, e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("whisper server returned {}: {}", status, body.trim()).into());
+        }
+
+        let parsed: ServerOutput = response
+            .json()
+            .await
+            .map_err(|e| format!("Unexpected whisper server response: {}", e))?;
+
+        Ok(parsed.segments
+            .into_iter()
+            .filter_map(|s| {
+                let confidence = s.avg_logprob.map_or(1.0, |lp| lp.exp().clamp(0.0, 1.0));
+                segment(
+                    request,
+                    (s.start * 1000.0).round() as u64,
+                    (s.end * 1000.0).round() as u64,
+                    &s.text,
+                    confidence,
+                )
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Transcriber for WhisperCpp {
+    fn name(&self) -> &'static str {
+        "whisper.cpp"
+    }
+
+    async fn transcribe(&self, request: &TranscriptionRequest) -> Result<Vec<Segment>, Error> {
+        check_input(&request.audio)?;
+
+        match &self.config.server_url {
+            Some(url) => self.transcribe_with_server(url, request).await,
+            None => self.transcribe_with_binary(request).await,
+        }
+    }
+}
+
+/// Rejects audio whisper.cpp would refuse, with a clearer message than it gives.
+fn check_input(path: &Path) -> Result<(), Error> {
+    let reader = hound::WavReader::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let spec = reader.spec();
+    if spec.sample_rate != WHISPER_SAMPLE_RATE || spec.channels != 1 || spec.bits_per_sample != 16 {
+        return Err(format!(
+            "{} is {} Hz, {} channel(s), {}-bit; whisper needs 16000 Hz mono 16-bit",
+            path.display(),
+            spec.sample_rate,
+            spec.channels,
+            spec.bits_per_sample,
+        ).into());
+    }
+    Ok(())
+}
+
+fn segment(
+    request: &TranscriptionRequest,
+    start_ms: u64,
+    end_ms: u64,
+    text: &str,
+    confidence: f32,
+) -> Option<Segment> {
+    let text = text.trim();
+    if text.is_empty() || NON_SPEECH.iter().any(|marker| text.eq_ignore_ascii_case(marker)) {
+        return None;
+    }
+
+    Some(Segment {
+        start_ms: request.offset_ms + start_ms,
+        end_ms: request.offset_ms + end_ms.max(start_ms),
+        text: text.to_string(),
+        confidence,
+        speaker: request.speaker,
+    })
+}
+
+/// Special tokens such as `[_BEG_]` and `[_TT_150]` carry no meaningful probability.
+fn mean_token_probability(tokens: &[CliToken]) -> f32 {
+    let probabilities: Vec<f32> = tokens
+        .iter()
+        .filter(|t| !t.text.starts_with("[_"))
+        .map(|t| t.p)
+        .collect();
+    if probabilities.is_empty() {
+        return 1.0;
+    }
+    probabilities.iter().sum::<f32>() / probabilities.len() as f32
+}
+
+// `whisper-cli --output-json-full`
+
+#[derive(Deserialize)]
+struct CliOutput {
+    transcription: Vec<CliSegment>,
+}
+
+#[derive(Deserialize)]
+struct CliSegment {
+    offsets: CliOffsets,
+    text: String,
+    #[serde(default)]
+    tokens: Vec<CliToken>,
+}
+
+#[derive(Deserialize)]
+struct CliOffsets {
+    from: u64,
+    to: u64,
+}
+
+#[derive(Deserialize)]
+struct CliToken {
+    text: String,
+    p: f32,
+}
+
+// `whisper-server` with `response_format=verbose_json`
+
+#[derive(Deserialize)]
+struct ServerOutput {
+    #[serde(default)]
+    segments: Vec<ServerSegment>,
+}
+
+#[derive(Deserialize)]
+struct ServerSegment {
+    start: f64,
+    end: f64,
+    text: String,
+    avg_logprob: Option<f32>,
+}
@@ -0,0 +1,453 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::ai::whisper::{Segment, WHISPER_SAMPLE_RATE};
+use crate::definitions::Error;
+
+/// Energy-based voice activity detection settings.
+#[derive(Clone, Debug)]
+pub struct VadConfig {
+    pub frame_ms: u32,
+    /// Frames this far above the track's noise floor count as speech
+    pub margin_db: f32,
+    /// Frames quieter than this are never speech, however quiet the track
+    pub min_speech_db: f32,
+    /// Speech shorter than this is treated as a click or breath
+    pub min_speech_ms: u32,
+    /// Kept before and after each stretch of speech so word edges aren't clipped
+    pub padding_ms: u32,
+    /// Pauses shorter than this stay in; longer ones are cut
+    pub max_pause_ms: u32,
+    /// Silence placed between kept stretches so the transcriber still sees a break
+    pub joint_ms: u32,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            frame_ms: 30,
+            margin_db: 12.0,
+            min_speech_db: -55.0,
+            min_speech_ms: 250,
+            padding_ms: 200,
+            max_pause_ms: 1_500,
+            joint_ms: 300,
+        }
+    }
+}
+
+/// A kept stretch of audio, in samples of the source track.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpeechRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Where each kept stretch of a trimmed file came from, so times reported against
+/// the trimmed audio can be moved back onto the session timeline.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimeMap {
+    pub spans: Vec<TimeSpan>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimeSpan {
+    pub trimmed_ms: u64,
+    pub original_ms: u64,
+    pub length_ms: u64,
+}
+
+impl TimeMap {
+    /// Maps a time in the trimmed audio to the original. Times inside a joint
+    /// between spans snap to the end of the span before it.
+    pub fn to_original(&self, trimmed_ms: u64) -> u64 {
+        let index = self.spans.partition_point(|s| s.trimmed_ms <= trimmed_ms);
+        let Some(span) = index.checked_sub(1).and_then(|i| self.spans.get(i)) else {
+            return self.spans.first().map_or(trimmed_ms, |s| s.original_ms);
+        };
+        span.original_ms + (trimmed_ms - span.trimmed_ms).min(span.length_ms)
+    }
+
+    pub fn remap(&self, segments: &mut [Segment]) {
+        for segment in segments {
+            segment.start_ms = self.to_original(segment.start_ms);
+            segment.end_ms = self.to_original(segment.end_ms).max(segment.start_ms);
+        }
+    }
+
+    /// How much of the original the trimmed audio keeps, without the joints.
+    pub fn speech_ms(&self) -> u64 {
+        self.spans.iter().map(|s| s.length_ms).sum()
+    }
+}
+
+/// What `prepare_for_transcription` produced.
+#[derive(Clone, Debug)]
+pub struct PreparedAudio {
+    pub time_map: TimeMap,
+    /// Length of the untrimmed track
+    pub original_ms: u64,
+}
+
+impl PreparedAudio {
+    pub fn is_silent(&self) -> bool {
+        self.time_map.spans.is_empty()
+    }
+}
+
+/// Cuts the long silences out of a recorded speaker track and writes what's left
+/// as 16kHz mono WAV for whisper. The source is streamed twice (once to measure,
+/// once to copy) so a four-hour track never has to fit in memory.
+pub fn prepare_for_transcription(input: &Path, output: &Path, config: &VadConfig) -> Result<PreparedAudio, Error> {
+    let mut reader = WavReader::open(input)
+        .map_err(|e| format!("Failed to open {}: {}", input.display(), e))?;
+    let spec = reader.spec();
+    if spec.channels != 1 || spec.bits_per_sample != 16 || spec.sample_format != SampleFormat::Int {
+        return Err(format!("{} must be 16-bit mono PCM", input.display()).into());
+    }
+    let rate = spec.sample_rate;
+    let total = reader.duration() as u64;
+
+    let frame_len = (rate as u64 * config.frame_ms as u64 / 1000).max(1) as usize;
+    let energies = frame_energies(reader.samples::<i16>(), frame_len)?;
+    let regions = detect_speech(&energies, frame_len as u64, total, rate, config);
+
+    let mut reader = WavReader::open(input)
+        .map_err(|e| format!("Failed to open {}: {}", input.display(), e))?;
+    let mut writer = WavWriter::create(output, WavSpec {
+        channels: 1,
+        sample_rate: WHISPER_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    })
+    .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    let mut resampler = Resampler::new(rate, WHISPER_SAMPLE_RATE);
+    let joint = vec![0i16; (rate as u64 * config.joint_ms as u64 / 1000) as usize];
+    let mut spans = Vec::with_capacity(regions.len());
+    let mut trimmed_samples: u64 = 0;
+    let mut position: u64 = 0;
+    let mut samples = reader.samples::<i16>();
+    let mut buffer = Vec::new();
+
+    for (index, region) in regions.iter().enumerate() {
+        if index > 0 {
+            write_all(&mut writer, &resampler.process(&joint))?;
+            trimmed_samples += joint.len() as u64;
+        }
+
+        // Skip to the region, then copy it
+        while position < region.start {
+            samples.next().transpose()?;
+            position += 1;
+        }
+        buffer.clear();
+        while position < region.end {
+            match samples.next().transpose()? {
+                Some(sample) => buffer.push(sample),
+                None => break,
+            }
+            position += 1;
+        }
+
+        spans.push(TimeSpan {
+            trimmed_ms: samples_to_ms(trimmed_samples, rate),
+            original_ms: samples_to_ms(region.start, rate),
+            length_ms: samples_to_ms(buffer.len() as u64, rate),
+        });
+        write_all(&mut writer, &resampler.process(&buffer))?;
+        trimmed_samples += buffer.len() as u64;
+    }
+    write_all(&mut writer, &resampler.flush())?;
+    writer.finalize().map_err(|e| format!("Failed to finalise {}: {}", output.display(), e))?;
+
+    Ok(PreparedAudio {
+        time_map: TimeMap { spans },
+        original_ms: samples_to_ms(total, rate),
+    })
+}
+
+fn write_all(writer: &mut WavWriter<std::io::BufWriter<std::fs::File>>, samples: &[i16]) -> Result<(), Error> {
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    Ok(())
+}
+
+fn samples_to_ms(samples: u64, rate: u32) -> u64 {
+    samples * 1000 / rate as u64
+}
+
+/// RMS level of each frame in dBFS.
+pub(crate) fn frame_energies<I>(samples: I, frame_len: usize) -> Result<Vec<f32>, Error>
+where
+    I: Iterator<Item = Result<i16, hound::Error>>,
+{
+    let mut energies = Vec::new();
+    let mut sum = 0f64;
+    let mut count = 0usize;
+    for sample in samples {
+        let sample = sample? as f64 / i16::MAX as f64;
+        sum += sample * sample;
+        count += 1;
+        if count == frame_len {
+            energies.push(to_db(sum / count as f64));
+            sum = 0.0;
+            count = 0;
+        }
+    }
+    if count > 0 {
+        energies.push(to_db(sum / count as f64));
+    }
+    Ok(energies)
+}
+
+fn to_db(mean_square: f64) -> f32 {
+    (10.0 * mean_square.max(1e-12).log10()) as f32
+}
+
+/// Finds the stretches worth transcribing from per-frame energies. The threshold
+/// adapts to each track: a margin above its noise floor (the 10th percentile of
+/// the non-silent frames), since microphones and rooms differ wildly.
+pub fn detect_speech(
+    energies: &[f32],
+    frame_len: u64,
+    total_samples: u64,
+    rate: u32,
+    config: &VadConfig,
+) -> Vec<SpeechRegion> {
+    // Recorder padding is digital silence; leave it out of the noise floor
+    let mut audible: Vec<f32> = energies.iter().copied().filter(|&e| e > -100.0).collect();
+    if audible.is_empty() {
+        return Vec::new();
+    }
+    audible.sort_by(|a, b| a.total_cmp(b));
+    let noise_floor = audible[audible.len() / 10];
+    let threshold = (noise_floor + config.margin_db).max(config.min_speech_db);
+
+    let ms_to_frames = |ms: u32| (rate as u64 * ms as u64 / 1000).div_ceil(frame_len) as usize;
+    let min_speech = ms_to_frames(config.min_speech_ms).max(1);
+    let padding = ms_to_frames(config.padding_ms);
+    let max_pause = ms_to_frames(config.max_pause_ms);
+
+    // Runs of loud frames
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    let mut start = None;
+    for (i, &energy) in energies.iter().enumerate() {
+        match (energy >= threshold, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                runs.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        runs.push((s, energies.len()));
+    }
+
+    // Bridge short pauses, then drop what's still too short to be speech
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in runs {
+        match merged.last_mut() {
+            Some(last) if s - last.1 <= max_pause => last.1 = e,
+            _ => merged.push((s, e)),
+        }
+    }
+
+    let mut regions: Vec<SpeechRegion> = Vec::new();
+    for (s, e) in merged.into_iter().filter(|(s, e)| e - s >= min_speech) {
+        let start = (s.saturating_sub(padding) as u64 * frame_len).min(total_samples);
+        let end = (((e + padding) as u64) * frame_len).min(total_samples);
+        match regions.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => regions.push(SpeechRegion { start, end }),
+        }
+    }
+    regions
+}
+
+/// Converts a whole in-memory clip, e.g. one live utterance, to another rate.
+pub fn resample(samples: &[i16], from: u32, to: u32) -> Vec<i16> {
+    let mut resampler = Resampler::new(from, to);
+    let mut output = resampler.process(samples);
+    output.extend(resampler.flush());
+    output
+}
+
+/// Streaming sample-rate converter: a windowed-sinc low-pass filter followed by
+/// fractional-step interpolation. For 48kHz to 16kHz this is a plain 3:1 decimator.
+struct Resampler {
+    step: f64,
+    taps: Vec<f32>,
+    history: Vec<f32>,
+    /// Position of the next output sample, in input samples relative to `history[0]`
+    next: f64,
+}
+
+impl Resampler {
+    const HALF_TAPS: usize = 16;
+
+    fn new(from: u32, to: u32) -> Self {
+        let step = from as f64 / to as f64;
+        // Cut off just below the output's Nyquist frequency
+        let cutoff = (0.45 / step.max(1.0)) as f32;
+        let len = 2 * Self::HALF_TAPS + 1;
+
+        let mut taps: Vec<f32> = (0..len)
+            .map(|i| {
+                let n = i as f32 - Self::HALF_TAPS as f32;
+                let sinc = if n == 0.0 { 2.0 * cutoff } else { (2.0 * PI * cutoff * n).sin() / (PI * n) };
+                let window = 0.54 - 0.46 * (2.0 * PI * i as f32 / (len - 1) as f32).cos();
+                sinc * window
+            })
+            .collect();
+        let sum: f32 = taps.iter().sum();
+        taps.iter_mut().for_each(|t| *t /= sum);
+
+        Self {
+            step,
+            taps,
+            history: vec![0.0; Self::HALF_TAPS],
+            next: Self::HALF_TAPS as f64,
+        }
+    }
+
+    fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        self.history.extend(input.iter().map(|&s| s as f32));
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+
+        while self.next + (Self::HALF_TAPS as f64) + 1.0 < self.history.len() as f64 {
+            let index = self.next.floor() as usize;
+            let frac = (self.next - index as f64) as f32;
+            let a = self.filtered(index);
+            let b = self.filtered(index + 1);
+            let value = a + (b - a) * frac;
+            output.push(value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            self.next += self.step;
+        }
+
+        // Keep only what the next call's filter window still needs
+        let keep_from = (self.next.floor() as usize).saturating_sub(Self::HALF_TAPS);
+        self.history.drain(..keep_from);
+        self.next -= keep_from as f64;
+        output
+    }
+
+    fn flush(&mut self) -> Vec<i16> {
+        self.process(&[0; 2 * Self::HALF_TAPS])
+    }
+
+    fn filtered(&self, center: usize) -> f32 {
+        let start = center - Self::HALF_TAPS;
+        self.taps
+            .iter()
+            .zip(&self.history[start..start + self.taps.len()])
+            .map(|(t, s)| t * s)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(trimmed_ms: u64, original_ms: u64, length_ms: u64) -> TimeSpan {
+        TimeSpan { trimmed_ms, original_ms, length_ms }
+    }
+
+    fn sine(freq: f32, rate: u32, amplitude: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| (amplitude * (2.0 * PI * freq * i as f32 / rate as f32).sin()) as i16)
+            .collect()
+    }
+
+    fn peak(samples: &[i16]) -> i16 {
+        samples.iter().map(|s| s.saturating_abs()).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn trimmed_times_map_back_onto_the_original() {
+        // Two kept stretches with a 200ms joint between them
+        let map = TimeMap { spans: vec![span(0, 1_000, 500), span(700, 5_000, 300)] };
+
+        assert_eq!(map.to_original(0), 1_000);
+        assert_eq!(map.to_original(250), 1_250);
+        assert_eq!(map.to_original(600), 1_500, "inside the joint snaps to the end of the span before");
+        assert_eq!(map.to_original(700), 5_000);
+        assert_eq!(map.to_original(900), 5_200);
+        assert_eq!(map.to_original(2_000), 5_300, "past the end clamps to the last span");
+        assert_eq!(map.speech_ms(), 800);
+
+        let mut segments = vec![Segment { start_ms: 600, end_ms: 650, text: String::new(), confidence: 1.0, speaker: None }];
+        map.remap(&mut segments);
+        assert_eq!((segments[0].start_ms, segments[0].end_ms), (1_500, 1_500));
+
+        assert_eq!(TimeMap::default().to_original(1_234), 1_234);
+    }
+
+    #[test]
+    fn speech_is_found_above_the_noise_floor() {
+        let config = VadConfig::default();
+        let rate = 48_000;
+        let frame_len = 1_440; // 30ms
+        let mut energies = Vec::new();
+        for (frames, level) in [
+            (100, -120.0), // recorder padding
+            (100, -60.0),
+            (30, -20.0),   // speech at 200..230
+            (20, -60.0),   // a pause short enough to bridge
+            (30, -20.0),   // speech at 250..280
+            (100, -60.0),
+            (3, -20.0),    // a click at 380, too short to keep
+            (100, -60.0),
+            (60, -20.0),   // speech at 483..543
+            (50, -60.0),
+        ] {
+            energies.extend(std::iter::repeat_n(level, frames));
+        }
+        let total = energies.len() as u64 * frame_len;
+
+        let regions = detect_speech(&energies, frame_len, total, rate, &config);
+        // 200ms of padding is 7 frames either side
+        assert_eq!(regions, vec![
+            SpeechRegion { start: 193 * frame_len, end: 287 * frame_len },
+            SpeechRegion { start: 476 * frame_len, end: 550 * frame_len },
+        ]);
+
+        assert!(detect_speech(&[-120.0; 50], frame_len, 50 * frame_len, rate, &config).is_empty());
+        assert!(detect_speech(&[-60.0; 50], frame_len, 50 * frame_len, rate, &config).is_empty());
+    }
+
+    #[test]
+    fn resampling_keeps_the_passband_and_removes_aliases() {
+        let len = 48_000;
+        let tone = resample(&sine(1_000.0, 48_000, 10_000.0, len), 48_000, 16_000);
+        // The flush lets the filter's tail out, a few samples past the end
+        assert!((16_000..16_000 + Resampler::HALF_TAPS).contains(&tone.len()), "{} samples", tone.len());
+        let steady = &tone[100..tone.len() - 100];
+        assert!((peak(steady) - 10_000).abs() < 300, "peak {}", peak(steady));
+
+        // 12kHz can't be represented at 16kHz and must not fold back as 4kHz
+        let alias = resample(&sine(12_000.0, 48_000, 10_000.0, len), 48_000, 16_000);
+        assert!(peak(&alias[100..alias.len() - 100]) < 500, "peak {}", peak(&alias));
+    }
+
+    #[test]
+    fn streaming_resampler_matches_one_shot() {
+        let input = sine(440.0, 44_100, 8_000.0, 10_000);
+        let whole = resample(&input, 44_100, 16_000);
+
+        let mut resampler = Resampler::new(44_100, 16_000);
+        let mut streamed = Vec::new();
+        for chunk in input.chunks(997) {
+            streamed.extend(resampler.process(chunk));
+        }
+        streamed.extend(resampler.flush());
+        assert_eq!(streamed, whole);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use sqlx::SqlitePool;
+
+use crate::db::chronicle::{fetch_characters, CharacterRow};
+use crate::definitions::Error;
+
+/// A player's character, as it should appear in transcripts and summaries.
+#[derive(Clone, Debug)]
+pub struct Character {
+    pub user_id: u64,
+    pub name: String,
+    pub class: Option<String>,
+}
+
+impl Character {
+    /// "Thalia the bard", or just "Thalia" without a class.
+    pub fn describe(&self) -> String {
+        match &self.class {
+            Some(class) => format!("{} the {}", self.name, class),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl From<CharacterRow> for Character {
+    fn from(row: CharacterRow) -> Self {
+        Self {
+            user_id: row.user_id as u64,
+            name: row.character_name,
+            class: row.character_class,
+        }
+    }
+}
+
+/// Maps Discord users to the characters they play in one campaign.
+#[derive(Clone, Debug, Default)]
+pub struct Roster {
+    characters: HashMap<u64, Character>,
+}
+
+impl Roster {
+    /// Loads the campaign's characters; a session without a campaign gets an empty roster.
+    pub async fn load(db_pool: &SqlitePool, campaign_id: Option<i64>) -> Result<Self, Error> {
+        let Some(campaign_id) = campaign_id else {
+            return Ok(Self::default());
+        };
+
+        let characters = fetch_characters(db_pool, campaign_id)
+            .await?
+            .into_iter()
+            .map(|row| (row.user_id as u64, Character::from(row)))
+            .collect();
+        Ok(Self { characters })
+    }
+
+    #[cfg(test)]
+    pub fn from_characters(characters: impl IntoIterator<Item = Character>) -> Self {
+        Self { characters: characters.into_iter().map(|c| (c.user_id, c)).collect() }
+    }
+
+    pub fn character(&self, user_id: u64) -> Option<&Character> {
+        self.characters.get(&user_id)
+    }
+
+    pub fn characters(&self) -> impl Iterator<Item = &Character> {
+        self.characters.values()
+    }
+
+    /// The character's name, falling back to a neutral label for players without one.
+    pub fn speaker_name(&self, user_id: Option<u64>) -> String {
+        match user_id {
+            Some(user_id) => self
+                .character(user_id)
+                .map(|c| c.name.clone())
+                .unwrap_or_else(|| format!("Player {}", user_id)),
+            None => "Unknown speaker".to_string(),
+        }
+    }
+}
+
+/// Folder name used when a campaign is created without one: the name with
+/// characters Obsidian and common filesystems reject removed.
+pub fn default_obsidian_folder(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']'))
+        .collect::<String>()
+        .trim()
+        .trim_matches('.')
+        .to_string()
+}
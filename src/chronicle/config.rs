@@ -0,0 +1,902 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::definitions::Error;
+
+pub const DEFAULT_CONFIG_PATH: &str = "config/chronicle.toml";
+/// Names an alternative config file, e.g. for a second bot instance.
+pub const CONFIG_PATH_VAR: &str = "CHRONICLE_CONFIG";
+
+const DEFAULT_STORAGE_ROOT: &str = "chronicle";
+const DEFAULT_WHISPER_BINARY: &str = "whisper-cli";
+const DEFAULT_WHISPER_MODEL: &str = "models/ggml-base.bin";
+const DEFAULT_WHISPER_LANGUAGE: &str = "auto";
+const DEFAULT_LLAMA_URL: &str = "http://127.0.0.1:8081";
+const DEFAULT_PIPER_BINARY: &str = "piper";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriberKind {
+    Whisper,
+}
+
+impl FromStr for TranscriberKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whisper" => Ok(TranscriberKind::Whisper),
+            other => Err(format!("unknown transcriber `{}` (expected `whisper`)", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LlmKind {
+    LlamaCpp,
+    /// Canned, deterministic responses; for trying the pipeline without a model
+    Mock,
+}
+
+impl FromStr for LlmKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "llama.cpp" => Ok(LlmKind::LlamaCpp),
+            "mock" => Ok(LlmKind::Mock),
+            other => Err(format!("unknown LLM backend `{}` (expected `llama.cpp` or `mock`)", other)),
+        }
+    }
+}
+
+/// Codec for the mixed-down archive of a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Opus,
+    Mp3,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Opus => "opus",
+            ArchiveFormat::Mp3  => "mp3",
+        }
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opus" => Ok(ArchiveFormat::Opus),
+            "mp3" => Ok(ArchiveFormat::Mp3),
+            other => Err(format!("unknown archive format `{}` (expected `opus` or `mp3`)", other)),
+        }
+    }
+}
+
+/// How long a kind of session file is kept once the session's summary is approved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionDays {
+    Forever,
+    Days(u32),
+}
+
+impl RetentionDays {
+    pub fn describe(&self) -> String {
+        match self {
+            RetentionDays::Forever => "kept forever".to_string(),
+            RetentionDays::Days(0) => "deleted once the summary is approved".to_string(),
+            RetentionDays::Days(days) => format!("deleted {} day(s) after the summary is approved", days),
+        }
+    }
+}
+
+impl FromStr for RetentionDays {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "forever" => Ok(RetentionDays::Forever),
+            days => days
+                .parse()
+                .map(RetentionDays::Days)
+                .map_err(|_| format!("expected a number of days or `forever`, got `{}`", days)),
+        }
+    }
+}
+
+/// `[chronicle]` in `config/chronicle.toml`, with paths expanded and values checked.
+#[derive(Clone, Debug)]
+pub struct ChronicleConfig {
+    pub enabled: bool,
+    pub storage_root: PathBuf,
+    pub retain_audio: bool,
+    pub retain_transcript: bool,
+    /// Mixdown codec and bitrate, used when `retain_audio` is on
+    pub archive_format: ArchiveFormat,
+    pub archive_bitrate_kbps: u32,
+    pub obsidian: ObsidianConfig,
+    pub live: LiveConfig,
+    pub retention: RetentionConfig,
+    pub pipeline: PipelineConfig,
+    pub ai: AiConfig,
+}
+
+/// `[chronicle.obsidian]`
+#[derive(Clone, Debug, Default)]
+pub struct ObsidianConfig {
+    pub vault: Option<PathBuf>,
+}
+
+/// `[chronicle.live]`: transcription while the session is still recording.
+#[derive(Clone, Debug)]
+pub struct LiveConfig {
+    pub transcribe: bool,
+    /// Post the rolling transcript to a thread in the channel `/chronicle start` ran in
+    pub captions: bool,
+    /// A speaker's utterance ends after this much silence
+    pub pause_ms: u32,
+    /// Longer monologues are cut here so captions don't fall too far behind
+    pub max_utterance_secs: u32,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            transcribe: false,
+            captions: false,
+            pause_ms: 800,
+            max_utterance_secs: 20,
+        }
+    }
+}
+
+/// `[chronicle.retention]`: server-wide rules; campaigns can override them.
+#[derive(Clone, Debug)]
+pub struct RetentionConfig {
+    /// Per-speaker recordings and anything derived from them but the mixdown
+    pub raw_audio: RetentionDays,
+    /// Transcripts and the model output cached from them
+    pub transcript: RetentionDays,
+    pub check_interval_hours: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            raw_audio: RetentionDays::Days(7),
+            transcript: RetentionDays::Forever,
+            check_interval_hours: 6,
+        }
+    }
+}
+
+/// `[chronicle.pipeline]`: processing recorded sessions into transcripts and notes.
+#[derive(Clone, Debug)]
+pub struct PipelineConfig {
+    /// Sessions processed at once; the rest wait their turn so music playback
+    /// keeps enough CPU
+    pub max_jobs: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self { max_jobs: 1 }
+    }
+}
+
+/// `[chronicle.ai]`
+#[derive(Clone, Debug)]
+pub struct AiConfig {
+    pub transcriber: TranscriberKind,
+    pub llm: LlmKind,
+    pub whisper: WhisperConfig,
+    pub llama: LlamaConfig,
+    pub tts: TtsConfig,
+}
+
+/// `[chronicle.ai.tts]`: a local Piper binary for reading recaps aloud. Off until
+/// a voice model is set.
+#[derive(Clone, Debug)]
+pub struct TtsConfig {
+    pub binary: PathBuf,
+    /// A Piper `.onnx` voice; its `.onnx.json` must sit next to it
+    pub model: Option<PathBuf>,
+    /// Speaker number, for voices trained on several speakers
+    pub speaker: Option<u32>,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from(DEFAULT_PIPER_BINARY),
+            model: None,
+            speaker: None,
+        }
+    }
+}
+
+/// `[chronicle.ai.llama]`: a llama.cpp `llama-server` on this machine or the local network.
+#[derive(Clone, Debug)]
+pub struct LlamaConfig {
+    pub server_url: String,
+    /// Sent as the `model` field; llama-server ignores it unless it hosts several
+    pub model: Option<String>,
+    /// Tokens the loaded model accepts, prompt and completion together
+    pub context_window: usize,
+    pub max_tokens: usize,
+    pub temperature: f32,
+    pub retries: u32,
+    pub timeout_secs: u64,
+}
+
+impl Default for LlamaConfig {
+    fn default() -> Self {
+        Self {
+            server_url: DEFAULT_LLAMA_URL.to_string(),
+            model: None,
+            context_window: 8192,
+            max_tokens: 1024,
+            temperature: 0.3,
+            retries: 3,
+            timeout_secs: 600,
+        }
+    }
+}
+
+/// `[chronicle.ai.whisper]`: a local whisper.cpp binary, or a whisper.cpp server
+/// on this machine or the local network when `server_url` is set.
+#[derive(Clone, Debug)]
+pub struct WhisperConfig {
+    pub binary: PathBuf,
+    pub server_url: Option<String>,
+    pub model: PathBuf,
+    pub language: String,
+    pub threads: usize,
+}
+
+impl Default for WhisperConfig {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from(DEFAULT_WHISPER_BINARY),
+            server_url: None,
+            model: PathBuf::from(DEFAULT_WHISPER_MODEL),
+            language: DEFAULT_WHISPER_LANGUAGE.to_string(),
+            threads: std::thread::available_parallelism().map_or(4, |n| n.get()),
+        }
+    }
+}
+
+impl Default for ChronicleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            storage_root: PathBuf::from(DEFAULT_STORAGE_ROOT),
+            retain_audio: true,
+            retain_transcript: true,
+            archive_format: ArchiveFormat::Opus,
+            archive_bitrate_kbps: 64,
+            obsidian: ObsidianConfig::default(),
+            live: LiveConfig::default(),
+            retention: RetentionConfig::default(),
+            pipeline: PipelineConfig::default(),
+            ai: AiConfig {
+                transcriber: TranscriberKind::Whisper,
+                llm: LlmKind::LlamaCpp,
+                whisper: WhisperConfig::default(),
+                llama: LlamaConfig::default(),
+                tts: TtsConfig::default(),
+            },
+        }
+    }
+}
+
+// The file as written: every value optional so defaults can fill the gaps, and
+// unknown keys rejected so typos don't silently fall back to a default.
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFile {
+    #[serde(default)]
+    chronicle: RawChronicle,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawChronicle {
+    enabled: Option<bool>,
+    storage_root: Option<String>,
+    retain_audio: Option<bool>,
+    retain_transcript: Option<bool>,
+    archive_format: Option<String>,
+    archive_bitrate_kbps: Option<u32>,
+    #[serde(default)]
+    obsidian: RawObsidian,
+    #[serde(default)]
+    live: RawLive,
+    #[serde(default)]
+    retention: RawRetention,
+    #[serde(default)]
+    pipeline: RawPipeline,
+    #[serde(default)]
+    ai: RawAi,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawObsidian {
+    vault: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLive {
+    transcribe: Option<bool>,
+    captions: Option<bool>,
+    pause_ms: Option<u32>,
+    max_utterance_secs: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRetention {
+    raw_audio_days: Option<RawDays>,
+    transcript_days: Option<RawDays>,
+    check_interval_hours: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPipeline {
+    max_jobs: Option<usize>,
+}
+
+/// `7` or `"forever"` in the file; either spelling from the environment.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawDays {
+    Days(u32),
+    Word(String),
+}
+
+impl FromStr for RawDays {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse().map(RawDays::Days).unwrap_or_else(|_| RawDays::Word(s.to_string())))
+    }
+}
+
+impl RawDays {
+    fn resolve(self, key: &str) -> Result<RetentionDays, String> {
+        match self {
+            RawDays::Days(days) => Ok(RetentionDays::Days(days)),
+            RawDays::Word(word) => word.parse().map_err(|e| format!("`{}`: {}", key, e)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAi {
+    transcriber: Option<String>,
+    llm: Option<String>,
+    #[serde(default)]
+    whisper: RawWhisper,
+    #[serde(default)]
+    llama: RawLlama,
+    #[serde(default)]
+    tts: RawTts,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLlama {
+    server_url: Option<String>,
+    model: Option<String>,
+    context_window: Option<usize>,
+    max_tokens: Option<usize>,
+    temperature: Option<f32>,
+    retries: Option<u32>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTts {
+    binary: Option<String>,
+    model: Option<String>,
+    speaker: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWhisper {
+    binary: Option<String>,
+    server_url: Option<String>,
+    model: Option<String>,
+    language: Option<String>,
+    threads: Option<usize>,
+}
+
+impl ChronicleConfig {
+    /// Reads the file named by `CHRONICLE_CONFIG`, or `config/chronicle.toml`.
+    pub fn load() -> Result<Self, Error> {
+        let path = std::env::var(CONFIG_PATH_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH));
+        Self::load_from(&path)
+    }
+
+    /// Reads `path` (defaults are used if it doesn't exist), applies `CHRONICLE_*`
+    /// environment overrides, expands paths and validates the result.
+    pub fn load_from(path: &Path) -> Result<Self, Error> {
+        let mut raw = if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            toml::from_str::<RawFile>(&content)
+                .map_err(|e| format!("Invalid {}: {}", path.display(), e))?
+                .chronicle
+        } else {
+            tracing::warn!("{} not found; using default chronicle settings", path.display());
+            RawChronicle::default()
+        };
+
+        apply_env_overrides(&mut raw)?;
+        Self::from_raw(raw).map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+    }
+
+    fn from_raw(raw: RawChronicle) -> Result<Self, String> {
+        let defaults = Self::default();
+
+        let storage_root = match raw.storage_root {
+            Some(root) => expand_path("chronicle.storage_root", &root)?,
+            None => defaults.storage_root,
+        };
+
+        let vault = raw.obsidian.vault
+            .map(|vault| expand_path("chronicle.obsidian.vault", &vault))
+            .transpose()?;
+        if let Some(vault) = &vault
+            && vault.exists()
+            && !vault.is_dir()
+        {
+            return Err(format!("`chronicle.obsidian.vault`: {} is not a directory", vault.display()));
+        }
+
+        let transcriber = match raw.ai.transcriber {
+            Some(name) => name.parse().map_err(|e| format!("`chronicle.ai.transcriber`: {}", e))?,
+            None => defaults.ai.transcriber,
+        };
+        let llm = match raw.ai.llm {
+            Some(name) => name.parse().map_err(|e| format!("`chronicle.ai.llm`: {}", e))?,
+            None => defaults.ai.llm,
+        };
+
+        let archive_format = match raw.archive_format {
+            Some(format) => format.parse().map_err(|e| format!("`chronicle.archive_format`: {}", e))?,
+            None => defaults.archive_format,
+        };
+        let archive_bitrate_kbps = raw.archive_bitrate_kbps.unwrap_or(defaults.archive_bitrate_kbps);
+        if !(16..=320).contains(&archive_bitrate_kbps) {
+            return Err(format!("`chronicle.archive_bitrate_kbps` must be between 16 and 320, got {}", archive_bitrate_kbps));
+        }
+
+        let live = live_from_raw(raw.live, defaults.live)?;
+        let retain_audio = raw.retain_audio.unwrap_or(defaults.retain_audio);
+        let retain_transcript = raw.retain_transcript.unwrap_or(defaults.retain_transcript);
+        let retention = retention_from_raw(raw.retention, defaults.retention, retain_audio, retain_transcript)?;
+        let max_jobs = raw.pipeline.max_jobs.unwrap_or(defaults.pipeline.max_jobs);
+        if !(1..=8).contains(&max_jobs) {
+            return Err(format!("`chronicle.pipeline.max_jobs` must be between 1 and 8, got {}", max_jobs));
+        }
+        let whisper = whisper_from_raw(raw.ai.whisper, defaults.ai.whisper)?;
+        let llama = llama_from_raw(raw.ai.llama, defaults.ai.llama)?;
+        let tts = tts_from_raw(raw.ai.tts, defaults.ai.tts)?;
+
+        Ok(Self {
+            enabled: raw.enabled.unwrap_or(defaults.enabled),
+            storage_root,
+            retain_audio,
+            retain_transcript,
+            archive_format,
+            archive_bitrate_kbps,
+            obsidian: ObsidianConfig { vault },
+            live,
+            retention,
+            pipeline: PipelineConfig { max_jobs },
+            ai: AiConfig { transcriber, llm, whisper, llama, tts },
+        })
+    }
+}
+
+fn live_from_raw(raw: RawLive, defaults: LiveConfig) -> Result<LiveConfig, String> {
+    let transcribe = raw.transcribe.unwrap_or(defaults.transcribe);
+    let captions = raw.captions.unwrap_or(defaults.captions);
+    if captions && !transcribe {
+        return Err("`chronicle.live.captions` needs `chronicle.live.transcribe = true`".to_string());
+    }
+
+    let pause_ms = raw.pause_ms.unwrap_or(defaults.pause_ms);
+    if !(200..=5_000).contains(&pause_ms) {
+        return Err(format!("`chronicle.live.pause_ms` must be between 200 and 5000, got {}", pause_ms));
+    }
+    let max_utterance_secs = raw.max_utterance_secs.unwrap_or(defaults.max_utterance_secs);
+    if !(2..=120).contains(&max_utterance_secs) {
+        return Err(format!("`chronicle.live.max_utterance_secs` must be between 2 and 120, got {}", max_utterance_secs));
+    }
+
+    Ok(LiveConfig { transcribe, captions, pause_ms, max_utterance_secs })
+}
+
+/// `retain_audio = false` or `retain_transcript = false` mean "don't keep it once
+/// the session is done", so they win over any number of days.
+fn retention_from_raw(
+    raw: RawRetention,
+    defaults: RetentionConfig,
+    retain_audio: bool,
+    retain_transcript: bool,
+) -> Result<RetentionConfig, String> {
+    let raw_audio = match raw.raw_audio_days {
+        _ if !retain_audio => RetentionDays::Days(0),
+        Some(days) => days.resolve("chronicle.retention.raw_audio_days")?,
+        None => defaults.raw_audio,
+    };
+    let transcript = match raw.transcript_days {
+        _ if !retain_transcript => RetentionDays::Days(0),
+        Some(days) => days.resolve("chronicle.retention.transcript_days")?,
+        None => defaults.transcript,
+    };
+
+    let check_interval_hours = raw.check_interval_hours.unwrap_or(defaults.check_interval_hours);
+    if check_interval_hours == 0 {
+        return Err("`chronicle.retention.check_interval_hours` must be at least 1".to_string());
+    }
+
+    Ok(RetentionConfig { raw_audio, transcript, check_interval_hours })
+}
+
+fn whisper_from_raw(raw: RawWhisper, defaults: WhisperConfig) -> Result<WhisperConfig, String> {
+    // A bare command name is looked up on PATH; anything path-like is expanded
+    let binary = match raw.binary {
+        Some(binary) if binary.contains('/') || binary.starts_with('~') => {
+            expand_path("chronicle.ai.whisper.binary", &binary)?
+        }
+        Some(binary) if !binary.trim().is_empty() => PathBuf::from(binary.trim()),
+        Some(_) => return Err("`chronicle.ai.whisper.binary` must not be empty".to_string()),
+        None => defaults.binary,
+    };
+
+    let server_url = raw.server_url
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty());
+    if let Some(url) = &server_url {
+        check_local_url("chronicle.ai.whisper.server_url", url)?;
+    }
+
+    let model = match raw.model {
+        Some(model) => expand_path("chronicle.ai.whisper.model", &model)?,
+        None => defaults.model,
+    };
+
+    let language = match raw.language {
+        Some(language) => {
+            let language = language.trim().to_lowercase();
+            if language != "auto" && !(2..=3).contains(&language.len()) {
+                return Err(format!(
+                    "`chronicle.ai.whisper.language`: expected `auto` or a language code like `en`, got `{}`",
+                    language,
+                ));
+            }
+            language
+        }
+        None => defaults.language,
+    };
+
+    let threads = raw.threads.unwrap_or(defaults.threads);
+    if threads == 0 {
+        return Err("`chronicle.ai.whisper.threads` must be at least 1".to_string());
+    }
+
+    Ok(WhisperConfig { binary, server_url, model, language, threads })
+}
+
+fn llama_from_raw(raw: RawLlama, defaults: LlamaConfig) -> Result<LlamaConfig, String> {
+    let server_url = match raw.server_url {
+        Some(url) => url.trim().trim_end_matches('/').to_string(),
+        None => defaults.server_url,
+    };
+    check_local_url("chronicle.ai.llama.server_url", &server_url)?;
+
+    let context_window = raw.context_window.unwrap_or(defaults.context_window);
+    if context_window < 512 {
+        return Err(format!("`chronicle.ai.llama.context_window` must be at least 512, got {}", context_window));
+    }
+    let max_tokens = raw.max_tokens.unwrap_or(defaults.max_tokens);
+    if max_tokens == 0 || max_tokens >= context_window / 2 {
+        return Err(format!(
+            "`chronicle.ai.llama.max_tokens` must be between 1 and half the context window ({}), got {}",
+            context_window / 2,
+            max_tokens,
+        ));
+    }
+    let temperature = raw.temperature.unwrap_or(defaults.temperature);
+    if !(0.0..=2.0).contains(&temperature) {
+        return Err(format!("`chronicle.ai.llama.temperature` must be between 0 and 2, got {}", temperature));
+    }
+    let timeout_secs = raw.timeout_secs.unwrap_or(defaults.timeout_secs);
+    if timeout_secs == 0 {
+        return Err("`chronicle.ai.llama.timeout_secs` must be at least 1".to_string());
+    }
+
+    Ok(LlamaConfig {
+        server_url,
+        model: raw.model.map(|m| m.trim().to_string()).filter(|m| !m.is_empty()),
+        context_window,
+        max_tokens,
+        temperature,
+        retries: raw.retries.unwrap_or(defaults.retries),
+        timeout_secs,
+    })
+}
+
+fn tts_from_raw(raw: RawTts, defaults: TtsConfig) -> Result<TtsConfig, String> {
+    let binary = match raw.binary {
+        Some(binary) if binary.contains('/') || binary.starts_with('~') => {
+            expand_path("chronicle.ai.tts.binary", &binary)?
+        }
+        Some(binary) if !binary.trim().is_empty() => PathBuf::from(binary.trim()),
+        Some(_) => return Err("`chronicle.ai.tts.binary` must not be empty".to_string()),
+        None => defaults.binary,
+    };
+
+    let model = match raw.model.filter(|m| !m.trim().is_empty()) {
+        Some(model) => {
+            let model = expand_path("chronicle.ai.tts.model", &model)?;
+            if model.extension().is_none_or(|ext| ext != "onnx") {
+                return Err(format!("`chronicle.ai.tts.model`: expected a Piper `.onnx` voice, got {}", model.display()));
+            }
+            Some(model)
+        }
+        None => defaults.model,
+    };
+
+    Ok(TtsConfig { binary, model, speaker: raw.speaker.or(defaults.speaker) })
+}
+
+/// Chronicle only talks to services on this machine or the local network, so
+/// recordings never leave the group's own hardware.
+pub(crate) fn check_local_url(key: &str, raw: &str) -> Result<(), String> {
+    let url = url::Url::parse(raw).map_err(|e| format!("`{}`: invalid URL `{}`: {}", key, raw, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("`{}`: expected an http(s) URL, got `{}`", key, raw));
+    }
+
+    let local = match url.host() {
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        Some(url::Host::Domain(domain)) => domain == "localhost" || domain.ends_with(".local") || !domain.contains('.'),
+        None => false,
+    };
+    if !local {
+        return Err(format!("`{}`: `{}` is not a local address; chronicle only uses local services", key, raw));
+    }
+    Ok(())
+}
+
+/// Each key can be overridden by its upper-cased path, e.g. `chronicle.obsidian.vault`
+/// by `CHRONICLE_OBSIDIAN_VAULT`.
+fn apply_env_overrides(raw: &mut RawChronicle) -> Result<(), Error> {
+    env_override(&mut raw.enabled, "CHRONICLE_ENABLED")?;
+    env_override(&mut raw.storage_root, "CHRONICLE_STORAGE_ROOT")?;
+    env_override(&mut raw.retain_audio, "CHRONICLE_RETAIN_AUDIO")?;
+    env_override(&mut raw.retain_transcript, "CHRONICLE_RETAIN_TRANSCRIPT")?;
+    env_override(&mut raw.archive_format, "CHRONICLE_ARCHIVE_FORMAT")?;
+    env_override(&mut raw.archive_bitrate_kbps, "CHRONICLE_ARCHIVE_BITRATE_KBPS")?;
+    env_override(&mut raw.obsidian.vault, "CHRONICLE_OBSIDIAN_VAULT")?;
+    env_override(&mut raw.live.transcribe, "CHRONICLE_LIVE_TRANSCRIBE")?;
+    env_override(&mut raw.live.captions, "CHRONICLE_LIVE_CAPTIONS")?;
+    env_override(&mut raw.live.pause_ms, "CHRONICLE_LIVE_PAUSE_MS")?;
+    env_override(&mut raw.live.max_utterance_secs, "CHRONICLE_LIVE_MAX_UTTERANCE_SECS")?;
+    env_override(&mut raw.retention.raw_audio_days, "CHRONICLE_RETENTION_RAW_AUDIO_DAYS")?;
+    env_override(&mut raw.retention.transcript_days, "CHRONICLE_RETENTION_TRANSCRIPT_DAYS")?;
+    env_override(&mut raw.retention.check_interval_hours, "CHRONICLE_RETENTION_CHECK_INTERVAL_HOURS")?;
+    env_override(&mut raw.pipeline.max_jobs, "CHRONICLE_PIPELINE_MAX_JOBS")?;
+    env_override(&mut raw.ai.transcriber, "CHRONICLE_AI_TRANSCRIBER")?;
+    env_override(&mut raw.ai.llm, "CHRONICLE_AI_LLM")?;
+    env_override(&mut raw.ai.whisper.binary, "CHRONICLE_AI_WHISPER_BINARY")?;
+    env_override(&mut raw.ai.whisper.server_url, "CHRONICLE_AI_WHISPER_SERVER_URL")?;
+    env_override(&mut raw.ai.whisper.model, "CHRONICLE_AI_WHISPER_MODEL")?;
+    env_override(&mut raw.ai.whisper.language, "CHRONICLE_AI_WHISPER_LANGUAGE")?;
+    env_override(&mut raw.ai.whisper.threads, "CHRONICLE_AI_WHISPER_THREADS")?;
+    env_override(&mut raw.ai.llama.server_url, "CHRONICLE_AI_LLAMA_SERVER_URL")?;
+    env_override(&mut raw.ai.llama.model, "CHRONICLE_AI_LLAMA_MODEL")?;
+    env_override(&mut raw.ai.llama.context_window, "CHRONICLE_AI_LLAMA_CONTEXT_WINDOW")?;
+    env_override(&mut raw.ai.llama.max_tokens, "CHRONICLE_AI_LLAMA_MAX_TOKENS")?;
+    env_override(&mut raw.ai.llama.temperature, "CHRONICLE_AI_LLAMA_TEMPERATURE")?;
+    env_override(&mut raw.ai.llama.retries, "CHRONICLE_AI_LLAMA_RETRIES")?;
+    env_override(&mut raw.ai.llama.timeout_secs, "CHRONICLE_AI_LLAMA_TIMEOUT_SECS")?;
+    env_override(&mut raw.ai.tts.binary, "CHRONICLE_AI_TTS_BINARY")?;
+    env_override(&mut raw.ai.tts.model, "CHRONICLE_AI_TTS_MODEL")?;
+    env_override(&mut raw.ai.tts.speaker, "CHRONICLE_AI_TTS_SPEAKER")?;
+    Ok(())
+}
+
+fn env_override<T>(field: &mut Option<T>, var: &str) -> Result<(), Error>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    if let Ok(value) = std::env::var(var) {
+        let parsed = value
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid value `{}` for {}: {}", value, var, e))?;
+        *field = Some(parsed);
+    }
+    Ok(())
+}
+
+/// Expands a leading `~` to the home directory and `$VAR` / `${VAR}` references.
+fn expand_path(key: &str, raw: &str) -> Result<PathBuf, String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Err(format!("`{}` must not be empty", key));
+    }
+
+    let mut expanded = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    if rest == "~" || rest.starts_with("~/") {
+        let home = std::env::var("HOME")
+            .map_err(|_| format!("`{}`: cannot expand `~` because HOME is not set", key))?;
+        expanded.push_str(&home);
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, remainder) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("`{}`: unclosed `${{` in `{}`", key, raw))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+
+        if name.is_empty() {
+            return Err(format!("`{}`: empty variable name in `{}`", key, raw));
+        }
+        let value = std::env::var(name)
+            .map_err(|_| format!("`{}`: environment variable `{}` is not set", key, name))?;
+        expanded.push_str(&value);
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Result<ChronicleConfig, String> {
+        let raw = toml::from_str::<RawFile>(toml).map_err(|e| e.to_string())?;
+        ChronicleConfig::from_raw(raw.chronicle)
+    }
+
+    #[test]
+    fn paths_expand_home_and_variables() {
+        let home = std::env::var("HOME").unwrap();
+        let path = std::env::var("PATH").unwrap();
+
+        assert_eq!(expand_path("key", "~").unwrap(), PathBuf::from(&home));
+        assert_eq!(expand_path("key", " ~/vault ").unwrap(), PathBuf::from(format!("{home}/vault")));
+        assert_eq!(expand_path("key", "$HOME/a/${PATH}.b").unwrap(), PathBuf::from(format!("{home}/a/{path}.b")));
+        assert_eq!(expand_path("key", "relative/~user").unwrap(), PathBuf::from("relative/~user"));
+
+        assert!(expand_path("key", "  ").unwrap_err().contains("must not be empty"));
+        assert!(expand_path("key", "${HOME").unwrap_err().contains("unclosed"));
+        assert!(expand_path("key", "a/$/b").unwrap_err().contains("empty variable name"));
+        assert!(expand_path("key", "$CHESTER_TEST_UNSET_VARIABLE").unwrap_err().contains("is not set"));
+    }
+
+    #[test]
+    fn an_empty_file_gives_the_defaults() {
+        let config = parse("").unwrap();
+        let defaults = ChronicleConfig::default();
+        assert_eq!(config.storage_root, defaults.storage_root);
+        assert_eq!(config.archive_bitrate_kbps, defaults.archive_bitrate_kbps);
+        assert_eq!(config.pipeline.max_jobs, defaults.pipeline.max_jobs);
+        assert_eq!(config.ai.llm, LlmKind::LlamaCpp);
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        for (toml, key) in [
+            ("[chronicle]\narchive_bitrate_kbps = 8", "archive_bitrate_kbps"),
+            ("[chronicle]\narchive_bitrate_kbps = 500", "archive_bitrate_kbps"),
+            ("[chronicle.pipeline]\nmax_jobs = 0", "max_jobs"),
+            ("[chronicle.pipeline]\nmax_jobs = 9", "max_jobs"),
+            ("[chronicle.live]\npause_ms = 100", "pause_ms"),
+            ("[chronicle.live]\nmax_utterance_secs = 121", "max_utterance_secs"),
+            ("[chronicle.live]\ncaptions = true", "captions"),
+            ("[chronicle.retention]\ncheck_interval_hours = 0", "check_interval_hours"),
+            ("[chronicle.retention]\nraw_audio_days = \"soon\"", "raw_audio_days"),
+            ("[chronicle.ai.llama]\ncontext_window = 256", "context_window"),
+            ("[chronicle.ai.llama]\ncontext_window = 4096\nmax_tokens = 2048", "max_tokens"),
+            ("[chronicle.ai.llama]\ntemperature = 2.5", "temperature"),
+            ("[chronicle.ai.llama]\nserver_url = \"https://api.example.com\"", "not a local address"),
+            ("[chronicle.ai.whisper]\nlanguage = \"english\"", "language"),
+            ("[chronicle.ai.whisper]\nthreads = 0", "threads"),
+            ("[chronicle.ai.tts]\nmodel = \"voice.bin\"", "onnx"),
+            ("[chronicle]\narchive_format = \"flac\"", "archive_format"),
+        ] {
+            let error = parse(toml).err().unwrap_or_else(|| panic!("`{toml}` was accepted"));
+            assert!(error.contains(key), "`{toml}` gave: {error}");
+        }
+
+        // The bounds themselves are fine
+        let config = parse("[chronicle]\narchive_bitrate_kbps = 16\n[chronicle.pipeline]\nmax_jobs = 8").unwrap();
+        assert_eq!((config.archive_bitrate_kbps, config.pipeline.max_jobs), (16, 8));
+        assert!(parse("[chronicle]\nunknown_key = 1").is_err());
+    }
+
+    #[test]
+    fn retention_flags_win_over_days() {
+        let config = parse("[chronicle]\nretain_audio = false\n[chronicle.retention]\nraw_audio_days = 30\ntranscript_days = \"forever\"").unwrap();
+        assert_eq!(config.retention.raw_audio, RetentionDays::Days(0));
+        assert_eq!(config.retention.transcript, RetentionDays::Forever);
+    }
+
+    /// The only test that sets `CHRONICLE_*` variables, so parallel tests never see them.
+    #[test]
+    fn environment_overrides_the_file() {
+        let dir = std::env::temp_dir().join(format!("chester-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("chronicle.toml");
+        std::fs::write(&path, "[chronicle]\narchive_bitrate_kbps = 96\n[chronicle.pipeline]\nmax_jobs = 2\n").unwrap();
+
+        let vars = [
+            ("CHRONICLE_PIPELINE_MAX_JOBS", " 4 "),
+            ("CHRONICLE_AI_LLM", "mock"),
+            ("CHRONICLE_RETENTION_TRANSCRIPT_DAYS", "forever"),
+            ("CHRONICLE_OBSIDIAN_VAULT", "$HOME/vault"),
+        ];
+        // SAFETY: no other test reads or writes these variables
+        unsafe {
+            for (name, value) in vars {
+                std::env::set_var(name, value);
+            }
+        }
+        let config = ChronicleConfig::load_from(&path);
+        unsafe { std::env::set_var("CHRONICLE_PIPELINE_MAX_JOBS", "many") };
+        let invalid = ChronicleConfig::load_from(&path);
+        unsafe {
+            for (name, _) in vars {
+                std::env::remove_var(name);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.archive_bitrate_kbps, 96);
+        assert_eq!(config.pipeline.max_jobs, 4);
+        assert_eq!(config.ai.llm, LlmKind::Mock);
+        assert_eq!(config.retention.transcript, RetentionDays::Forever);
+        assert_eq!(config.obsidian.vault, Some(PathBuf::from(format!("{}/vault", std::env::var("HOME").unwrap()))));
+        assert!(invalid.unwrap_err().to_string().contains("CHRONICLE_PIPELINE_MAX_JOBS"));
+    }
+}
@@ -0,0 +1,385 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::db::chronicle::{replace_session_entities, NewEntityMention, SessionRow};
+use crate::definitions::Error;
+
+pub const ENTITIES_FILE: &str = "entities.json";
+
+const NPC_STATUSES: &[&str] = &["alive", "dead", "missing", "unknown"];
+const ATTITUDES: &[&str] = &["friendly", "neutral", "hostile", "unknown"];
+const ITEM_CHANGES: &[&str] = &["gained", "lost"];
+const QUEST_STATUSES: &[&str] = &["started", "progressed", "completed", "failed"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EntityKind {
+    Npc,
+    Location,
+    Item,
+    Quest,
+}
+
+impl EntityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntityKind::Npc      => "npc",
+            EntityKind::Location => "location",
+            EntityKind::Item     => "item",
+            EntityKind::Quest    => "quest",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntityKind::Npc      => "NPC",
+            EntityKind::Location => "Location",
+            EntityKind::Item     => "Item",
+            EntityKind::Quest    => "Quest",
+        }
+    }
+}
+
+impl FromStr for EntityKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "npc" => Ok(EntityKind::Npc),
+            "location" => Ok(EntityKind::Location),
+            "item" => Ok(EntityKind::Item),
+            "quest" => Ok(EntityKind::Quest),
+            other => Err(format!("unknown entity kind `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Npc {
+    pub name: String,
+    pub description: Option<String>,
+    /// alive, dead, missing or unknown
+    pub status: String,
+    /// friendly, neutral, hostile or unknown, towards the party
+    pub attitude: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub item: String,
+    /// Who has it now; `None` once it's lost or spent
+    pub holder: Option<String>,
+    /// gained or lost
+    pub change: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Quest {
+    pub name: String,
+    pub giver: Option<String>,
+    /// started, progressed, completed or failed
+    pub status: String,
+    pub notes: Option<String>,
+}
+
+/// Model replies as they come, before validation. Every field is optional so one
+/// sloppy entry is dropped rather than failing the whole reply.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RawNpc {
+    name: Option<String>,
+    description: Option<String>,
+    status: Option<String>,
+    attitude: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RawLocation {
+    name: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RawItem {
+    item: Option<String>,
+    holder: Option<String>,
+    change: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RawQuest {
+    name: Option<String>,
+    giver: Option<String>,
+    status: Option<String>,
+    notes: Option<String>,
+}
+
+/// Everything extracted from one session's summary.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionEntities {
+    pub session_id: i64,
+    pub npcs: Vec<Npc>,
+    pub locations: Vec<Location>,
+    pub items: Vec<Item>,
+    pub quests: Vec<Quest>,
+    /// Prompt fingerprints and model that produced it
+    pub produced_by: String,
+}
+
+impl SessionEntities {
+    /// Checks the model's entries against the prompts' schemas: unnamed entries are
+    /// dropped, unknown values fall back to a neutral one, player characters are
+    /// removed from the NPCs and repeated names are merged, the last one winning.
+    pub fn validate(
+        session_id: i64,
+        npcs: Vec<RawNpc>,
+        locations: Vec<RawLocation>,
+        items: Vec<RawItem>,
+        quests: Vec<RawQuest>,
+        characters: &[String],
+        produced_by: String,
+    ) -> Self {
+        let npcs = npcs
+            .into_iter()
+            .filter_map(|raw| {
+                let name = clean(raw.name)?;
+                if characters.iter().any(|c| c.eq_ignore_ascii_case(&name)) {
+                    return None;
+                }
+                Some(Npc {
+                    name,
+                    description: clean(raw.description),
+                    status: choice(raw.status, NPC_STATUSES).unwrap_or("unknown").to_string(),
+                    attitude: choice(raw.attitude, ATTITUDES).unwrap_or("unknown").to_string(),
+                })
+            })
+            .collect();
+
+        let locations = locations
+            .into_iter()
+            .filter_map(|raw| Some(Location { name: clean(raw.name)?, description: clean(raw.description) }))
+            .collect();
+
+        let items = items
+            .into_iter()
+            .filter_map(|raw| {
+                let item = clean(raw.item)?;
+                let holder = clean(raw.holder);
+                // Without a change, an item with nobody holding it is gone
+                let change = choice(raw.change, ITEM_CHANGES)
+                    .unwrap_or(if holder.is_some() { "gained" } else { "lost" });
+                Some(Item { item, holder, change: change.to_string() })
+            })
+            .collect();
+
+        let quests = quests
+            .into_iter()
+            .filter_map(|raw| {
+                Some(Quest {
+                    name: clean(raw.name)?,
+                    giver: clean(raw.giver),
+                    status: choice(raw.status, QUEST_STATUSES).unwrap_or("progressed").to_string(),
+                    notes: clean(raw.notes),
+                })
+            })
+            .collect();
+
+        Self {
+            session_id,
+            npcs: dedup_by_name(npcs, |n: &Npc| &n.name),
+            locations: dedup_by_name(locations, |l: &Location| &l.name),
+            items: dedup_by_name(items, |i: &Item| &i.item),
+            quests: dedup_by_name(quests, |q: &Quest| &q.name),
+            produced_by,
+        }
+    }
+
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(ENTITIES_FILE);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        std::fs::write(dir.join(ENTITIES_FILE), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// One row per entity for the campaign tables. "unknown" is left out so an
+    /// NPC's last known status survives a session that didn't say.
+    pub fn mentions(&self) -> Vec<NewEntityMention> {
+        let known = |value: &str| (value != "unknown").then(|| value.to_string());
+        let mut mentions = Vec::new();
+        for npc in &self.npcs {
+            mentions.push(NewEntityMention {
+                kind: EntityKind::Npc.as_str(),
+                name: npc.name.clone(),
+                status: known(&npc.status),
+                description: npc.description.clone(),
+                details: known(&npc.attitude),
+            });
+        }
+        for location in &self.locations {
+            mentions.push(NewEntityMention {
+                kind: EntityKind::Location.as_str(),
+                name: location.name.clone(),
+                status: None,
+                description: location.description.clone(),
+                details: None,
+            });
+        }
+        for item in &self.items {
+            mentions.push(NewEntityMention {
+                kind: EntityKind::Item.as_str(),
+                name: item.item.clone(),
+                status: Some(item.change.clone()),
+                description: None,
+                details: item.holder.clone(),
+            });
+        }
+        for quest in &self.quests {
+            mentions.push(NewEntityMention {
+                kind: EntityKind::Quest.as_str(),
+                name: quest.name.clone(),
+                status: Some(quest.status.clone()),
+                description: quest.notes.clone(),
+                details: quest.giver.clone(),
+            });
+        }
+        mentions
+    }
+}
+
+/// Merges the session's entities into its campaign's knowledge base. Sessions
+/// without a campaign have nowhere to merge into and are skipped.
+pub async fn record(db_pool: &SqlitePool, session: &SessionRow, entities: &SessionEntities, now: i64) -> Result<(), Error> {
+    let Some(campaign_id) = session.campaign_id else {
+        return Ok(());
+    };
+    replace_session_entities(db_pool, campaign_id, session.id, &entities.mentions(), now).await
+}
+
+/// Parses a model reply that should be a JSON array, tolerating a Markdown fence
+/// or a sentence around it.
+pub fn parse_json_array<T: DeserializeOwned>(reply: &str) -> Result<Vec<T>, String> {
+    let start = reply.find('[').ok_or("the reply contains no JSON array")?;
+    let end = reply.rfind(']').filter(|&end| end > start).ok_or("the JSON array is never closed")?;
+    serde_json::from_str(&reply[start..=end]).map_err(|e| format!("the JSON array is invalid: {}", e))
+}
+
+fn clean(value: Option<String>) -> Option<String> {
+    let value = value?.trim().to_string();
+    let empty = value.is_empty() || ["null", "none", "n/a"].iter().any(|v| value.eq_ignore_ascii_case(v));
+    (!empty).then_some(value)
+}
+
+/// `value` lower-cased, if it's one of `allowed`.
+fn choice(value: Option<String>, allowed: &[&'static str]) -> Option<&'static str> {
+    let value = value?.trim().to_lowercase();
+    allowed.iter().copied().find(|a| *a == value)
+}
+
+fn dedup_by_name<T>(entries: Vec<T>, name: impl Fn(&T) -> &String) -> Vec<T> {
+    let mut kept: Vec<T> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match kept.iter().position(|k| name(k).eq_ignore_ascii_case(name(&entry))) {
+            Some(index) => kept[index] = entry,
+            None => kept.push(entry),
+        }
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(npcs: &str, locations: &str, items: &str, quests: &str) -> SessionEntities {
+        SessionEntities::validate(
+            4,
+            parse_json_array(npcs).unwrap(),
+            parse_json_array(locations).unwrap(),
+            parse_json_array(items).unwrap(),
+            parse_json_array(quests).unwrap(),
+            &["Thalia".to_string()],
+            "test".to_string(),
+        )
+    }
+
+    #[test]
+    fn parses_an_array_wrapped_in_prose_or_a_fence() {
+        let reply = "Here are the NPCs:\n```json\n[{\"name\": \"Volo\"}]\n```\nLet me know if you need more.";
+        let npcs: Vec<RawNpc> = parse_json_array(reply).unwrap();
+        assert_eq!(npcs.len(), 1);
+        assert_eq!(npcs[0].name.as_deref(), Some("Volo"));
+
+        let empty: Vec<RawNpc> = parse_json_array("[]").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn rejects_replies_without_a_usable_array() {
+        for reply in ["No NPCs appeared this session.", "[{\"name\": \"Volo\"}", "] nothing here [", "[{\"name\": }]"] {
+            assert!(parse_json_array::<RawNpc>(reply).is_err(), "{}", reply);
+        }
+        // An array of the wrong shape is invalid too
+        assert!(parse_json_array::<RawNpc>("[\"Volo\", \"Xanathar\"]").is_err());
+    }
+
+    #[test]
+    fn drops_unnamed_entries_and_player_characters() {
+        let entities = validate(
+            r#"[{"name": "Volo"}, {"name": "  "}, {"description": "a hooded figure"}, {"name": "N/A"}, {"name": "thalia"}]"#,
+            r#"[{"name": null}, {"name": "Yawning Portal", "description": ""}]"#,
+            "[]",
+            r#"[{"status": "started"}]"#,
+        );
+        assert_eq!(entities.session_id, 4);
+        assert_eq!(entities.npcs.iter().map(|n| n.name.as_str()).collect::<Vec<_>>(), ["Volo"]);
+        assert_eq!(entities.locations, [Location { name: "Yawning Portal".to_string(), description: None }]);
+        assert!(entities.quests.is_empty());
+    }
+
+    #[test]
+    fn falls_back_on_unknown_values() {
+        let entities = validate(
+            r#"[{"name": "Volo", "status": "Alive ", "attitude": "chaotic"}, {"name": "Xanathar", "status": null}]"#,
+            "[]",
+            r#"[{"item": "Stone of Golorr", "holder": "Thalia"}, {"item": "Rope"}, {"item": "Dagger", "change": "sold"}]"#,
+            r#"[{"name": "Find Floon", "status": "abandoned", "giver": "Volo"}]"#,
+        );
+        assert_eq!(entities.npcs[0].status, "alive");
+        assert_eq!(entities.npcs[0].attitude, "unknown");
+        assert_eq!(entities.npcs[1].status, "unknown");
+        let changes: Vec<&str> = entities.items.iter().map(|i| i.change.as_str()).collect();
+        assert_eq!(changes, ["gained", "lost", "lost"]);
+        assert_eq!(entities.quests[0].status, "progressed");
+        assert_eq!(entities.quests[0].giver.as_deref(), Some("Volo"));
+    }
+
+    #[test]
+    fn merges_repeated_names_keeping_the_last() {
+        let entities = validate(
+            r#"[{"name": "Volo", "status": "alive"}, {"name": "VOLO", "status": "missing"}]"#,
+            "[]",
+            "[]",
+            "[]",
+        );
+        assert_eq!(entities.npcs.len(), 1);
+        assert_eq!(entities.npcs[0].name, "VOLO");
+        assert_eq!(entities.npcs[0].status, "missing");
+    }
+}
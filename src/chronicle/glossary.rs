@@ -0,0 +1,522 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::obsidian::{CHARACTERS_DIR, LOCATIONS_DIR, NPCS_DIR};
+use crate::chronicle::transcript::Transcript;
+use crate::chronicle::entities::EntityKind;
+use crate::db::chronicle::{fetch_entities, fetch_glossary, CampaignRow};
+use crate::definitions::Error;
+
+/// Every correction made to a session's transcript, next to the transcript.
+pub const CORRECTIONS_FILE: &str = "corrections.json";
+/// The glossary the session was last corrected with, so later sessions' names
+/// don't make it out of date.
+pub const GLOSSARY_FILE: &str = "glossary.json";
+
+/// whisper.cpp keeps about 224 tokens of initial prompt; stay well inside that.
+const PROMPT_MAX_CHARS: usize = 600;
+/// Shorter names are too easily confused with ordinary words to correct towards.
+const MIN_TERM_LETTERS: usize = 4;
+const MATCH_THRESHOLD: f32 = 0.82;
+/// A term the same speaker has already said clearly this session is what they
+/// most likely meant, so it's trusted on weaker evidence.
+const FAMILIAR_THRESHOLD: f32 = 0.75;
+/// Whisper tends to split an unfamiliar name into a few short words.
+const MAX_EXTRA_WORDS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, poise::ChoiceParameter)]
+pub enum TermKind {
+    #[name = "Character"]
+    Character,
+    #[name = "NPC"]
+    Npc,
+    #[name = "Place"]
+    Place,
+    #[name = "Spell"]
+    Spell,
+    #[name = "Item"]
+    Item,
+    #[name = "Other"]
+    Other,
+}
+
+impl TermKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TermKind::Character => "character",
+            TermKind::Npc       => "npc",
+            TermKind::Place     => "place",
+            TermKind::Spell     => "spell",
+            TermKind::Item      => "item",
+            TermKind::Other     => "other",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TermKind::Character => "Characters",
+            TermKind::Npc       => "NPCs",
+            TermKind::Place     => "Places",
+            TermKind::Spell     => "Spells",
+            TermKind::Item      => "Items",
+            TermKind::Other     => "Other",
+        }
+    }
+}
+
+impl FromStr for TermKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "character" => Ok(TermKind::Character),
+            "npc" => Ok(TermKind::Npc),
+            "place" => Ok(TermKind::Place),
+            "spell" => Ok(TermKind::Spell),
+            "item" => Ok(TermKind::Item),
+            "other" => Ok(TermKind::Other),
+            other => Err(format!("unknown glossary kind `{}`", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Term {
+    /// As it should be spelled
+    pub text: String,
+    pub kind: TermKind,
+    /// Lower-cased letters and digits only, for comparison
+    key: String,
+    words: usize,
+}
+
+impl Term {
+    fn new(text: &str, kind: TermKind) -> Self {
+        Self {
+            text: text.trim().to_string(),
+            kind,
+            key: letters(text),
+            words: text.split_whitespace().count().max(1),
+        }
+    }
+}
+
+/// A term as kept in `GLOSSARY_FILE`.
+#[derive(Serialize, Deserialize)]
+struct SavedTerm {
+    text: String,
+    kind: String,
+}
+
+/// One near-miss replaced by a glossary term.
+#[derive(Clone, Debug, Serialize)]
+pub struct Correction {
+    pub start_ms: u64,
+    pub speaker: Option<u64>,
+    pub speaker_name: String,
+    pub original: String,
+    pub corrected: String,
+    pub kind: &'static str,
+    /// Similarity of the two spellings, 0.0 to 1.0
+    pub score: f32,
+}
+
+/// The campaign's proper nouns: the characters, manual entries, the NPCs and
+/// locations extracted from earlier sessions and the names of its NPC and location
+/// notes in Obsidian.
+#[derive(Clone, Debug, Default)]
+pub struct Glossary {
+    terms: Vec<Term>,
+}
+
+impl Glossary {
+    pub async fn load(
+        db_pool: &SqlitePool,
+        campaign: &CampaignRow,
+        vault: Option<&Path>,
+        roster: &Roster,
+    ) -> Result<Self, Error> {
+        let mut glossary = Self::default();
+        for character in roster.characters() {
+            glossary.add(&character.name, TermKind::Character);
+        }
+        for row in fetch_glossary(db_pool, campaign.id).await? {
+            glossary.add(&row.term, row.kind.parse().unwrap_or(TermKind::Other));
+        }
+        for (entity_kind, kind) in [(EntityKind::Npc, TermKind::Npc), (EntityKind::Location, TermKind::Place)] {
+            for row in fetch_entities(db_pool, campaign.id, Some(entity_kind.as_str())).await? {
+                glossary.add(&row.name, kind);
+            }
+        }
+
+        if let Some(vault) = vault {
+            let campaign_dir = vault.join(&campaign.obsidian_folder);
+            for (dir, kind) in [(CHARACTERS_DIR, TermKind::Character), (NPCS_DIR, TermKind::Npc), (LOCATIONS_DIR, TermKind::Place)] {
+                for name in note_names(&campaign_dir.join(dir)) {
+                    glossary.add(&name, kind);
+                }
+            }
+        }
+
+        glossary.terms.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.text.cmp(&b.text)));
+        Ok(glossary)
+    }
+
+    /// The glossary saved by `save`, if the session has one.
+    pub fn load_saved(dir: &Path) -> Result<Option<Self>, Error> {
+        let path = dir.join(GLOSSARY_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let saved: Vec<SavedTerm> = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+        let mut glossary = Self::default();
+        for term in saved {
+            glossary.add(&term.text, term.kind.parse().unwrap_or(TermKind::Other));
+        }
+        Ok(Some(glossary))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        let saved: Vec<SavedTerm> = self.terms
+            .iter()
+            .map(|t| SavedTerm { text: t.text.clone(), kind: t.kind.as_str().to_string() })
+            .collect();
+        let path = dir.join(GLOSSARY_FILE);
+        std::fs::write(&path, serde_json::to_vec_pretty(&saved)?)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Adds a term unless one with the same spelling is already there.
+    pub fn add(&mut self, text: &str, kind: TermKind) {
+        let term = Term::new(text, kind);
+        if term.key.is_empty() || self.terms.iter().any(|t| t.key == term.key) {
+            return;
+        }
+        self.terms.push(term);
+    }
+
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Initial prompt for the transcriber, so it knows how the names are spelled.
+    pub fn prompt(&self) -> Option<String> {
+        let mut prompt = String::new();
+        for term in &self.terms {
+            if prompt.len() + term.text.len() + 2 > PROMPT_MAX_CHARS {
+                break;
+            }
+            if !prompt.is_empty() {
+                prompt.push_str(", ");
+            }
+            prompt.push_str(&term.text);
+        }
+        (!prompt.is_empty()).then_some(prompt)
+    }
+
+    /// Fixes near-miss spellings of glossary terms in every entry. Returns each
+    /// change made, in transcript order.
+    pub fn correct(&self, transcript: &mut Transcript) -> Vec<Correction> {
+        let candidates: Vec<&Term> = self.terms.iter().filter(|t| t.key.len() >= MIN_TERM_LETTERS).collect();
+        if candidates.is_empty() {
+            return Vec::new();
+        }
+
+        // Terms each speaker has said correctly somewhere in the session
+        let mut familiar: HashMap<Option<u64>, HashSet<usize>> = HashMap::new();
+        for entry in &transcript.entries {
+            let tokens = tokenize(&entry.text);
+            let heard = familiar.entry(entry.speaker).or_default();
+            for start in 0..tokens.len() {
+                for (index, term) in candidates.iter().enumerate() {
+                    if window_key(&entry.text, &tokens, start, term.words).is_some_and(|(key, _)| key == term.key) {
+                        heard.insert(index);
+                    }
+                }
+            }
+        }
+
+        let mut corrections = Vec::new();
+        for entry in &mut transcript.entries {
+            let heard = familiar.get(&entry.speaker);
+            let (text, changes) = correct_text(&entry.text, &candidates, heard);
+            for (original, index, score) in changes {
+                corrections.push(Correction {
+                    start_ms: entry.start_ms,
+                    speaker: entry.speaker,
+                    speaker_name: entry.speaker_name.clone(),
+                    original,
+                    corrected: candidates[index].text.clone(),
+                    kind: candidates[index].kind.as_str(),
+                    score,
+                });
+            }
+            entry.text = text;
+        }
+        corrections
+    }
+}
+
+pub fn save_corrections(dir: &Path, corrections: &[Correction]) -> Result<(), Error> {
+    let path = dir.join(CORRECTIONS_FILE);
+    std::fs::write(&path, serde_json::to_vec_pretty(corrections)?)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// What the words from one position match best.
+enum Found {
+    /// This many words already spell a term right
+    Exact(usize),
+    /// (score, term index, words, where the replaced text ends)
+    Near(f32, usize, usize, usize),
+}
+
+/// Returns the corrected text and (original, term index, score) per change.
+fn correct_text(text: &str, terms: &[&Term], familiar: Option<&HashSet<usize>>) -> (String, Vec<(String, usize, f32)>) {
+    let tokens = tokenize(text);
+    let mut out = String::with_capacity(text.len());
+    let mut changes = Vec::new();
+    let mut cursor = 0;
+    let mut start = 0;
+
+    while start < tokens.len() {
+        match best_match(text, &tokens, start, terms, familiar) {
+            Some(Found::Exact(words)) => start += words,
+            Some(Found::Near(score, index, words, suffix_at)) => {
+                // A name a word further on shouldn't take the word before it along ("to Never winter")
+                let later_is_better = (1..words).any(|offset| match best_match(text, &tokens, start + offset, terms, familiar) {
+                    Some(Found::Exact(_)) => true,
+                    Some(Found::Near(later, ..)) => later > score,
+                    None => false,
+                });
+                if later_is_better {
+                    start += 1;
+                    continue;
+                }
+                let from = tokens[start].0;
+                out.push_str(&text[cursor..from]);
+                out.push_str(&terms[index].text);
+                changes.push((text[from..suffix_at].to_string(), index, score));
+                cursor = suffix_at;
+                start += words;
+            }
+            None => start += 1,
+        }
+    }
+    out.push_str(&text[cursor..]);
+    (out, changes)
+}
+
+fn best_match(
+    text: &str,
+    tokens: &[(usize, usize)],
+    start: usize,
+    terms: &[&Term],
+    familiar: Option<&HashSet<usize>>,
+) -> Option<Found> {
+    let mut best: Option<(f32, usize, usize, usize)> = None;
+    for (index, term) in terms.iter().enumerate() {
+        let threshold = if familiar.is_some_and(|f| f.contains(&index)) { FAMILIAR_THRESHOLD } else { MATCH_THRESHOLD };
+        for words in term.words.saturating_sub(1).max(1)..=term.words + MAX_EXTRA_WORDS {
+            let Some((key, suffix_at)) = window_key(text, tokens, start, words) else { break };
+            if key == term.key {
+                if words == term.words {
+                    // Already spelled right; leave these words alone
+                    return Some(Found::Exact(words));
+                }
+                // The right letters, split or run together ("Never winter")
+                best = Some((1.0, index, words, suffix_at));
+                continue;
+            }
+            let length_gap = key.len().abs_diff(term.key.len());
+            if length_gap * 10 > term.key.len() * 3 {
+                continue;
+            }
+            let score = similarity(&key, &term.key);
+            if score >= threshold && best.is_none_or(|(best_score, ..)| score > best_score) {
+                best = Some((score, index, words, suffix_at));
+            }
+        }
+    }
+    best.map(|(score, index, words, suffix_at)| Found::Near(score, index, words, suffix_at))
+}
+
+/// Byte ranges of the words in `text`.
+fn tokenize(text: &str) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        let word = c.is_alphanumeric() || c == '\'' || c == '’' || c == '-';
+        match (word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, text.len()));
+    }
+    tokens
+}
+
+/// The comparison key of `words` tokens from `start`, with a trailing possessive
+/// left out, and where the replaced text ends (so "Zan a thar's" keeps its "'s").
+fn window_key(text: &str, tokens: &[(usize, usize)], start: usize, words: usize) -> Option<(String, usize)> {
+    let window = tokens.get(start..start + words)?;
+    let (_, mut end) = *window.last()?;
+    let last = &text[window.last()?.0..end];
+    for suffix in ["'s", "’s"] {
+        if last.len() > suffix.len() && last.ends_with(suffix) {
+            end -= suffix.len();
+        }
+    }
+
+    let key = window
+        .iter()
+        .map(|&(from, to)| letters(&text[from..to.min(end)]))
+        .collect::<String>();
+    Some((key, end))
+}
+
+fn letters(text: &str) -> String {
+    text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// 1.0 for identical strings, falling with the Levenshtein distance.
+fn similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f32 / longest as f32
+}
+
+fn note_names(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "md"))
+                .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::whisper::Segment;
+
+    fn glossary() -> Glossary {
+        let mut glossary = Glossary::default();
+        glossary.add("Xanathar", TermKind::Npc);
+        glossary.add("Neverwinter", TermKind::Place);
+        glossary.add("Thalia", TermKind::Character);
+        glossary
+    }
+
+    fn fix(glossary: &Glossary, text: &str) -> String {
+        let terms: Vec<&Term> = glossary.terms().iter().collect();
+        correct_text(text, &terms, None).0
+    }
+
+    fn segment(start_ms: u64, speaker: u64, text: &str) -> Segment {
+        Segment { start_ms, end_ms: start_ms + 1_000, text: text.to_string(), confidence: 0.5, speaker: Some(speaker) }
+    }
+
+    #[test]
+    fn joins_a_name_split_into_words() {
+        let glossary = glossary();
+        assert_eq!(fix(&glossary, "We owe Zan a thar money."), "We owe Xanathar money.");
+        assert_eq!(fix(&glossary, "Zan a thar's eye is on us"), "Xanathar's eye is on us");
+        assert_eq!(fix(&glossary, "Back to Never winter."), "Back to Neverwinter.");
+    }
+
+    #[test]
+    fn fixes_near_misses_and_leaves_correct_spellings() {
+        let glossary = glossary();
+        assert_eq!(fix(&glossary, "Xanathar and Thalea"), "Xanathar and Thalia");
+
+        let terms: Vec<&Term> = glossary.terms().iter().collect();
+        let (_, changes) = correct_text("Xanathar and Thalia", &terms, None);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn leaves_common_words_alone() {
+        let glossary = glossary();
+        for text in [
+            "Then the thief ran into the tavern and ordered another round.",
+            "I think that is a terrible idea, never mind the winter.",
+            "Natural twenty, that hits. Roll damage.",
+            "Tell her what the letter said.",
+        ] {
+            assert_eq!(fix(&glossary, text), text);
+        }
+    }
+
+    #[test]
+    fn trusts_a_familiar_name_on_weaker_evidence() {
+        let mut glossary = Glossary::default();
+        glossary.add("Waterdeep", TermKind::Place);
+        let text = "We ride for Wader deap at dawn.";
+        assert_eq!(fix(&glossary, text), text);
+
+        let mut transcript = Transcript::from_segments(
+            1,
+            vec![segment(0, 7, "Waterdeep is a day away."), segment(5_000, 7, text), segment(9_000, 8, text)],
+            &Roster::default(),
+        );
+        let corrections = glossary.correct(&mut transcript);
+        assert_eq!(transcript.entries[1].text, "We ride for Waterdeep at dawn.");
+        assert_eq!(transcript.entries[2].text, text);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!(corrections[0].original, "Wader deap");
+        assert_eq!(corrections[0].kind, "place");
+    }
+
+    #[test]
+    fn prompt_stops_before_the_limit() {
+        let mut glossary = Glossary::default();
+        assert_eq!(glossary.prompt(), None);
+        for i in 0..200 {
+            glossary.add(&format!("Name{}", i), TermKind::Other);
+        }
+        let prompt = glossary.prompt().unwrap();
+        assert!(prompt.len() <= PROMPT_MAX_CHARS);
+        assert!(prompt.starts_with("Name0, Name1, "));
+    }
+}
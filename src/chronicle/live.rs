@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use poise::serenity_prelude::{ChannelId, Http};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::ai::whisper::{Segment, Transcriber, TranscriptionRequest, WHISPER_SAMPLE_RATE};
+use crate::chronicle::audio::resample;
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::recorder::{Utterance, SAMPLE_RATE};
+use crate::chronicle::transcript::clock;
+use crate::definitions::Error;
+
+/// Scratch space for utterance audio waiting to be transcribed.
+pub const LIVE_DIR: &str = "live";
+/// Segments appended as each utterance is transcribed.
+pub const LIVE_SEGMENTS_FILE: &str = "live.jsonl";
+/// Every segment of the session, written only when no utterance failed, so the
+/// post-session pipeline can use it instead of transcribing the tracks again.
+pub const LIVE_COMPLETE_FILE: &str = "live-transcript.json";
+
+/// Discord rejects messages over 2000 characters.
+const CAPTION_MAX_CHARS: usize = 1_900;
+
+/// Where live captions are posted.
+pub struct Captions {
+    pub http: Arc<Http>,
+    pub thread: ChannelId,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LiveSummary {
+    pub utterances: usize,
+    pub segments: usize,
+    pub failed: usize,
+}
+
+impl LiveSummary {
+    pub fn is_complete(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+/// Transcribes utterances from the recorder while the session runs.
+pub struct LiveTranscription {
+    handle: JoinHandle<LiveSummary>,
+}
+
+impl LiveTranscription {
+    /// Starts the worker; the recorder sends utterances to the returned sender and
+    /// the worker stops once every sender is dropped and the queue is empty.
+    pub fn spawn(
+        transcriber: Arc<dyn Transcriber>,
+        roster: Roster,
+        session_dir: PathBuf,
+        captions: Option<Captions>,
+        prompt: Option<String>,
+    ) -> (UnboundedSender<Utterance>, Self) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(run(receiver, transcriber, roster, session_dir, captions, prompt));
+        (sender, Self { handle })
+    }
+
+    /// Waits for the queued utterances to be transcribed.
+    pub async fn finish(self) -> Result<LiveSummary, Error> {
+        self.handle
+            .await
+            .map_err(|e| format!("Live transcription panicked: {}", e).into())
+    }
+}
+
+/// The live transcript of a session, if every utterance made it into one.
+pub fn load_completed(session_dir: &Path) -> Result<Option<Vec<Segment>>, Error> {
+    let path = session_dir.join(LIVE_COMPLETE_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+async fn run(
+    mut receiver: UnboundedReceiver<Utterance>,
+    transcriber: Arc<dyn Transcriber>,
+    roster: Roster,
+    session_dir: PathBuf,
+    captions: Option<Captions>,
+    prompt: Option<String>,
+) -> LiveSummary {
+    let scratch = session_dir.join(LIVE_DIR);
+    if let Err(e) = tokio::fs::create_dir_all(&scratch).await {
+        warn!(error = %e, "Failed to create live transcription directory");
+    }
+
+    let mut summary = LiveSummary::default();
+    let mut segments = Vec::new();
+    let mut captions_failed = false;
+    while let Some(utterance) = receiver.recv().await {
+        summary.utterances += 1;
+        let backlog = receiver.len();
+        if backlog > 0 {
+            debug!(backlog, "Live transcription is behind");
+        }
+
+        let transcribed = match transcribe_utterance(transcriber.as_ref(), &scratch, &utterance, prompt.clone()).await {
+            Ok(transcribed) => transcribed,
+            Err(e) => {
+                warn!(ssrc = utterance.ssrc, error = %e, "Live transcription failed");
+                summary.failed += 1;
+                continue;
+            }
+        };
+        if transcribed.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = append_segments(&session_dir, &transcribed).await {
+            warn!(error = %e, "Failed to save live transcript");
+        }
+        if let Some(captions) = &captions {
+            let text = caption(&transcribed, &roster);
+            if let Err(e) = captions.thread.say(&*captions.http, text).await {
+                // One warning is enough; a deleted thread would otherwise log every line
+                if !captions_failed {
+                    warn!(error = %e, "Failed to post live captions");
+                    captions_failed = true;
+                }
+            }
+        }
+        summary.segments += transcribed.len();
+        segments.extend(transcribed);
+    }
+
+    tokio::fs::remove_dir_all(&scratch).await.ok();
+    if summary.is_complete() {
+        segments.sort_by_key(|s| s.start_ms);
+        let path = session_dir.join(LIVE_COMPLETE_FILE);
+        let written = match serde_json::to_vec_pretty(&segments) {
+            Ok(json) => tokio::fs::write(&path, json).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = written {
+            warn!(path = %path.display(), error = %e, "Failed to save live transcript");
+        }
+    }
+
+    info!(
+        utterances = summary.utterances,
+        segments = summary.segments,
+        failed = summary.failed,
+        "Live transcription finished",
+    );
+    summary
+}
+
+async fn transcribe_utterance(
+    transcriber: &dyn Transcriber,
+    scratch: &Path,
+    utterance: &Utterance,
+    prompt: Option<String>,
+) -> Result<Vec<Segment>, Error> {
+    let audio = scratch.join(format!("{}-{}.wav", utterance.ssrc, utterance.start_sample));
+    tokio::task::spawn_blocking({
+        let audio = audio.clone();
+        let samples = utterance.samples.clone();
+        move || write_wav(&audio, &resample(&samples, SAMPLE_RATE, WHISPER_SAMPLE_RATE))
+    })
+    .await
+    .map_err(|e| format!("Utterance writer panicked: {}", e))??;
+
+    let request = TranscriptionRequest {
+        audio: audio.clone(),
+        speaker: utterance.user_id,
+        offset_ms: utterance.start_sample * 1000 / SAMPLE_RATE as u64,
+        prompt,
+    };
+    let result = transcriber.transcribe(&request).await;
+    tokio::fs::remove_file(&audio).await.ok();
+    result
+}
+
+fn write_wav(path: &Path, samples: &[i16]) -> Result<(), Error> {
+    let mut writer = WavWriter::create(path, WavSpec {
+        channels: 1,
+        sample_rate: WHISPER_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    })
+    .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+async fn append_segments(session_dir: &Path, segments: &[Segment]) -> Result<(), Error> {
+    let mut lines = String::new();
+    for segment in segments {
+        lines.push_str(&serde_json::to_string(segment)?);
+        lines.push('\n');
+    }
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(session_dir.join(LIVE_SEGMENTS_FILE))
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    Ok(())
+}
+
+/// `` `[00:12:03]` **Thalia:** text ``, one line per utterance.
+fn caption(segments: &[Segment], roster: &Roster) -> String {
+    let text = segments.iter().map(|s| s.text.trim()).collect::<Vec<_>>().join(" ");
+    let line = format!(
+        "`[{}]` **{}:** {}",
+        clock(segments[0].start_ms),
+        roster.speaker_name(segments[0].speaker),
+        text,
+    );
+    if line.chars().count() <= CAPTION_MAX_CHARS {
+        return line;
+    }
+    let cut: String = line.chars().take(CAPTION_MAX_CHARS - 1).collect();
+    format!("{}…", cut)
+}
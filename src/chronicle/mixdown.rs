@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::chronicle::audio::{detect_speech, frame_energies, VadConfig};
+use crate::chronicle::config::{ArchiveFormat, ChronicleConfig};
+use crate::chronicle::obsidian::SESSIONS_DIR;
+use crate::chronicle::processor::SessionSummary;
+use crate::chronicle::recorder::RecordingManifest;
+use crate::definitions::Error;
+
+/// Archived audio sits beside the session notes, in this subfolder.
+pub const AUDIO_DIR: &str = "audio";
+const MIX_FILE: &str = "mixdown.wav";
+const CHAPTERS_FILE: &str = "chapters.ffmeta";
+
+/// Each speaker is brought towards this speech level before mixing.
+const TARGET_SPEECH_DB: f32 = -20.0;
+/// Never boost or cut a speaker by more than this, so a near-silent track isn't
+/// turned into a wall of noise.
+const MAX_GAIN_DB: f32 = 12.0;
+/// Final integrated loudness, the usual target for spoken-word podcasts.
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+const MIX_BLOCK: usize = 4_800;
+const CHAPTER_TITLE_MAX: usize = 60;
+
+/// Where a session's archive goes: `<campaign folder>/Sessions/audio/<note title>.<ext>`.
+pub fn archive_path(vault: &Path, campaign_folder: &str, note_title: &str, format: ArchiveFormat) -> PathBuf {
+    vault
+        .join(campaign_folder)
+        .join(SESSIONS_DIR)
+        .join(AUDIO_DIR)
+        .join(format!("{}.{}", note_title, format.extension()))
+}
+
+/// Embeds the archive in the session note.
+pub fn note_embed(path: &Path) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    format!("## Recording\n![[{}]]", name)
+}
+
+/// Mixes the session's speaker tracks into one loudness-normalised file with a
+/// chapter per summarised scene, using ffmpeg for the encode.
+pub async fn mix_session(
+    session_dir: &Path,
+    manifest: &RecordingManifest,
+    summary: Option<&SessionSummary>,
+    title: &str,
+    output: &Path,
+    config: &ChronicleConfig,
+) -> Result<(), Error> {
+    if manifest.speakers.is_empty() {
+        return Err("The session has no recorded speakers to mix".into());
+    }
+
+    let tracks: Vec<PathBuf> = manifest.speakers.iter().map(|s| session_dir.join(&s.file)).collect();
+    let mix_path = session_dir.join(MIX_FILE);
+    tokio::task::spawn_blocking({
+        let mix_path = mix_path.clone();
+        move || mix_tracks(&tracks, &mix_path)
+    })
+    .await
+    .map_err(|e| format!("Mixdown panicked: {}", e))??;
+
+    let chapters = summary.map(|s| chapters(s, manifest.total_samples * 1000 / manifest.sample_rate as u64)).unwrap_or_default();
+    let chapters_path = session_dir.join(CHAPTERS_FILE);
+    tokio::fs::write(&chapters_path, ffmetadata(title, &chapters)).await?;
+
+    if let Some(parent) = output.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let codec = match config.archive_format {
+        ArchiveFormat::Opus => ["-c:a", "libopus", "-application", "voip"],
+        ArchiveFormat::Mp3 => ["-c:a", "libmp3lame", "-id3v2_version", "3"],
+    };
+    let result = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(&mix_path)
+        .arg("-i")
+        .arg(&chapters_path)
+        .args(["-map", "0:a", "-map_metadata", "1", "-map_chapters", "1", "-af", LOUDNORM_FILTER])
+        .args(codec)
+        .args(["-b:a", &format!("{}k", config.archive_bitrate_kbps)])
+        .arg(output)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e));
+
+    tokio::fs::remove_file(&mix_path).await.ok();
+    tokio::fs::remove_file(&chapters_path).await.ok();
+
+    let result = result?;
+    if !result.status.success() {
+        return Err(format!(
+            "ffmpeg failed to encode {}: {}",
+            output.display(),
+            String::from_utf8_lossy(&result.stderr).trim(),
+        ).into());
+    }
+
+    info!(path = %output.display(), chapters = chapters.len(), "Session mixdown written");
+    Ok(())
+}
+
+/// Sums the tracks (all the same length, as the recorder writes them) with a gain
+/// per speaker that evens out quiet and loud microphones.
+fn mix_tracks(tracks: &[PathBuf], output: &Path) -> Result<(), Error> {
+    let vad = VadConfig::default();
+    let mut gains = Vec::with_capacity(tracks.len());
+    for track in tracks {
+        let gain_db = speech_level_db(track, &vad)?
+            .map_or(0.0, |level| (TARGET_SPEECH_DB - level).clamp(-MAX_GAIN_DB, MAX_GAIN_DB));
+        debug!(track = %track.display(), gain_db, "Speaker gain");
+        gains.push(10f32.powf(gain_db / 20.0) / i16::MAX as f32);
+    }
+
+    let mut readers = tracks
+        .iter()
+        .map(|t| WavReader::open(t).map_err(|e| format!("Failed to open {}: {}", t.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let rate = readers[0].spec().sample_rate;
+    if readers.iter().any(|r| r.spec().sample_rate != rate || r.spec().channels != 1) {
+        return Err("Speaker tracks must all be mono at the same sample rate".into());
+    }
+
+    let mut writer = WavWriter::create(output, WavSpec {
+        channels: 1,
+        sample_rate: rate,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    })
+    .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+
+    let mut samples: Vec<_> = readers.iter_mut().map(|r| r.samples::<i16>()).collect();
+    let mut block = vec![0f32; MIX_BLOCK];
+    loop {
+        block.iter_mut().for_each(|s| *s = 0.0);
+        let mut filled = 0;
+        for (track, gain) in samples.iter_mut().zip(&gains) {
+            for (i, slot) in block.iter_mut().enumerate() {
+                match track.next() {
+                    Some(sample) => {
+                        *slot += sample? as f32 * gain;
+                        filled = filled.max(i + 1);
+                    }
+                    None => break,
+                }
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+        for &value in &block[..filled] {
+            // Soft-clip the rare moments several people shout at once
+            let limited = if value.abs() > 0.9 { value.signum() * (0.9 + 0.1 * ((value.abs() - 0.9) * 10.0).tanh()) } else { value };
+            writer.write_sample((limited * i16::MAX as f32) as i16)?;
+        }
+    }
+    writer.finalize().map_err(|e| format!("Failed to finalise {}: {}", output.display(), e))?;
+    Ok(())
+}
+
+/// Average level of the speech in a track, ignoring the silence between turns.
+fn speech_level_db(track: &Path, vad: &VadConfig) -> Result<Option<f32>, Error> {
+    let mut reader = WavReader::open(track).map_err(|e| format!("Failed to open {}: {}", track.display(), e))?;
+    let rate = reader.spec().sample_rate;
+    let total = reader.duration() as u64;
+    let frame_len = (rate as u64 * vad.frame_ms as u64 / 1000).max(1);
+
+    let energies = frame_energies(reader.samples::<i16>(), frame_len as usize)?;
+    let regions = detect_speech(&energies, frame_len, total, rate, vad);
+
+    let mut power = 0f64;
+    let mut frames = 0usize;
+    for region in regions {
+        let first = (region.start / frame_len) as usize;
+        let last = (region.end.div_ceil(frame_len) as usize).min(energies.len());
+        for &energy in &energies[first..last] {
+            power += 10f64.powf(energy as f64 / 10.0);
+            frames += 1;
+        }
+    }
+    Ok((frames > 0).then(|| (10.0 * (power / frames as f64).log10()) as f32))
+}
+
+struct Chapter {
+    start_ms: u64,
+    end_ms: u64,
+    title: String,
+}
+
+/// One chapter per scene with a start time, each running until the next.
+fn chapters(summary: &SessionSummary, total_ms: u64) -> Vec<Chapter> {
+    let mut starts: Vec<(u64, String)> = summary
+        .scenes
+        .iter()
+        .filter_map(|scene| Some((scene.start_ms?.min(total_ms), chapter_title(&scene.text))))
+        .collect();
+    starts.sort_by_key(|(start, _)| *start);
+    starts.dedup_by_key(|(start, _)| *start);
+    if starts.first().is_some_and(|(start, _)| *start > 0) {
+        starts.insert(0, (0, "Opening".to_string()));
+    }
+
+    let ends: Vec<u64> = starts.iter().skip(1).map(|(start, _)| *start).chain(std::iter::once(total_ms)).collect();
+    starts
+        .into_iter()
+        .zip(ends)
+        .filter(|((start, _), end)| end > start)
+        .map(|((start_ms, title), end_ms)| Chapter { start_ms, end_ms, title })
+        .collect()
+}
+
+/// The scene's first sentence, shortened for a player's chapter list.
+fn chapter_title(text: &str) -> String {
+    let sentence = text.split_inclusive(['.', '!', '?']).next().unwrap_or(text).trim();
+    if sentence.chars().count() <= CHAPTER_TITLE_MAX {
+        return sentence.trim_end_matches('.').to_string();
+    }
+    let cut: String = sentence.chars().take(CHAPTER_TITLE_MAX - 1).collect();
+    format!("{}…", cut.trim_end())
+}
+
+fn ffmetadata(title: &str, chapters: &[Chapter]) -> String {
+    let escape = |s: &str| {
+        s.chars().fold(String::new(), |mut out, c| {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                out.push('\\');
+            }
+            out.push(c);
+            out
+        })
+    };
+
+    let mut out = format!(";FFMETADATA1\ntitle={}\n", escape(title));
+    for chapter in chapters {
+        out.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            chapter.start_ms,
+            chapter.end_ms,
+            escape(&chapter.title),
+        ));
+    }
+    out
+}
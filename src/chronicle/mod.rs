@@ -0,0 +1,15 @@
+pub mod audio;
+pub mod campaign;
+pub mod config;
+pub mod entities;
+pub mod glossary;
+pub mod live;
+pub mod mixdown;
+pub mod obsidian;
+pub mod pipeline;
+pub mod processor;
+pub mod recorder;
+pub mod retention;
+pub mod search;
+pub mod session;
+pub mod transcript;
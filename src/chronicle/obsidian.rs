@@ -0,0 +1,427 @@
+use std::path::{Path, PathBuf};
+
+use crate::chronicle::campaign::{default_obsidian_folder, Roster};
+use crate::chronicle::entities::SessionEntities;
+use crate::chronicle::processor::SessionSummary;
+use crate::db::chronicle::{CampaignRow, SessionRow};
+use crate::definitions::Error;
+
+pub const SESSIONS_DIR: &str = "Sessions";
+pub const CHARACTERS_DIR: &str = "Characters";
+pub const NPCS_DIR: &str = "NPCs";
+pub const LOCATIONS_DIR: &str = "Locations";
+
+/// Generated text sits between these markers; everything outside them is the
+/// user's and is never touched on a rerun.
+const BLOCK_BEGIN: &str = "<!-- chronicle:begin";
+const BLOCK_END: &str = "<!-- chronicle:end";
+const FRONTMATTER_FENCE: &str = "---";
+
+/// Notes a session note links to, by display name.
+#[derive(Clone, Debug, Default)]
+pub struct NoteLinks {
+    pub characters: Vec<String>,
+    pub npcs: Vec<String>,
+    pub locations: Vec<String>,
+}
+
+impl NoteLinks {
+    /// Links for the campaign's characters who were at the session.
+    pub fn from_roster(roster: &Roster, participants: &[u64]) -> Self {
+        let mut characters: Vec<String> = participants
+            .iter()
+            .filter_map(|id| roster.character(*id).map(|c| c.name.clone()))
+            .collect();
+        characters.sort();
+        characters.dedup();
+        Self { characters, ..Default::default() }
+    }
+
+    /// Adds the NPCs and locations extracted from the session.
+    pub fn with_entities(mut self, entities: &SessionEntities) -> Self {
+        self.npcs.extend(entities.npcs.iter().map(|n| n.name.clone()));
+        self.locations.extend(entities.locations.iter().map(|l| l.name.clone()));
+        for names in [&mut self.npcs, &mut self.locations] {
+            names.sort();
+            names.dedup();
+        }
+        self
+    }
+}
+
+/// Everything written into one session note.
+pub struct SessionNote<'a> {
+    pub campaign: &'a CampaignRow,
+    pub session: &'a SessionRow,
+    pub summary: &'a SessionSummary,
+    /// Character names, or player labels for players without one
+    pub participants: Vec<String>,
+    pub duration_secs: u64,
+    pub links: NoteLinks,
+    /// Extra Markdown for the note's generated part, e.g. an audio embed
+    pub attachments: Vec<String>,
+}
+
+/// Writes (or refreshes) the session note, creates stubs for anything it links to
+/// that has no note yet, and rebuilds the session list in the campaign index.
+/// Returns the session note's path.
+pub fn export_session(vault: &Path, note: &SessionNote) -> Result<PathBuf, Error> {
+    let campaign_dir = vault.join(&note.campaign.obsidian_folder);
+    let sessions_dir = campaign_dir.join(SESSIONS_DIR);
+    std::fs::create_dir_all(&sessions_dir)
+        .map_err(|e| format!("Failed to create {}: {}", sessions_dir.display(), e))?;
+
+    let path = sessions_dir.join(format!("{}.md", session_title(note.session)));
+    let existing = std::fs::read_to_string(&path).unwrap_or_default();
+
+    let frontmatter = vec![
+        ("campaign", yaml_string(&note.campaign.name)),
+        ("session", note.session.session_number.unwrap_or(note.session.id).to_string()),
+        ("date", format_date(note.session.started_at)),
+        ("participants", yaml_list(&note.participants)),
+        ("duration", format_duration(note.duration_secs)),
+        ("tags", yaml_list(&["chronicle/session".to_string()])),
+    ];
+    let mut content = merge_frontmatter(&existing, &frontmatter);
+    content = upsert_block(&content, "summary", &render_summary(note));
+    content = upsert_block(&content, "links", &render_links(&note.links));
+
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    for name in &note.links.characters {
+        create_stub(&campaign_dir.join(CHARACTERS_DIR), name, "character", &note.campaign.name)?;
+    }
+    for name in &note.links.npcs {
+        create_stub(&campaign_dir.join(NPCS_DIR), name, "npc", &note.campaign.name)?;
+    }
+    for name in &note.links.locations {
+        create_stub(&campaign_dir.join(LOCATIONS_DIR), name, "location", &note.campaign.name)?;
+    }
+
+    update_index(&campaign_dir, note.campaign)?;
+    Ok(path)
+}
+
+/// "Session 003", sortable by name in the file explorer.
+pub fn session_title(session: &SessionRow) -> String {
+    match session.session_number {
+        Some(number) => format!("Session {:03}", number),
+        None => format!("Session #{}", session.id),
+    }
+}
+
+fn render_summary(note: &SessionNote) -> String {
+    let mut markdown = link_names(&note.summary.markdown, &note.links);
+    for attachment in &note.attachments {
+        markdown.push_str("\n\n");
+        markdown.push_str(attachment);
+    }
+    markdown
+}
+
+fn render_links(links: &NoteLinks) -> String {
+    let mut out = String::from("## Appearing\n");
+    for (label, names) in [("Characters", &links.characters), ("NPCs", &links.npcs), ("Locations", &links.locations)] {
+        if names.is_empty() {
+            continue;
+        }
+        let linked: Vec<String> = names.iter().map(|n| format!("[[{}]]", note_name(n))).collect();
+        out.push_str(&format!("- **{}:** {}\n", label, linked.join(", ")));
+    }
+    out
+}
+
+/// Turns the first mention of each known name into a wiki link.
+fn link_names(markdown: &str, links: &NoteLinks) -> String {
+    let mut names: Vec<&String> = links.characters.iter().chain(&links.npcs).chain(&links.locations).collect();
+    // Longest first, so "Waterdeep Docks" is linked before "Waterdeep"
+    names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+
+    let mut out = markdown.to_string();
+    for name in names {
+        if name.is_empty() || out.contains(&format!("[[{}", name)) {
+            continue;
+        }
+        if let Some(start) = find_word(&out, name) {
+            let target = note_name(name);
+            let link = if target == *name { format!("[[{}]]", name) } else { format!("[[{}|{}]]", target, name) };
+            out.replace_range(start..start + name.len(), &link);
+        }
+    }
+    out
+}
+
+/// Byte offset of `word` where it isn't part of a longer word or an existing link.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(offset) = text[from..].find(word) {
+        let start = from + offset;
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        let boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric() && c != '[' && c != '|');
+        if boundary(before) && boundary(after) {
+            return Some(start);
+        }
+        from = end;
+    }
+    None
+}
+
+fn create_stub(dir: &Path, name: &str, kind: &str, campaign: &str) -> Result<(), Error> {
+    let path = dir.join(format!("{}.md", note_name(name)));
+    if path.exists() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    std::fs::write(
+        &path,
+        format!(
+            "{FRONTMATTER_FENCE}\ntype: {kind}\ncampaign: {}\ntags: [chronicle/{kind}]\n{FRONTMATTER_FENCE}\n\n# {name}\n\nCreated by chronicle. Add notes here; this file is never overwritten.\n",
+            yaml_string(campaign),
+        ),
+    )
+    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// The campaign's folder note lists every session note, newest last.
+fn update_index(campaign_dir: &Path, campaign: &CampaignRow) -> Result<(), Error> {
+    let path = campaign_dir.join(format!("{}.md", note_name(&campaign.name)));
+    let existing = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| format!("# {}\n", campaign.name));
+
+    let mut sessions: Vec<String> = std::fs::read_dir(campaign_dir.join(SESSIONS_DIR))
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|e| e == "md"))
+                .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .collect()
+        })
+        .unwrap_or_default();
+    sessions.sort();
+
+    let mut list = String::from("## Sessions\n");
+    for session in sessions {
+        let date = frontmatter_value(
+            &std::fs::read_to_string(campaign_dir.join(SESSIONS_DIR).join(format!("{session}.md"))).unwrap_or_default(),
+            "date",
+        );
+        match date {
+            Some(date) => list.push_str(&format!("- [[{}]] · {}\n", session, date)),
+            None => list.push_str(&format!("- [[{}]]\n", session)),
+        }
+    }
+
+    let content = upsert_block(&existing, "sessions", &list);
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Replaces the named generated block, or appends it if the note doesn't have one.
+fn upsert_block(content: &str, name: &str, body: &str) -> String {
+    let begin = format!("{BLOCK_BEGIN} {name} -->");
+    let end = format!("{BLOCK_END} {name} -->");
+    let block = format!("{begin}\n{}\n{end}", body.trim_end());
+
+    if let Some(start) = content.find(&begin)
+        && let Some(offset) = content[start..].find(&end)
+    {
+        let stop = start + offset + end.len();
+        return format!("{}{}{}", &content[..start], block, &content[stop..]);
+    }
+
+    let mut out = content.trim_end().to_string();
+    if !out.is_empty() {
+        out.push_str("\n\n");
+    }
+    out.push_str(&block);
+    out.push('\n');
+    out
+}
+
+/// Sets chronicle's frontmatter keys, keeping any the user added and the body as is.
+fn merge_frontmatter(content: &str, keys: &[(&str, String)]) -> String {
+    let (existing, body) = split_frontmatter(content);
+
+    // Top-level keys own the indented or list lines beneath them
+    let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+    for line in existing.lines() {
+        match line.split_once(':') {
+            Some((key, _)) if !line.starts_with([' ', '\t', '-']) => {
+                entries.push((key.trim().to_string(), vec![line.to_string()]));
+            }
+            _ => match entries.last_mut() {
+                Some((_, lines)) => lines.push(line.to_string()),
+                None => entries.push((String::new(), vec![line.to_string()])),
+            },
+        }
+    }
+
+    for (key, value) in keys {
+        let line = format!("{}: {}", key, value);
+        match entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = vec![line],
+            None => entries.push((key.to_string(), vec![line])),
+        }
+    }
+
+    let yaml: Vec<String> = entries.into_iter().flat_map(|(_, lines)| lines).collect();
+    format!("{FRONTMATTER_FENCE}\n{}\n{FRONTMATTER_FENCE}\n\n{}", yaml.join("\n"), body.trim_start())
+}
+
+fn split_frontmatter(content: &str) -> (&str, &str) {
+    if let Some(rest) = content.strip_prefix(&format!("{FRONTMATTER_FENCE}\n"))
+        && let Some(end) = rest.find(&format!("\n{FRONTMATTER_FENCE}"))
+    {
+        let after = &rest[end + 1 + FRONTMATTER_FENCE.len()..];
+        return (&rest[..end], after.strip_prefix('\n').unwrap_or(after));
+    }
+    ("", content)
+}
+
+fn frontmatter_value(content: &str, key: &str) -> Option<String> {
+    let (frontmatter, _) = split_frontmatter(content);
+    frontmatter.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+/// A file name Obsidian accepts for a note titled `name`.
+pub fn note_name(name: &str) -> String {
+    let cleaned = default_obsidian_folder(name);
+    if cleaned.is_empty() { "Untitled".to_string() } else { cleaned }
+}
+
+fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn yaml_list(values: &[String]) -> String {
+    format!("[{}]", values.iter().map(|v| yaml_string(v)).collect::<Vec<_>>().join(", "))
+}
+
+/// `H:MM:SS` for a length in seconds.
+pub fn format_duration(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// `YYYY-MM-DD` (UTC) for a Unix timestamp.
+pub fn format_date(unix_secs: i64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let days = unix_secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campaign() -> CampaignRow {
+        CampaignRow {
+            id: 1,
+            name: "Dragon Heist".to_string(),
+            obsidian_folder: "Dragon Heist".to_string(),
+            channel_id: None,
+            archived: false,
+            audio_retention_days: None,
+            transcript_retention_days: None,
+        }
+    }
+
+    fn session() -> SessionRow {
+        SessionRow {
+            id: 12,
+            guild_id: 1,
+            text_channel_id: 2,
+            campaign_id: Some(1),
+            session_number: Some(3),
+            started_at: 1_700_000_000,
+            ended_at: Some(1_700_010_000),
+            storage_dir: String::new(),
+            approved_at: None,
+        }
+    }
+
+    fn summary(overview: &str) -> SessionSummary {
+        SessionSummary {
+            session_id: 12,
+            overview: overview.to_string(),
+            markdown: format!("## Overview\n{overview}"),
+            ..Default::default()
+        }
+    }
+
+    fn export(vault: &Path, summary: &SessionSummary, npcs: &[&str]) -> PathBuf {
+        let campaign = campaign();
+        let session = session();
+        let links = NoteLinks { npcs: npcs.iter().map(|n| n.to_string()).collect(), ..Default::default() };
+        export_session(vault, &SessionNote {
+            campaign: &campaign,
+            session: &session,
+            summary,
+            participants: vec!["Thalia".to_string()],
+            duration_secs: 3_725,
+            links,
+            attachments: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn rerunning_the_export_keeps_hand_written_sections() {
+        let vault = std::env::temp_dir().join(format!("chester-obsidian-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&vault);
+
+        let path = export(&vault, &summary("The party met Volo."), &["Volo"]);
+        assert!(path.ends_with("Dragon Heist/Sessions/Session 003.md"));
+
+        // The user adds a frontmatter key, notes around the generated blocks and to a stub
+        let written = std::fs::read_to_string(&path).unwrap();
+        let edited = written
+            .replacen("---\n\n", "---\n\n# My notes\nThalia owes Volo a favour.\n\n", 1)
+            .replacen("campaign:", "mood: tense\ncampaign:", 1)
+            + "\n## After the session\nBring snacks next time.\n";
+        std::fs::write(&path, &edited).unwrap();
+        let stub = vault.join("Dragon Heist").join(NPCS_DIR).join("Volo.md");
+        let stub_notes = std::fs::read_to_string(&stub).unwrap() + "\nSells books.\n";
+        std::fs::write(&stub, &stub_notes).unwrap();
+
+        export(&vault, &summary("The party met Volo and took the job."), &["Volo"]);
+
+        let rerun = std::fs::read_to_string(&path).unwrap();
+        assert!(rerun.contains("mood: tense"));
+        assert!(rerun.contains("# My notes\nThalia owes Volo a favour.\n"));
+        assert!(rerun.ends_with("## After the session\nBring snacks next time.\n"));
+        // The first mention of a known name becomes a link
+        assert!(rerun.contains("The party met [[Volo]] and took the job."));
+        assert!(!rerun.contains("The party met [[Volo]].\n"));
+        assert_eq!(rerun.matches(&format!("{BLOCK_BEGIN} summary -->")).count(), 1);
+        assert_eq!(frontmatter_value(&rerun, "duration").as_deref(), Some("1:02:05"));
+        assert_eq!(std::fs::read_to_string(&stub).unwrap(), stub_notes);
+
+        let index = std::fs::read_to_string(vault.join("Dragon Heist").join("Dragon Heist.md")).unwrap();
+        assert_eq!(index.matches("[[Session 003]]").count(), 1);
+
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn dates_and_durations_are_formatted() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_duration(59), "0:00:59");
+        assert_eq!(format_duration(36_061), "10:01:01");
+    }
+}
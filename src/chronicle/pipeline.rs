@@ -0,0 +1,679 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use poise::serenity_prelude::{ChannelId, Http};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
+
+use crate::ai::prompts::{fnv1a, PromptKind, PromptTemplate};
+use crate::ai::whisper::{Segment, TranscriptionRequest};
+use crate::chronicle::audio::{prepare_for_transcription, TimeMap, VadConfig};
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::config::{ChronicleConfig, LlmKind};
+use crate::chronicle::entities::{self, SessionEntities, ENTITIES_FILE};
+use crate::chronicle::glossary::{save_corrections, Glossary};
+use crate::chronicle::live::{load_completed, LIVE_COMPLETE_FILE};
+use crate::chronicle::mixdown::{archive_path, mix_session, note_embed};
+use crate::chronicle::obsidian::{export_session, session_title, NoteLinks, SessionNote};
+use crate::chronicle::processor::{Processor, SessionSummary, SummaryContext, CACHE_DIR, SUMMARY_FILE};
+use crate::chronicle::recorder::{RecordingManifest, MANIFEST_FILE};
+use crate::chronicle::search;
+use crate::chronicle::session::unix_now;
+use crate::chronicle::transcript::Transcript;
+use crate::db::chronicle::{
+    fail_unfinished_jobs, fetch_campaign, fetch_jobs, fetch_session, fetch_session_participants,
+    fetch_unfinished_job_sessions, finish_job, queue_jobs, start_job, CampaignRow, SessionRow,
+};
+use crate::definitions::Error;
+
+/// Speaker tracks with the silences cut out, ready for the transcriber.
+pub const PREPARED_DIR: &str = "prepared";
+const PREPARED_FILE: &str = "prepared.json";
+/// Every speaker's transcribed segments on the session timeline, before correction.
+pub const SEGMENTS_FILE: &str = "segments.json";
+const REPORT_ERROR_MAX_CHARS: usize = 1_500;
+
+/// The steps from a finished recording to a session note, in the order they run.
+/// Each reads what the one before it wrote in the session directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, poise::ChoiceParameter)]
+pub enum Stage {
+    #[name = "Speech detection"]
+    Vad,
+    #[name = "Transcription"]
+    Transcribe,
+    #[name = "Glossary correction"]
+    Correct,
+    #[name = "Summary"]
+    Summarise,
+    #[name = "Entity extraction"]
+    Entities,
+    #[name = "Obsidian export"]
+    Export,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Vad,
+        Stage::Transcribe,
+        Stage::Correct,
+        Stage::Summarise,
+        Stage::Entities,
+        Stage::Export,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Vad        => "vad",
+            Stage::Transcribe => "transcribe",
+            Stage::Correct    => "correct",
+            Stage::Summarise  => "summarise",
+            Stage::Entities   => "entities",
+            Stage::Export     => "export",
+        }
+    }
+
+    /// Prefixes of the model output this stage caches, cleared when it's forced
+    /// to run again so a swapped model isn't answered from the cache.
+    fn cache_steps(&self) -> &'static [&'static str] {
+        match self {
+            Stage::Summarise => &["map-", "reduce-"],
+            Stage::Entities  => &["entities-"],
+            _ => &[],
+        }
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vad" => Ok(Stage::Vad),
+            "transcribe" => Ok(Stage::Transcribe),
+            "correct" => Ok(Stage::Correct),
+            "summarise" => Ok(Stage::Summarise),
+            "entities" => Ok(Stage::Entities),
+            "export" => Ok(Stage::Export),
+            other => Err(format!("unknown stage `{}`", other)),
+        }
+    }
+}
+
+/// How a stage's last run ended. Queued and running stages are `pending` and
+/// `running` in the job table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    Done,
+    /// Nothing to do, e.g. no vault to export to
+    Skipped,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Done    => "done",
+            JobStatus::Skipped => "skipped",
+            JobStatus::Failed  => "failed",
+        }
+    }
+}
+
+/// One speaker's track after speech detection.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PreparedTrack {
+    speaker: Option<u64>,
+    /// File name in `PREPARED_DIR`
+    audio: String,
+    time_map: TimeMap,
+}
+
+/// How a processing run went, stage by stage.
+#[derive(Clone, Debug, Default)]
+pub struct RunReport {
+    pub stages: Vec<(Stage, StageResult)>,
+    /// The stage that stopped the run, with why
+    pub failed: Option<(Stage, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageResult {
+    Ran,
+    Skipped,
+    /// Its inputs haven't changed since it last ran
+    UpToDate,
+}
+
+/// A session's place in `queued`, given up when dropped, even by a panicking run.
+struct QueueSlot {
+    queued: Arc<Mutex<HashSet<i64>>>,
+    session_id: i64,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.queued.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.session_id);
+    }
+}
+
+/// Processes finished sessions in the background, a few at a time. Transcription
+/// and summaries keep the CPU busy for a long while, so the rest queue behind
+/// `chronicle.pipeline.max_jobs` rather than starving music playback.
+#[derive(Clone)]
+pub struct Pipeline {
+    permits: Arc<Semaphore>,
+    /// Sessions queued or running, so one can't be processed twice at once
+    queued: Arc<Mutex<HashSet<i64>>>,
+}
+
+impl Pipeline {
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_jobs)),
+            queued: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Queues the session for processing and returns at once; the outcome is posted
+    /// in the session's text channel. The stages before `from` are left alone, `from`
+    /// itself always runs again, and any stage whose inputs changed since it last ran
+    /// does too.
+    pub async fn enqueue(
+        &self,
+        db_pool: SqlitePool,
+        config: ChronicleConfig,
+        http: Arc<Http>,
+        session_id: i64,
+        from: Option<Stage>,
+    ) -> Result<(), Error> {
+        if !self.queued.lock().unwrap_or_else(PoisonError::into_inner).insert(session_id) {
+            return Err(format!("Session #{} is already being processed.", session_id).into());
+        }
+        let slot = QueueSlot { queued: self.queued.clone(), session_id };
+
+        let stages: Vec<&str> = Stage::ALL
+            .iter()
+            .filter(|stage| from.is_none_or(|from| **stage >= from))
+            .map(Stage::as_str)
+            .collect();
+        queue_jobs(&db_pool, session_id, &stages).await?;
+
+        let permits = self.permits.clone();
+        tokio::spawn(async move {
+            let permit = permits.acquire_owned().await;
+            let result = process(&db_pool, &config, session_id, from).await;
+            drop(permit);
+            drop(slot);
+
+            if let Err(e) = report(&db_pool, &http, session_id, result).await {
+                warn!(session_id, error = %e, "Couldn't post the processing report");
+            }
+        });
+        Ok(())
+    }
+
+    /// Picks up the sessions a restart interrupted. Returns how many were queued.
+    pub async fn resume(&self, db_pool: &SqlitePool, config: &ChronicleConfig, http: Arc<Http>) -> Result<usize, Error> {
+        let sessions = fetch_unfinished_job_sessions(db_pool).await?;
+        for session_id in &sessions {
+            self.enqueue(db_pool.clone(), config.clone(), http.clone(), *session_id, None).await?;
+        }
+        Ok(sessions.len())
+    }
+}
+
+/// Runs the queued stages in order and stops at the first that fails. A run that
+/// can't start fails its queued stages, so they aren't retried on every restart.
+async fn process(
+    db_pool: &SqlitePool,
+    config: &ChronicleConfig,
+    session_id: i64,
+    from: Option<Stage>,
+) -> Result<RunReport, Error> {
+    let result = run_stages(db_pool, config, session_id, from).await;
+    if let Err(e) = &result
+        && let Err(mark) = fail_unfinished_jobs(db_pool, session_id, &e.to_string(), unix_now()).await
+    {
+        warn!(session_id, error = %mark, "Couldn't mark the queued stages failed");
+    }
+    result
+}
+
+async fn run_stages(
+    db_pool: &SqlitePool,
+    config: &ChronicleConfig,
+    session_id: i64,
+    from: Option<Stage>,
+) -> Result<RunReport, Error> {
+    let session = fetch_session(db_pool, session_id)
+        .await?
+        .ok_or_else(|| format!("Session #{} no longer exists", session_id))?;
+    let job = Job::load(db_pool, config, session, from).await?;
+    let previous: HashMap<String, _> = fetch_jobs(db_pool, session_id)
+        .await?
+        .into_iter()
+        .map(|row| (row.stage.clone(), row))
+        .collect();
+
+    info!(session_id, from = ?from, "Processing session");
+    let mut report = RunReport::default();
+    // Outputs of the stages so far; a change in any of them reruns the rest
+    let mut upstream: Vec<String> = Vec::new();
+    for stage in Stage::ALL {
+        let row = previous.get(stage.as_str());
+        if from.is_some_and(|from| stage < from) {
+            upstream.extend(row.and_then(|r| r.output_hash.clone()));
+            continue;
+        }
+
+        let (inputs, material) = match job.inputs(stage).await {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                finish_job(db_pool, session_id, stage.as_str(), JobStatus::Failed.as_str(), None, Some(&e.to_string()), unix_now()).await?;
+                report.failed = Some((stage, e.to_string()));
+                break;
+            }
+        };
+        let mut parts = vec![inputs.as_str(), material.as_str()];
+        parts.extend(upstream.iter().map(String::as_str));
+        let input_hash = format!("{:016x}", fnv1a(&parts));
+
+        // A stage that finished cleanly on the same inputs is kept as it is
+        let up_to_date = row.filter(|r| {
+            from != Some(stage)
+                && r.input_hash.as_deref() == Some(input_hash.as_str())
+                && r.error.is_none()
+                && r.finished_at.is_some()
+        });
+        if let Some(row) = up_to_date {
+            let status = if row.output_hash.is_some() { JobStatus::Done } else { JobStatus::Skipped };
+            finish_job(db_pool, session_id, stage.as_str(), status.as_str(), row.output_hash.as_deref(), None, row.finished_at.unwrap_or_default()).await?;
+            upstream.extend(row.output_hash.clone());
+            report.stages.push((stage, StageResult::UpToDate));
+            continue;
+        }
+
+        start_job(db_pool, session_id, stage.as_str(), &inputs, &input_hash, unix_now()).await?;
+        if from == Some(stage) {
+            clear_cache(&job.dir, stage.cache_steps());
+        }
+        match job.run(stage, from == Some(stage)).await {
+            Ok(output_hash) => {
+                let status = if output_hash.is_some() { JobStatus::Done } else { JobStatus::Skipped };
+                finish_job(db_pool, session_id, stage.as_str(), status.as_str(), output_hash.as_deref(), None, unix_now()).await?;
+                upstream.extend(output_hash);
+                report.stages.push((stage, if status == JobStatus::Done { StageResult::Ran } else { StageResult::Skipped }));
+            }
+            Err(e) => {
+                warn!(session_id, stage = stage.as_str(), error = %e, "Processing stage failed");
+                finish_job(db_pool, session_id, stage.as_str(), JobStatus::Failed.as_str(), None, Some(&e.to_string()), unix_now()).await?;
+                report.failed = Some((stage, e.to_string()));
+                break;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Everything about the session the stages share, loaded once per run.
+struct Job<'a> {
+    db_pool: &'a SqlitePool,
+    config: &'a ChronicleConfig,
+    session: SessionRow,
+    dir: PathBuf,
+    campaign: Option<CampaignRow>,
+    roster: Roster,
+    glossary: Option<Glossary>,
+}
+
+impl<'a> Job<'a> {
+    async fn load(
+        db_pool: &'a SqlitePool,
+        config: &'a ChronicleConfig,
+        session: SessionRow,
+        from: Option<Stage>,
+    ) -> Result<Self, Error> {
+        let dir = PathBuf::from(&session.storage_dir);
+        let campaign = match session.campaign_id {
+            Some(id) => fetch_campaign(db_pool, id).await?,
+            None => None,
+        };
+        let roster = Roster::load(db_pool, session.campaign_id).await?;
+        // The session's own entities and notes would otherwise change the glossary it was
+        // corrected with; only reprocessing from correction or earlier takes the current one
+        let saved = match from {
+            Some(from) if from <= Stage::Correct => None,
+            _ => Glossary::load_saved(&dir)?,
+        };
+        let glossary = match (&campaign, saved) {
+            (Some(_), Some(saved)) => Some(saved),
+            (Some(campaign), None) => Some(Glossary::load(db_pool, campaign, config.obsidian.vault.as_deref(), &roster).await?),
+            (None, _) => None,
+        };
+        Ok(Self {
+            db_pool,
+            config,
+            dir,
+            session,
+            campaign,
+            roster,
+            glossary,
+        })
+    }
+
+    /// What the stage would run with: a description for the job table, and
+    /// anything else that should rerun it when changed.
+    async fn inputs(&self, stage: Stage) -> Result<(String, String), Error> {
+        Ok(match stage {
+            Stage::Vad => {
+                let path = self.dir.join(MANIFEST_FILE);
+                let manifest = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                ("energy VAD".to_string(), manifest)
+            }
+            Stage::Transcribe => {
+                let transcriber = crate::ai::transcriber(&self.config.ai);
+                let whisper = &self.config.ai.whisper;
+                let model = whisper.model.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                // The glossary prompt is left out: it grows with every later session, and
+                // correction already picks up new names without transcribing again
+                (format!("{} · {} · {}", transcriber.name(), model, whisper.language), String::new())
+            }
+            Stage::Correct => {
+                let terms: Vec<&str> = self.glossary.iter().flat_map(|g| g.terms()).map(|t| t.text.as_str()).collect();
+                (format!("glossary of {} term(s)", terms.len()), terms.join("\n"))
+            }
+            Stage::Summarise => {
+                let context = SummaryContext::load(self.db_pool, self.config, &self.session).await?;
+                let prompts = fingerprints(&[PromptKind::ChunkSummary, PromptKind::SessionSummary, PromptKind::KeyEvents], &context)?;
+                let material = format!("{}\n{}", context.characters, context.previous_recap);
+                (format!("{} · {}", prompts, llm_label(self.config)), material)
+            }
+            Stage::Entities => {
+                let context = SummaryContext::load(self.db_pool, self.config, &self.session).await?;
+                let kinds = [PromptKind::Npcs, PromptKind::Locations, PromptKind::Loot, PromptKind::Quests];
+                let prompts = fingerprints(&kinds, &context)?;
+                (format!("{} · {}", prompts, llm_label(self.config)), context.character_names.join("\n"))
+            }
+            Stage::Export => match &self.config.obsidian.vault {
+                Some(vault) => (format!("{} · {}", vault.display(), self.config.archive_format.extension()), String::new()),
+                None => ("no vault".to_string(), String::new()),
+            },
+        })
+    }
+
+    /// Runs one stage. Returns the hash of what it wrote, or `None` if it had
+    /// nothing to do.
+    async fn run(&self, stage: Stage, forced: bool) -> Result<Option<String>, Error> {
+        match stage {
+            Stage::Vad => self.detect_speech(forced).await,
+            Stage::Transcribe => self.transcribe().await,
+            Stage::Correct => self.correct().await,
+            Stage::Summarise => self.summarise().await,
+            Stage::Entities => self.extract_entities().await,
+            Stage::Export => self.export().await,
+        }
+    }
+
+    /// Cuts the silence out of each speaker's track. Skipped when live
+    /// transcription already covered the whole session, unless asked for.
+    async fn detect_speech(&self, forced: bool) -> Result<Option<String>, Error> {
+        if !forced && load_completed(&self.dir)?.is_some() {
+            return Ok(None);
+        }
+
+        let manifest = RecordingManifest::load(&self.dir)?;
+        let tracks: Vec<(Option<u64>, PathBuf)> = manifest.speakers
+            .iter()
+            .map(|s| (s.user_id, self.dir.join(&s.file)))
+            .collect();
+        if let Some((_, missing)) = tracks.iter().find(|(_, path)| !path.is_file()) {
+            return Err(format!(
+                "{} is gone, most likely deleted by retention; reprocess from a later stage",
+                missing.display(),
+            ).into());
+        }
+
+        let prepared_dir = self.dir.join(PREPARED_DIR);
+        let prepared = tokio::task::spawn_blocking({
+            let prepared_dir = prepared_dir.clone();
+            move || -> Result<Vec<PreparedTrack>, Error> {
+                std::fs::create_dir_all(&prepared_dir)
+                    .map_err(|e| format!("Failed to create {}: {}", prepared_dir.display(), e))?;
+                let config = VadConfig::default();
+                let mut prepared = Vec::with_capacity(tracks.len());
+                for (speaker, path) in tracks {
+                    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                    let output = prepared_dir.join(&name);
+                    let audio = prepare_for_transcription(&path, &output, &config)?;
+                    debug!(track = name, speech_ms = audio.time_map.speech_ms(), original_ms = audio.original_ms, "Cut silences");
+                    if audio.is_silent() {
+                        std::fs::remove_file(&output).ok();
+                        continue;
+                    }
+                    prepared.push(PreparedTrack { speaker, audio: name, time_map: audio.time_map });
+                }
+                Ok(prepared)
+            }
+        })
+        .await
+        .map_err(|e| format!("Speech detection panicked: {}", e))??;
+
+        let json = serde_json::to_string_pretty(&prepared)?;
+        let path = prepared_dir.join(PREPARED_FILE);
+        std::fs::write(&path, &json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(Some(hash(&json)))
+    }
+
+    /// Transcribes the prepared tracks, or takes the live transcript if speech
+    /// detection was skipped for it.
+    async fn transcribe(&self) -> Result<Option<String>, Error> {
+        let prepared_dir = self.dir.join(PREPARED_DIR);
+        let prepared_path = prepared_dir.join(PREPARED_FILE);
+        let segments: Vec<Segment> = if prepared_path.is_file() && !self.vad_skipped().await? {
+            let content = std::fs::read_to_string(&prepared_path)
+                .map_err(|e| format!("Failed to read {}: {}", prepared_path.display(), e))?;
+            let prepared: Vec<PreparedTrack> = serde_json::from_str(&content)?;
+
+            let transcriber = crate::ai::transcriber(&self.config.ai);
+            let prompt = self.glossary.as_ref().and_then(Glossary::prompt);
+            let mut segments = Vec::new();
+            for track in prepared {
+                let request = TranscriptionRequest {
+                    audio: prepared_dir.join(&track.audio),
+                    speaker: track.speaker,
+                    offset_ms: 0,
+                    prompt: prompt.clone(),
+                };
+                let mut found = transcriber.transcribe(&request).await?;
+                track.time_map.remap(&mut found);
+                segments.extend(found);
+            }
+            segments
+        } else {
+            load_completed(&self.dir)?.ok_or_else(|| format!(
+                "There is neither prepared audio nor a complete {} to transcribe from; reprocess from speech detection",
+                LIVE_COMPLETE_FILE,
+            ))?
+        };
+
+        let json = serde_json::to_string_pretty(&segments)?;
+        let path = self.dir.join(SEGMENTS_FILE);
+        std::fs::write(&path, &json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(Some(hash(&json)))
+    }
+
+    /// Whether speech detection's latest run left the live transcript to be used.
+    async fn vad_skipped(&self) -> Result<bool, Error> {
+        let jobs = fetch_jobs(self.db_pool, self.session.id).await?;
+        Ok(jobs.iter().any(|j| j.stage == Stage::Vad.as_str() && j.status == JobStatus::Skipped.as_str()))
+    }
+
+    /// Builds the transcript, fixes glossary names in it, saves every format and
+    /// refreshes the search index.
+    async fn correct(&self) -> Result<Option<String>, Error> {
+        let path = self.dir.join(SEGMENTS_FILE);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let segments: Vec<Segment> = serde_json::from_str(&content)?;
+
+        let mut transcript = Transcript::from_segments(self.session.id, segments, &self.roster);
+        let corrections = match &self.glossary {
+            Some(glossary) => {
+                glossary.save(&self.dir)?;
+                glossary.correct(&mut transcript)
+            }
+            None => Vec::new(),
+        };
+        save_corrections(&self.dir, &corrections)?;
+        let path = transcript.save(&self.dir)?;
+        search::index(self.db_pool, &self.session, &transcript).await?;
+        file_hash(&path).map(Some)
+    }
+
+    async fn summarise(&self) -> Result<Option<String>, Error> {
+        let transcript = Transcript::load(&Transcript::path(&self.dir), self.session.id, &self.roster)?;
+        if transcript.entries.is_empty() {
+            return Err("The transcript is empty; there's nothing to summarise".into());
+        }
+        let context = SummaryContext::load(self.db_pool, self.config, &self.session).await?;
+        Processor::new(crate::ai::llm(&self.config.ai))
+            .summarise(&transcript, &context, &self.dir)
+            .await?;
+        file_hash(&self.dir.join(SUMMARY_FILE)).map(Some)
+    }
+
+    async fn extract_entities(&self) -> Result<Option<String>, Error> {
+        let summary = SessionSummary::load(&self.dir)?;
+        let context = SummaryContext::load(self.db_pool, self.config, &self.session).await?;
+        let entities = Processor::new(crate::ai::llm(&self.config.ai))
+            .extract_entities(&summary, &context, &self.dir)
+            .await?;
+        entities::record(self.db_pool, &self.session, &entities, unix_now()).await?;
+        file_hash(&self.dir.join(ENTITIES_FILE)).map(Some)
+    }
+
+    /// Writes the session note, with the mixdown embedded when audio is kept.
+    /// Skipped without a vault or for a session outside any campaign.
+    async fn export(&self) -> Result<Option<String>, Error> {
+        let (Some(vault), Some(campaign)) = (self.config.obsidian.vault.as_deref(), &self.campaign) else {
+            return Ok(None);
+        };
+
+        let summary = SessionSummary::load(&self.dir)?;
+        let entities = SessionEntities::load(&self.dir)?;
+        let manifest = RecordingManifest::load(&self.dir)?;
+        let participant_ids = fetch_session_participants(self.db_pool, self.session.id).await?;
+
+        let title = session_title(&self.session);
+        let mut attachments = Vec::new();
+        if self.config.retain_audio {
+            let archive = archive_path(vault, &campaign.obsidian_folder, &title, self.config.archive_format);
+            // Once retention has deleted the tracks, the archive from before is kept
+            let tracks_kept = !manifest.speakers.is_empty()
+                && manifest.speakers.iter().all(|s| self.dir.join(&s.file).is_file());
+            if tracks_kept {
+                mix_session(&self.dir, &manifest, Some(&summary), &title, &archive, self.config).await?;
+            }
+            if archive.is_file() {
+                attachments.push(note_embed(&archive));
+            }
+        }
+
+        let note = SessionNote {
+            campaign,
+            session: &self.session,
+            summary: &summary,
+            participants: participant_ids.iter().map(|id| self.roster.speaker_name(Some(*id))).collect(),
+            duration_secs: manifest.duration_secs(),
+            links: NoteLinks::from_roster(&self.roster, &participant_ids).with_entities(&entities),
+            attachments,
+        };
+        let path = export_session(vault, &note)?;
+        file_hash(&path).map(Some)
+    }
+}
+
+/// Posts how processing went in the channel the session was started from.
+async fn report(db_pool: &SqlitePool, http: &Http, session_id: i64, result: Result<RunReport, Error>) -> Result<(), Error> {
+    let Some(session) = fetch_session(db_pool, session_id).await? else {
+        return Ok(());
+    };
+    let label = match session.session_number {
+        Some(number) => format!("session {}", number),
+        None => format!("session #{}", session.id),
+    };
+
+    let message = match result {
+        Ok(RunReport { stages, failed: None }) => {
+            let ran: Vec<&str> = stages.iter().filter(|(_, r)| *r == StageResult::Ran).map(|(s, _)| s.as_str()).collect();
+            if ran.is_empty() {
+                format!("📚 Processing {}: everything was already up to date.", label)
+            } else {
+                format!("📚 Processed {}: ran {}.", label, ran.join(", "))
+            }
+        }
+        Ok(RunReport { failed: Some((stage, error)), .. }) => format!(
+            "⚠️ Processing {} stopped at **{}**: {}\nFix it and run `/chronicle reprocess {}`.",
+            label,
+            stage.as_str(),
+            truncate(&error),
+            session.id,
+        ),
+        Err(e) => format!("⚠️ Processing {} couldn't start: {}", label, truncate(&e.to_string())),
+    };
+    ChannelId::new(session.text_channel_id as u64).say(http, message).await?;
+    Ok(())
+}
+
+/// Tool output in an error, e.g. ffmpeg's, can run past Discord's message limit.
+fn truncate(error: &str) -> String {
+    if error.chars().count() <= REPORT_ERROR_MAX_CHARS {
+        return error.to_string();
+    }
+    error.chars().take(REPORT_ERROR_MAX_CHARS - 1).collect::<String>() + "…"
+}
+
+/// Prompt versions, as in `chunk-summary-v2-…`, joined for the job table.
+fn fingerprints(kinds: &[PromptKind], context: &SummaryContext) -> Result<String, Error> {
+    let mut fingerprints = Vec::with_capacity(kinds.len());
+    for kind in kinds {
+        fingerprints.push(PromptTemplate::load(*kind, context.prompt_dir.as_deref())?.fingerprint());
+    }
+    Ok(fingerprints.join(", "))
+}
+
+/// The model as configured; swapping the one llama-server loads without changing
+/// the config isn't visible here, which is what `from_stage` is for.
+fn llm_label(config: &ChronicleConfig) -> String {
+    let name = crate::ai::llm(&config.ai).name();
+    match (&config.ai.llm, &config.ai.llama.model) {
+        (LlmKind::LlamaCpp, Some(model)) => format!("{} ({})", name, model),
+        _ => name.to_string(),
+    }
+}
+
+fn clear_cache(session_dir: &Path, steps: &[&str]) {
+    if steps.is_empty() {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(session_dir.join(CACHE_DIR)) else { return };
+    for entry in entries.filter_map(Result::ok) {
+        if steps.iter().any(|step| entry.file_name().to_string_lossy().starts_with(step)) {
+            std::fs::remove_file(entry.path()).ok();
+        }
+    }
+}
+
+fn hash(content: &str) -> String {
+    format!("{:016x}", fnv1a(&[content]))
+}
+
+fn file_hash(path: &Path) -> Result<String, Error> {
+    let content = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(hash(&String::from_utf8_lossy(&content)))
+}
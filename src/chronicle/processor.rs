@@ -0,0 +1,760 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tracing::{debug, info, warn};
+
+use crate::ai::llm::{CompletionRequest, Llm};
+use crate::ai::prompts::{fnv1a, override_dir, PromptKind, PromptTemplate, PromptVars, RenderedPrompt};
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::config::ChronicleConfig;
+use crate::chronicle::entities::{parse_json_array, SessionEntities};
+use crate::chronicle::transcript::{clock, Transcript, TranscriptEntry};
+use crate::db::chronicle::{fetch_campaign, fetch_campaign_sessions, SessionRow};
+use crate::definitions::Error;
+
+pub const SUMMARY_FILE: &str = "summary.json";
+pub const SUMMARY_MARKDOWN_FILE: &str = "summary.md";
+pub const CACHE_DIR: &str = "cache";
+
+/// Share of the prompt budget given to transcript text; the rest absorbs the
+/// error in estimating tokens from characters.
+const CHUNK_BUDGET_SHARE: f32 = 0.75;
+/// Share of each chunk repeated at the start of the next, so a scene that straddles
+/// a boundary is seen whole at least once.
+const CHUNK_OVERLAP_SHARE: f32 = 0.1;
+/// Conservative characters-per-token for English prose with names and punctuation.
+const CHARS_PER_TOKEN: usize = 3;
+
+/// A slice of the transcript sent to the model in one request.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub index: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub text: String,
+}
+
+/// One scene of the final summary. The start time comes from the `[hh:mm:ss]` the
+/// model is asked to begin each scene with, when it complied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub start_ms: Option<u64>,
+    pub text: String,
+}
+
+/// The structured session summary, parsed from the model's Markdown.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: i64,
+    pub overview: String,
+    pub scenes: Vec<Scene>,
+    pub decisions: Vec<String>,
+    pub combat: Vec<String>,
+    pub loot: Vec<String>,
+    pub open_threads: Vec<String>,
+    /// The turning points of the session, one line each, from a second pass over
+    /// the summary
+    #[serde(default)]
+    pub key_events: Vec<String>,
+    pub markdown: String,
+    /// Prompt fingerprint and model that produced it
+    pub produced_by: String,
+}
+
+impl SessionSummary {
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(SUMMARY_FILE);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        std::fs::write(dir.join(SUMMARY_FILE), serde_json::to_vec_pretty(self)?)?;
+        std::fs::write(dir.join(SUMMARY_MARKDOWN_FILE), &self.markdown)?;
+        Ok(())
+    }
+
+    /// How the session ended, for the next session's context.
+    pub fn ending(&self) -> String {
+        let mut ending = self.overview.clone();
+        if !self.open_threads.is_empty() {
+            ending.push_str("\nOpen threads:\n");
+            for thread in &self.open_threads {
+                ending.push_str(&format!("- {}\n", thread));
+            }
+        }
+        ending
+    }
+
+    fn parse(session_id: i64, markdown: &str, produced_by: String) -> Self {
+        let mut summary = Self {
+            session_id,
+            markdown: markdown.trim().to_string(),
+            produced_by,
+            ..Default::default()
+        };
+
+        for (heading, body) in markdown_sections(markdown) {
+            match heading.to_lowercase().as_str() {
+                "overview" => summary.overview = body.trim().to_string(),
+                "scenes" => summary.scenes = paragraphs(&body).into_iter().map(parse_scene).collect(),
+                "decisions" => summary.decisions = bullets(&body),
+                "combat" => summary.combat = bullets(&body),
+                "loot" => summary.loot = bullets(&body),
+                "open threads" => summary.open_threads = bullets(&body),
+                _ => {}
+            }
+        }
+        summary
+    }
+}
+
+/// Everything about the session the prompts need besides the transcript.
+#[derive(Clone, Debug)]
+pub struct SummaryContext {
+    pub session_id: i64,
+    pub session_number: String,
+    pub campaign: String,
+    pub characters: String,
+    /// The player characters' names, which are never NPCs
+    pub character_names: Vec<String>,
+    pub previous_recap: String,
+    pub prompt_dir: Option<PathBuf>,
+}
+
+impl SummaryContext {
+    pub async fn load(
+        db_pool: &SqlitePool,
+        config: &ChronicleConfig,
+        session: &SessionRow,
+    ) -> Result<Self, Error> {
+        let campaign = match session.campaign_id {
+            Some(id) => fetch_campaign(db_pool, id).await?,
+            None => None,
+        };
+        let roster = Roster::load(db_pool, session.campaign_id).await?;
+
+        let mut characters: Vec<String> = roster.characters().map(|c| format!("- {}", c.describe())).collect();
+        characters.sort();
+
+        // The latest earlier session with a summary, skipping any that failed
+        let mut previous_recap = None;
+        if let (Some(campaign_id), Some(number)) = (session.campaign_id, session.session_number) {
+            let sessions = fetch_campaign_sessions(db_pool, campaign_id).await?;
+            previous_recap = sessions
+                .iter()
+                .rev()
+                .filter(|s| s.session_number.is_some_and(|n| n < number))
+                .find_map(|s| SessionSummary::load(Path::new(&s.storage_dir)).ok())
+                .map(|summary| summary.ending());
+        }
+
+        Ok(Self {
+            session_id: session.id,
+            session_number: session.session_number
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| format!("#{}", session.id)),
+            campaign: campaign.as_ref().map(|c| c.name.clone()).unwrap_or_else(|| "Untitled campaign".to_string()),
+            characters: if characters.is_empty() {
+                "(no characters recorded; speakers are named as players)".to_string()
+            } else {
+                characters.join("\n")
+            },
+            character_names: roster.characters().map(|c| c.name.clone()).collect(),
+            previous_recap: previous_recap.unwrap_or_else(|| "(this is the first recorded session)".to_string()),
+            prompt_dir: campaign.and_then(|c| override_dir(config.obsidian.vault.as_deref(), &c.obsidian_folder)),
+        })
+    }
+
+    fn vars(&self) -> PromptVars {
+        PromptVars::new()
+            .set("campaign", self.campaign.clone())
+            .set("characters", self.characters.clone())
+            .set("session_number", self.session_number.clone())
+            .set("previous_recap", self.previous_recap.clone())
+    }
+}
+
+/// Summarises transcripts too long for one request: each overlapping chunk is
+/// summarised on its own (map), then the partial notes are combined, in rounds if
+/// they are still too long, into the final summary (reduce).
+///
+/// Every model response is cached under the session's `cache/` folder, keyed by the
+/// prompt, its input and the model, so a crashed run picks up where it stopped.
+pub struct Processor {
+    llm: Arc<dyn Llm>,
+}
+
+impl Processor {
+    pub fn new(llm: Arc<dyn Llm>) -> Self {
+        Self { llm }
+    }
+
+    pub async fn summarise(
+        &self,
+        transcript: &Transcript,
+        context: &SummaryContext,
+        session_dir: &Path,
+    ) -> Result<SessionSummary, Error> {
+        let cache_dir = session_dir.join(CACHE_DIR);
+        std::fs::create_dir_all(&cache_dir)
+            .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+
+        let chunk_prompt = PromptTemplate::load(PromptKind::ChunkSummary, context.prompt_dir.as_deref())?;
+        let reduce_prompt = PromptTemplate::load(PromptKind::SessionSummary, context.prompt_dir.as_deref())?;
+
+        // Map
+        let budget = self.text_budget(&chunk_prompt, context).await?;
+        let chunks = chunk_transcript(&transcript.entries, budget);
+        info!(session_id = context.session_id, chunks = chunks.len(), "Summarising transcript");
+
+        let mut partials = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            let vars = context.vars()
+                .set("chunk_number", (chunk.index + 1).to_string())
+                .set("chunk_count", chunks.len().to_string())
+                .set("transcript", chunk.text.clone());
+            let notes = self.complete_cached(&chunk_prompt, &vars, &cache_dir, &format!("map-{}", chunk.index)).await?;
+            partials.push(format!("Part {} ({} to {}):\n{}", chunk.index + 1, clock(chunk.start_ms), clock(chunk.end_ms), notes.trim()));
+        }
+
+        // Reduce, in as many rounds as it takes for the notes to fit one request
+        let budget = self.text_budget(&reduce_prompt, context).await?;
+        let mut round = 0;
+        let markdown = loop {
+            let groups = group_to_budget(&partials, budget);
+            if groups.len() == 1 {
+                let vars = context.vars().set("partial_summaries", groups[0].clone());
+                break self.complete_cached(&reduce_prompt, &vars, &cache_dir, "reduce-final").await?;
+            }
+            if groups.len() >= partials.len() {
+                return Err("Partial summaries are too long to combine; lower `max_tokens` or use a model with a larger context window".into());
+            }
+
+            debug!(round, groups = groups.len(), "Combining partial summaries");
+            let mut combined = Vec::with_capacity(groups.len());
+            for (index, group) in groups.iter().enumerate() {
+                let vars = context.vars().set("partial_summaries", group.clone());
+                let key = format!("reduce-{}-{}", round, index);
+                combined.push(self.complete_cached(&reduce_prompt, &vars, &cache_dir, &key).await?);
+            }
+            partials = combined;
+            round += 1;
+        };
+
+        let key_events_prompt = PromptTemplate::load(PromptKind::KeyEvents, context.prompt_dir.as_deref())?;
+        let produced_by = format!(
+            "{}, {} · {}",
+            reduce_prompt.fingerprint(),
+            key_events_prompt.fingerprint(),
+            self.llm.name(),
+        );
+        let mut summary = SessionSummary::parse(context.session_id, &markdown, produced_by);
+
+        let vars = context.vars().set("summary", summary.markdown.clone());
+        let events = self.complete_cached(&key_events_prompt, &vars, &cache_dir, "key-events").await?;
+        summary.key_events = bullets(&events);
+        if !summary.key_events.is_empty() {
+            summary.markdown.push_str("\n\n## Key events\n");
+            for event in &summary.key_events {
+                summary.markdown.push_str(&format!("- {}\n", event));
+            }
+        }
+        summary.save(session_dir)?;
+        Ok(summary)
+    }
+
+    /// A "previously on" read from earlier summaries, given oldest first with a
+    /// label each. Whole summaries are sent when they fit; otherwise only how each
+    /// session ended.
+    pub async fn recap(&self, context: &SummaryContext, summaries: &[(String, SessionSummary)]) -> Result<String, Error> {
+        let template = PromptTemplate::load(PromptKind::Recap, context.prompt_dir.as_deref())?;
+        let join = |part: fn(&SessionSummary) -> String| {
+            summaries
+                .iter()
+                .map(|(label, summary)| format!("{}:\n{}", label, part(summary).trim()))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+        let vars = context.vars().set("session_count", summaries.len().to_string());
+
+        let mut rendered = template.render(&vars.clone().set("summaries", join(|s| s.markdown.clone())))?;
+        let tokens = self.llm.count_tokens(&rendered.system).await? + self.llm.count_tokens(&rendered.user).await?;
+        if tokens > self.llm.prompt_budget(None) {
+            debug!(tokens, "Full summaries don't fit; recapping from session endings");
+            rendered = template.render(&vars.set("summaries", join(SessionSummary::ending)))?;
+        }
+
+        let request = CompletionRequest::new(Some(rendered.system), rendered.user);
+        let recap = self.llm.complete(&request).await?;
+        Ok(recap.trim().to_string())
+    }
+
+    /// Pulls the NPCs, locations, items and quests out of a summary, one prompt
+    /// each, and saves them next to it.
+    pub async fn extract_entities(
+        &self,
+        summary: &SessionSummary,
+        context: &SummaryContext,
+        session_dir: &Path,
+    ) -> Result<SessionEntities, Error> {
+        let cache_dir = session_dir.join(CACHE_DIR);
+        std::fs::create_dir_all(&cache_dir)
+            .map_err(|e| format!("Failed to create {}: {}", cache_dir.display(), e))?;
+
+        let vars = context.vars().set("summary", summary.markdown.clone());
+        let mut fingerprints = Vec::new();
+        let npcs = self.complete_json(PromptKind::Npcs, &vars, context, &cache_dir, &mut fingerprints).await?;
+        let locations = self.complete_json(PromptKind::Locations, &vars, context, &cache_dir, &mut fingerprints).await?;
+        let items = self.complete_json(PromptKind::Loot, &vars, context, &cache_dir, &mut fingerprints).await?;
+        let quests = self.complete_json(PromptKind::Quests, &vars, context, &cache_dir, &mut fingerprints).await?;
+
+        let produced_by = format!("{} · {}", fingerprints.join(", "), self.llm.name());
+        let entities = SessionEntities::validate(
+            context.session_id,
+            npcs,
+            locations,
+            items,
+            quests,
+            &context.character_names,
+            produced_by,
+        );
+        entities.save(session_dir)?;
+        info!(
+            session_id = context.session_id,
+            npcs = entities.npcs.len(),
+            locations = entities.locations.len(),
+            items = entities.items.len(),
+            quests = entities.quests.len(),
+            "Extracted entities",
+        );
+        Ok(entities)
+    }
+
+    /// A JSON array from the model. A reply that isn't one is sent back once with
+    /// the parse error, which small local models usually fix.
+    async fn complete_json<T: DeserializeOwned>(
+        &self,
+        kind: PromptKind,
+        vars: &PromptVars,
+        context: &SummaryContext,
+        cache_dir: &Path,
+        fingerprints: &mut Vec<String>,
+    ) -> Result<Vec<T>, Error> {
+        let template = PromptTemplate::load(kind, context.prompt_dir.as_deref())?;
+        fingerprints.push(template.fingerprint());
+        let step = format!("entities-{}", kind.name());
+
+        let mut rendered = template.render(vars)?;
+        let reply = self.complete_rendered(&template, rendered.clone(), cache_dir, &step).await?;
+        let error = match parse_json_array(&reply) {
+            Ok(entries) => return Ok(entries),
+            Err(e) => e,
+        };
+
+        warn!(step, error = %error, "Model reply wasn't valid JSON; asking again");
+        rendered.user.push_str(&format!(
+            "\n\nYour previous reply could not be used because {}. Reply with the JSON array only.",
+            error,
+        ));
+        let reply = self.complete_rendered(&template, rendered, cache_dir, &format!("{step}-retry")).await?;
+        parse_json_array(&reply)
+            .map_err(|e| format!("The model's `{}` reply is unusable: {}", kind.name(), e).into())
+    }
+
+    /// Characters of transcript or notes that fit beside the template in one request.
+    async fn text_budget(&self, template: &PromptTemplate, context: &SummaryContext) -> Result<usize, Error> {
+        let vars = context.vars()
+            .set("chunk_number", "0")
+            .set("chunk_count", "0")
+            .set("transcript", "")
+            .set("partial_summaries", "");
+        let rendered = template.render(&vars)?;
+        let overhead = self.llm.count_tokens(&rendered.system).await? + self.llm.count_tokens(&rendered.user).await?;
+
+        let tokens = self.llm.prompt_budget(None).saturating_sub(overhead);
+        let chars = (tokens as f32 * CHUNK_BUDGET_SHARE) as usize * CHARS_PER_TOKEN;
+        if chars < 1_000 {
+            return Err(format!(
+                "The {}-token context window leaves no room for transcript text; raise `context_window`",
+                self.llm.context_window(),
+            ).into());
+        }
+        Ok(chars)
+    }
+
+    async fn complete_cached(
+        &self,
+        template: &PromptTemplate,
+        vars: &PromptVars,
+        cache_dir: &Path,
+        step: &str,
+    ) -> Result<String, Error> {
+        self.complete_rendered(template, template.render(vars)?, cache_dir, step).await
+    }
+
+    async fn complete_rendered(
+        &self,
+        template: &PromptTemplate,
+        rendered: RenderedPrompt,
+        cache_dir: &Path,
+        step: &str,
+    ) -> Result<String, Error> {
+        let key = fnv1a(&[&template.fingerprint(), self.llm.name(), &rendered.system, &rendered.user]);
+        let path = cache_dir.join(format!("{step}-{key:016x}.md"));
+
+        if let Ok(cached) = std::fs::read_to_string(&path) {
+            debug!(step, "Using cached model output");
+            return Ok(cached);
+        }
+
+        let request = CompletionRequest::new(Some(rendered.system), rendered.user);
+        let output = self.llm.complete(&request).await?;
+
+        // Written whole then renamed, so a crash never leaves a truncated entry
+        let partial = path.with_extension("md.partial");
+        std::fs::write(&partial, &output)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(output)
+    }
+}
+
+/// Splits the transcript at line boundaries into chunks of at most `budget`
+/// characters, each starting with the tail of the one before.
+pub fn chunk_transcript(entries: &[TranscriptEntry], budget: usize) -> Vec<Chunk> {
+    let lines: Vec<(u64, u64, String)> = entries
+        .iter()
+        .flat_map(|entry| split_entry(entry, budget))
+        .collect();
+    let overlap = (budget as f32 * CHUNK_OVERLAP_SHARE) as usize;
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start;
+        let mut size = 0;
+        while end < lines.len() && (end == start || size + lines[end].2.len() < budget) {
+            size += lines[end].2.len() + 1;
+            end += 1;
+        }
+
+        chunks.push(Chunk {
+            index: chunks.len(),
+            start_ms: lines[start].0,
+            end_ms: lines[end - 1].1,
+            text: lines[start..end].iter().map(|l| l.2.as_str()).collect::<Vec<_>>().join("\n"),
+        });
+        if end == lines.len() {
+            break;
+        }
+
+        // Step back over whole lines to repeat up to `overlap` characters
+        let mut next = end;
+        let mut repeated = 0;
+        while next > start + 1 && repeated + lines[next - 1].2.len() <= overlap {
+            next -= 1;
+            repeated += lines[next].2.len() + 1;
+        }
+        start = next;
+    }
+    chunks
+}
+
+/// One transcript line, or several if a single speaker ran on longer than a chunk.
+fn split_entry(entry: &TranscriptEntry, budget: usize) -> Vec<(u64, u64, String)> {
+    let prefix = format!("[{}] {}: ", clock(entry.start_ms), entry.speaker_name);
+    if prefix.len() + entry.text.len() <= budget {
+        return vec![(entry.start_ms, entry.end_ms, format!("{}{}", prefix, entry.text))];
+    }
+
+    let room = budget.saturating_sub(prefix.len()).max(200);
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for sentence in entry.text.split_inclusive(['.', '!', '?']) {
+        if !current.is_empty() && current.len() + sentence.len() > room {
+            pieces.push(format!("{}{}", prefix, current.trim()));
+            current.clear();
+        }
+        current.push_str(sentence);
+    }
+    if !current.trim().is_empty() {
+        pieces.push(format!("{}{}", prefix, current.trim()));
+    }
+    pieces.into_iter().map(|p| (entry.start_ms, entry.end_ms, p)).collect()
+}
+
+/// Packs notes, in order, into as few groups of at most `budget` characters as possible.
+fn group_to_budget(notes: &[String], budget: usize) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for note in notes {
+        if !current.is_empty() && current.len() + note.len() + 2 > budget {
+            groups.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(note);
+    }
+    if !current.is_empty() {
+        groups.push(current);
+    }
+    groups
+}
+
+/// `## Heading` sections of a Markdown document.
+fn markdown_sections(markdown: &str) -> Vec<(String, String)> {
+    let mut sections: Vec<(String, String)> = Vec::new();
+    for line in markdown.lines() {
+        if let Some(heading) = line.trim().strip_prefix("## ") {
+            sections.push((heading.trim().trim_matches(['#', ':']).trim().to_string(), String::new()));
+        } else if let Some((_, body)) = sections.last_mut() {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    sections
+}
+
+fn bullets(body: &str) -> Vec<String> {
+    body.lines()
+        .map(str::trim)
+        .filter_map(|l| l.strip_prefix("- ").or_else(|| l.strip_prefix("* ")))
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty() && !l.eq_ignore_ascii_case("none."))
+        .collect()
+}
+
+fn paragraphs(body: &str) -> Vec<String> {
+    body.split("\n\n")
+        .map(|p| p.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+        .map(|p| p.trim_start_matches(['-', '*']).trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Reads a leading `[hh:mm:ss]` off a scene paragraph.
+fn parse_scene(paragraph: String) -> Scene {
+    let parsed = paragraph
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .and_then(|(time, text)| {
+            let mut parts = time.trim().split(':').map(|p| p.parse::<u64>().ok());
+            let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+            Some(((h * 3600 + m * 60 + s) * 1000, text.trim().to_string()))
+        });
+
+    match parsed {
+        Some((start_ms, text)) => Scene { start_ms: Some(start_ms), text },
+        None => Scene { start_ms: None, text: paragraph },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::llm::MockLlm;
+
+    /// A line of exactly 50 characters once prefixed with `[hh:mm:ss] A: `.
+    fn entry(index: u64) -> TranscriptEntry {
+        TranscriptEntry {
+            start_ms: index * 1_000,
+            end_ms: index * 1_000 + 900,
+            speaker: Some(1),
+            speaker_name: "A".to_string(),
+            text: format!("line {:03} {}", index, "x".repeat(27)),
+            confidence: 1.0,
+        }
+    }
+
+    fn context(session_id: i64) -> SummaryContext {
+        SummaryContext {
+            session_id,
+            session_number: "3".to_string(),
+            campaign: "Test campaign".to_string(),
+            characters: "- Thalia the bard".to_string(),
+            character_names: vec!["Thalia".to_string()],
+            previous_recap: "(this is the first recorded session)".to_string(),
+            prompt_dir: None,
+        }
+    }
+
+    fn temp_session_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chester-processor-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn chunks_fill_the_budget_and_overlap_by_whole_lines() {
+        let entries: Vec<TranscriptEntry> = (0..40).map(entry).collect();
+        let chunks = chunk_transcript(&entries, 1_000);
+
+        // 19 lines of 50 characters and their newlines fit in 1000, a 20th doesn't
+        let lines: Vec<Vec<&str>> = chunks.iter().map(|c| c.text.lines().collect()).collect();
+        assert_eq!(lines[0].len(), 19);
+        for chunk in &chunks {
+            assert!(chunk.text.len() <= 1_000);
+        }
+        // One line fits in the 100-character overlap, so each chunk repeats the last line of the one before
+        for pair in lines.windows(2) {
+            assert_eq!(pair[1][0], *pair[0].last().unwrap());
+        }
+        assert_eq!(chunks[1].start_ms, entries[18].start_ms);
+        assert_eq!(chunks.last().unwrap().end_ms, entries[39].end_ms);
+        assert_eq!(chunks.iter().map(|c| c.index).collect::<Vec<_>>(), (0..chunks.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn chunking_always_moves_forward() {
+        // No room to overlap: consecutive chunks share nothing but must still advance
+        let entries: Vec<TranscriptEntry> = (0..10).map(entry).collect();
+        let chunks = chunk_transcript(&entries, 60);
+        assert_eq!(chunks.len(), 10);
+        assert!(chunks.iter().all(|c| c.text.lines().count() == 1));
+    }
+
+    #[test]
+    fn oversized_entries_are_split_at_sentences() {
+        let sentence = "The party argued about the map for a long while. ";
+        let long = TranscriptEntry {
+            text: sentence.repeat(40).trim().to_string(),
+            ..entry(0)
+        };
+        let pieces = split_entry(&long, 500);
+
+        assert!(pieces.len() > 1);
+        for (start_ms, _, text) in &pieces {
+            assert_eq!(*start_ms, 0);
+            assert!(text.starts_with("[00:00:00] A: The party"));
+            assert!(text.len() <= 500);
+        }
+        let words: usize = pieces.iter().map(|(_, _, t)| t.split_whitespace().count() - 2).sum();
+        assert_eq!(words, long.text.split_whitespace().count());
+
+        // Short entries are left whole
+        assert_eq!(split_entry(&entry(1), 500).len(), 1);
+    }
+
+    #[test]
+    fn notes_are_grouped_in_order_up_to_the_budget() {
+        let notes: Vec<String> = ["a".repeat(40), "b".repeat(40), "c".repeat(40)].into();
+        let groups = group_to_budget(&notes, 90);
+        assert_eq!(groups, vec![format!("{}\n\n{}", notes[0], notes[1]), notes[2].clone()]);
+
+        // Notes that are each over budget can't be combined, so a reduce round wouldn't shrink them
+        let oversized = group_to_budget(&notes, 30);
+        assert_eq!(oversized.len(), notes.len());
+    }
+
+    #[test]
+    fn summary_markdown_is_parsed_into_sections() {
+        let markdown = "\
+## Overview
+The party reached Waterdeep.
+
+## Scenes
+[00:01:05] They met Volo at the Yawning Portal.
+
+- [1:02:00] A fight broke out.
+
+No time given here.
+
+## Decisions
+- Took the job
+* Trusted Volo
+
+## Combat
+None.
+
+## Loot:
+- 50 gp from Volo
+
+## Open Threads
+- Who hired the thugs?
+";
+        let summary = SessionSummary::parse(7, markdown, "test".to_string());
+
+        assert_eq!(summary.overview, "The party reached Waterdeep.");
+        assert_eq!(summary.scenes, vec![
+            Scene { start_ms: Some(65_000), text: "They met Volo at the Yawning Portal.".to_string() },
+            Scene { start_ms: Some(3_720_000), text: "A fight broke out.".to_string() },
+            Scene { start_ms: None, text: "No time given here.".to_string() },
+        ]);
+        assert_eq!(summary.decisions, vec!["Took the job", "Trusted Volo"]);
+        assert!(summary.combat.is_empty());
+        assert_eq!(summary.loot, vec!["50 gp from Volo"]);
+        assert_eq!(summary.open_threads, vec!["Who hired the thugs?"]);
+        assert_eq!(markdown_sections("no headings at all"), Vec::new());
+    }
+
+    #[tokio::test]
+    async fn map_reduce_resumes_from_cached_responses() {
+        let dir = temp_session_dir("resume");
+        let transcript = Transcript { version: 1, session_id: 7, entries: (0..400).map(entry).collect() };
+        let context = context(7);
+        let final_summary = "## Overview\nThe party reached Waterdeep.\n\n## Open threads\n- Who hired the thugs?";
+
+        let llm = Arc::new(MockLlm::new(2_000, 100));
+        let processor = Processor::new(llm.clone());
+        let chunk_prompt = PromptTemplate::load(PromptKind::ChunkSummary, None).unwrap();
+        let chunks = chunk_transcript(&transcript.entries, processor.text_budget(&chunk_prompt, &context).await.unwrap());
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            llm.push_response(format!("- notes on part {}", chunk.index + 1));
+        }
+        llm.push_response(final_summary);
+        llm.push_response("- They reached Waterdeep");
+
+        let summary = processor.summarise(&transcript, &context, &dir).await.unwrap();
+        assert_eq!(llm.requests().len(), chunks.len() + 2);
+        assert_eq!(summary.overview, "The party reached Waterdeep.");
+        assert_eq!(summary.key_events, vec!["They reached Waterdeep"]);
+        assert!(summary.markdown.ends_with("## Key events\n- They reached Waterdeep\n"));
+        assert_eq!(SessionSummary::load(&dir).unwrap(), summary);
+
+        // A run cut short after the map: only the reduce and key events are asked again
+        for cached in std::fs::read_dir(dir.join(CACHE_DIR)).unwrap() {
+            let path = cached.unwrap().path();
+            if !path.file_name().unwrap().to_string_lossy().starts_with("map-") {
+                std::fs::remove_file(path).unwrap();
+            }
+        }
+        let llm = Arc::new(MockLlm::new(2_000, 100));
+        llm.push_response(final_summary);
+        llm.push_response("- They reached Waterdeep");
+        let resumed = Processor::new(llm.clone()).summarise(&transcript, &context, &dir).await.unwrap();
+
+        let requests = llm.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].prompt.contains("- notes on part 1"));
+        assert_eq!(resumed, summary);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reduce_fails_when_partials_cannot_shrink() {
+        let dir = temp_session_dir("no-shrink");
+        let transcript = Transcript { version: 1, session_id: 8, entries: (0..400).map(entry).collect() };
+        let context = context(8);
+
+        let llm = Arc::new(MockLlm::new(2_000, 100));
+        let processor = Processor::new(llm.clone());
+        let chunk_prompt = PromptTemplate::load(PromptKind::ChunkSummary, None).unwrap();
+        let budget = processor.text_budget(&chunk_prompt, &context).await.unwrap();
+        for _ in chunk_transcript(&transcript.entries, budget) {
+            llm.push_response("word ".repeat(budget));
+        }
+
+        let error = processor.summarise(&transcript, &context, &dir).await.unwrap_err();
+        assert!(error.to_string().contains("too long to combine"), "{error}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
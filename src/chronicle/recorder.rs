@@ -0,0 +1,544 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use poise::serenity_prelude::async_trait;
+use serde::{Deserialize, Serialize};
+use songbird::driver::{Channels, DecodeConfig, DecodeMode, SampleRate};
+use songbird::{Call, CoreEvent, Event, EventContext, EventHandler};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{debug, warn};
+
+use crate::definitions::Error;
+
+/// Songbird decodes received voice to 48kHz; the recorder asks for mono.
+pub const SAMPLE_RATE: u32 = 48_000;
+/// One `VoiceTick` covers 20ms of audio.
+pub const SAMPLES_PER_TICK: u64 = (SAMPLE_RATE / 50) as u64;
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Size of the header of a 16-bit PCM WAV file, before the first sample.
+const WAV_HEADER_LEN: u64 = 44;
+
+/// Ticks quieter than this count towards the pause that ends an utterance.
+const UTTERANCE_SPEECH_DB: f32 = -50.0;
+/// Silence left on the end of an utterance so the last word isn't clipped.
+const UTTERANCE_TAIL_SAMPLES: u64 = SAMPLE_RATE as u64 / 5;
+/// Anything shorter is a cough or a click, not worth a transcription.
+const UTTERANCE_MIN_SAMPLES: usize = SAMPLE_RATE as usize / 4;
+/// Audio from a speaker Discord hasn't identified yet is held this long, then
+/// discarded; it is only written once they turn out to have consented.
+const UNIDENTIFIED_HOLD_SAMPLES: u64 = SAMPLE_RATE as u64 * 2;
+
+/// One speaker's recorded track, as described in the session manifest.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeakerFile {
+    pub user_id: Option<u64>,
+    pub ssrc: u32,
+    pub file: String,
+    /// Sample offset of the first audio actually spoken, on the shared timeline
+    pub first_sample: u64,
+}
+
+/// Written next to the per-speaker tracks when a recording finishes. Every track
+/// has exactly `total_samples` samples, so offsets line up across files.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordingManifest {
+    pub sample_rate: u32,
+    pub total_samples: u64,
+    pub speakers: Vec<SpeakerFile>,
+}
+
+impl RecordingManifest {
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join(MANIFEST_FILE);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn duration_secs(&self) -> u64 {
+        self.total_samples / self.sample_rate as u64
+    }
+}
+
+/// One speaker's audio up to a pause, handed to live transcription as soon as
+/// they stop talking.
+#[derive(Clone, Debug)]
+pub struct Utterance {
+    pub ssrc: u32,
+    pub user_id: Option<u64>,
+    /// Position on the shared timeline, in samples
+    pub start_sample: u64,
+    pub samples: Vec<i16>,
+}
+
+struct PendingUtterance {
+    start_sample: u64,
+    samples: Vec<i16>,
+    /// Quiet samples at the end of `samples`
+    trailing_silence: u64,
+}
+
+impl PendingUtterance {
+    fn end_sample(&self) -> u64 {
+        self.start_sample + self.samples.len() as u64
+    }
+}
+
+/// Cuts each speaker's audio into utterances at pauses while recording.
+struct UtteranceSplitter {
+    sender: UnboundedSender<Utterance>,
+    pause_samples: u64,
+    max_samples: usize,
+    pending: HashMap<u32, PendingUtterance>,
+}
+
+impl UtteranceSplitter {
+    fn push_tick(&mut self, tick_end: u64, admitted: &[Admitted], users: &HashMap<u32, u64>) {
+        for (position, ssrc, samples) in admitted {
+            let position = *position;
+            let loud = level_db(samples) >= UTTERANCE_SPEECH_DB;
+            match self.pending.get_mut(ssrc) {
+                Some(pending) => {
+                    // Discord sends nothing while someone is quiet; keep the gap
+                    let gap = position.saturating_sub(pending.end_sample());
+                    pending.samples.resize(pending.samples.len() + gap as usize, 0);
+                    pending.trailing_silence += gap;
+                    pending.samples.extend_from_slice(samples);
+                    if loud {
+                        pending.trailing_silence = 0;
+                    } else {
+                        pending.trailing_silence += samples.len() as u64;
+                    }
+                }
+                None if loud => {
+                    self.pending.insert(*ssrc, PendingUtterance {
+                        start_sample: position,
+                        samples: samples.clone(),
+                        trailing_silence: 0,
+                    });
+                }
+                None => {}
+            }
+        }
+
+        let finished: Vec<u32> = self.pending
+            .iter()
+            .filter(|(_, p)| {
+                p.trailing_silence + tick_end.saturating_sub(p.end_sample()) >= self.pause_samples
+                    || p.samples.len() >= self.max_samples
+            })
+            .map(|(ssrc, _)| *ssrc)
+            .collect();
+        for ssrc in finished {
+            self.flush(ssrc, users);
+        }
+    }
+
+    fn flush(&mut self, ssrc: u32, users: &HashMap<u32, u64>) {
+        let Some(mut pending) = self.pending.remove(&ssrc) else { return };
+        let trim = pending.trailing_silence.saturating_sub(UTTERANCE_TAIL_SAMPLES) as usize;
+        pending.samples.truncate(pending.samples.len().saturating_sub(trim));
+        if pending.samples.len() < UTTERANCE_MIN_SAMPLES {
+            return;
+        }
+
+        let utterance = Utterance {
+            ssrc,
+            user_id: users.get(&ssrc).copied(),
+            start_sample: pending.start_sample,
+            samples: pending.samples,
+        };
+        if self.sender.send(utterance).is_err() {
+            debug!(ssrc, "Live transcription has stopped; dropping utterance");
+        }
+    }
+
+    fn discard(&mut self, ssrcs: &[u32]) {
+        for ssrc in ssrcs {
+            self.pending.remove(ssrc);
+        }
+    }
+
+    fn flush_all(&mut self, users: &HashMap<u32, u64>) {
+        let ssrcs: Vec<u32> = self.pending.keys().copied().collect();
+        for ssrc in ssrcs {
+            self.flush(ssrc, users);
+        }
+    }
+}
+
+/// Audio cleared for recording: (timeline position, SSRC, samples).
+type Admitted = (u64, u32, Vec<i16>);
+/// Audio held until its SSRC is matched to a user: (timeline position, samples), by SSRC.
+type Unidentified = HashMap<u32, Vec<(u64, Vec<i16>)>>;
+
+/// A speaker's WAV file, written in place: each sample lives at its position on
+/// the shared timeline, and the header is filled in when the recording finishes.
+struct SpeakerTrack {
+    file: BufWriter<File>,
+    /// Where the file currently ends on the timeline
+    samples_written: u64,
+    first_sample: u64,
+}
+
+struct RecorderState {
+    dir: PathBuf,
+    paused: AtomicBool,
+    /// Set by `detach`; the handlers unregister themselves the next time they fire
+    detached: AtomicBool,
+    /// Shared timeline position in samples; advances once per unpaused tick
+    position: Mutex<u64>,
+    ssrc_users: Mutex<HashMap<u32, u64>>,
+    /// Only these users' audio is ever written to disk
+    consented: Mutex<HashSet<u64>>,
+    /// Audio from SSRCs not yet matched to a user, by SSRC
+    unidentified: Mutex<Unidentified>,
+    tracks: Mutex<HashMap<u32, SpeakerTrack>>,
+    utterances: Mutex<Option<UtteranceSplitter>>,
+}
+
+/// Captures each speaker in a call to their own time-aligned mono WAV file.
+///
+/// Tracks are keyed by SSRC while recording, since audio can arrive before Discord
+/// says who is speaking, and renamed after their user when the recording finishes.
+/// Only users who have consented are recorded: audio from anyone else is dropped
+/// before it reaches a file, and audio from a speaker who hasn't been identified
+/// yet is held in memory until they are.
+#[derive(Clone)]
+pub struct Recorder {
+    state: Arc<RecorderState>,
+}
+
+impl Recorder {
+    pub fn new(dir: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create recording directory {}: {}", dir.display(), e))?;
+
+        Ok(Self {
+            state: Arc::new(RecorderState {
+                dir,
+                paused: AtomicBool::new(false),
+                detached: AtomicBool::new(false),
+                position: Mutex::new(0),
+                ssrc_users: Mutex::new(HashMap::new()),
+                consented: Mutex::new(HashSet::new()),
+                unidentified: Mutex::new(HashMap::new()),
+                tracks: Mutex::new(HashMap::new()),
+                utterances: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Starts or stops recording `user_id`. Revoking takes effect from the next tick;
+    /// their unfinished utterance is discarded rather than transcribed.
+    pub fn set_consent(&self, user_id: u64, consented: bool) {
+        let mut allowed = self.state.consented.lock().unwrap();
+        if consented {
+            allowed.insert(user_id);
+            return;
+        }
+        allowed.remove(&user_id);
+        drop(allowed);
+
+        let ssrcs: Vec<u32> = self.state.ssrc_users
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, user)| **user == user_id)
+            .map(|(ssrc, _)| *ssrc)
+            .collect();
+        if let Some(splitter) = self.state.utterances.lock().unwrap().as_mut() {
+            splitter.discard(&ssrcs);
+        }
+    }
+
+    /// Also sends each speaker's utterances to `sender` as they finish, cutting at
+    /// pauses of `pause_ms` or after `max_secs` of continuous speech.
+    pub fn stream_utterances(&self, sender: UnboundedSender<Utterance>, pause_ms: u32, max_secs: u32) {
+        *self.state.utterances.lock().unwrap() = Some(UtteranceSplitter {
+            sender,
+            pause_samples: SAMPLE_RATE as u64 * pause_ms as u64 / 1000,
+            max_samples: (SAMPLE_RATE * max_secs) as usize,
+            pending: HashMap::new(),
+        });
+    }
+
+    /// Switches the call to decoding received audio and registers the recorder's handlers.
+    pub fn attach(&self, call: &mut Call) {
+        let config = call
+            .config()
+            .clone()
+            .decode_mode(DecodeMode::Decode(DecodeConfig::new(Channels::Mono, SampleRate::Hz48000)));
+        call.set_config(config);
+
+        call.add_global_event(CoreEvent::SpeakingStateUpdate.into(), self.clone());
+        call.add_global_event(CoreEvent::VoiceTick.into(), self.clone());
+        call.add_global_event(CoreEvent::ClientDisconnect.into(), self.clone());
+    }
+
+    /// Stops decoding received audio and unregisters the recorder's handlers,
+    /// leaving any others on the call in place. Songbird can only remove handlers
+    /// all at once, so each of ours cancels itself when it next fires.
+    pub fn detach(&self, call: &mut Call) {
+        self.state.detached.store(true, Ordering::SeqCst);
+        let config = call
+            .config()
+            .clone()
+            .decode_mode(songbird::Config::default().decode_mode);
+        call.set_config(config);
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.state.dir
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.state.paused.store(paused, Ordering::SeqCst);
+        if paused {
+            self.flush_utterances();
+        }
+    }
+
+    fn flush_utterances(&self) {
+        if let Some(splitter) = self.state.utterances.lock().unwrap().as_mut() {
+            splitter.flush_all(&self.state.ssrc_users.lock().unwrap());
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+
+    /// Recorded length so far, excluding paused time.
+    pub fn elapsed_secs(&self) -> u64 {
+        *self.state.position.lock().unwrap() / SAMPLE_RATE as u64
+    }
+
+    /// Users heard so far.
+    pub fn speakers(&self) -> Vec<u64> {
+        let tracks = self.state.tracks.lock().unwrap();
+        let users = self.state.ssrc_users.lock().unwrap();
+        let mut speakers: Vec<u64> = tracks.keys().filter_map(|ssrc| users.get(ssrc).copied()).collect();
+        speakers.sort_unstable();
+        speakers.dedup();
+        speakers
+    }
+
+    fn record_tick(&self, speaking: Vec<(u32, Vec<i16>)>) {
+        let position = {
+            let mut position = self.state.position.lock().unwrap();
+            let current = *position;
+            *position += SAMPLES_PER_TICK;
+            current
+        };
+
+        let admitted = self.admit(position, speaking);
+
+        let mut tracks = self.state.tracks.lock().unwrap();
+        for (position, ssrc, samples) in &admitted {
+            let (position, ssrc) = (*position, *ssrc);
+            let track = match tracks.entry(ssrc) {
+                std::collections::hash_map::Entry::Occupied(e) => e.into_mut(),
+                std::collections::hash_map::Entry::Vacant(e) => match self.open_track(ssrc, position) {
+                    Ok(track) => e.insert(track),
+                    Err(err) => {
+                        warn!(ssrc, error = %err, "Failed to open speaker track");
+                        continue;
+                    }
+                },
+            };
+
+            if let Err(err) = write_aligned(track, position, samples) {
+                warn!(ssrc, error = %err, "Failed to write speaker audio");
+            }
+        }
+        drop(tracks);
+
+        if let Some(splitter) = self.state.utterances.lock().unwrap().as_mut() {
+            splitter.push_tick(position + SAMPLES_PER_TICK, &admitted, &self.state.ssrc_users.lock().unwrap());
+        }
+    }
+
+    /// Keeps the audio of consenting speakers, plus anything held back for them
+    /// before Discord said who they were, and drops everyone else's.
+    fn admit(&self, position: u64, speaking: Vec<(u32, Vec<i16>)>) -> Vec<Admitted> {
+        let users = self.state.ssrc_users.lock().unwrap();
+        let consented = self.state.consented.lock().unwrap();
+        let mut unidentified = self.state.unidentified.lock().unwrap();
+
+        let mut admitted = Vec::with_capacity(speaking.len());
+        for (ssrc, samples) in speaking {
+            match users.get(&ssrc) {
+                Some(user_id) if consented.contains(user_id) => {
+                    for (held_position, held) in unidentified.remove(&ssrc).unwrap_or_default() {
+                        admitted.push((held_position, ssrc, held));
+                    }
+                    admitted.push((position, ssrc, samples));
+                }
+                Some(_) => {
+                    unidentified.remove(&ssrc);
+                }
+                None => unidentified.entry(ssrc).or_default().push((position, samples)),
+            }
+        }
+
+        unidentified.retain(|_, held| {
+            held.retain(|(held_position, _)| position - held_position < UNIDENTIFIED_HOLD_SAMPLES);
+            !held.is_empty()
+        });
+        admitted
+    }
+
+    fn open_track(&self, ssrc: u32, position: u64) -> Result<SpeakerTrack, Error> {
+        let path = self.state.dir.join(format!("ssrc-{ssrc}.wav"));
+        let mut file = File::create(&path)
+            .map(BufWriter::new)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        // Leaves the file a valid, empty WAV until `finish` fills in the length
+        file.write_all(&wav_header(0))?;
+        debug!(ssrc, path = %path.display(), "Opened speaker track");
+
+        Ok(SpeakerTrack { file, samples_written: 0, first_sample: position })
+    }
+
+    /// Pads every track to the same length, renames them after their speakers and
+    /// writes the manifest. The recorder must be detached from the call first.
+    pub fn finish(&self) -> Result<RecordingManifest, Error> {
+        // Hand over the last utterances and close the stream so live transcription can finish
+        self.flush_utterances();
+        self.state.utterances.lock().unwrap().take();
+
+        let total_samples = *self.state.position.lock().unwrap();
+        let tracks = std::mem::take(&mut *self.state.tracks.lock().unwrap());
+        let users = self.state.ssrc_users.lock().unwrap().clone();
+
+        let mut speakers = Vec::new();
+        for (ssrc, track) in tracks {
+            let first_sample = track.first_sample;
+            finalise_track(track, total_samples)
+                .map_err(|e| format!("Failed to finalise track for SSRC {}: {}", ssrc, e))?;
+
+            let user_id = users.get(&ssrc).copied();
+            let file = match user_id {
+                Some(user_id) => {
+                    let mut name = format!("{user_id}.wav");
+                    if self.state.dir.join(&name).exists() {
+                        // The same user rejoined with a new SSRC
+                        name = format!("{user_id}-{ssrc}.wav");
+                    }
+                    std::fs::rename(
+                        self.state.dir.join(format!("ssrc-{ssrc}.wav")),
+                        self.state.dir.join(&name),
+                    )?;
+                    name
+                }
+                None => format!("ssrc-{ssrc}.wav"),
+            };
+
+            speakers.push(SpeakerFile { user_id, ssrc, file, first_sample });
+        }
+        speakers.sort_by_key(|s| s.first_sample);
+
+        let manifest = RecordingManifest { sample_rate: SAMPLE_RATE, total_samples, speakers };
+        std::fs::write(
+            self.state.dir.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        Ok(manifest)
+    }
+}
+
+/// Writes `samples` at `position` on the timeline. Any gap since the speaker last
+/// talked is seeked over rather than written: the file system reads the hole back
+/// as zeroes, which is silence, so a long quiet stretch costs nothing on the voice
+/// thread.
+fn write_aligned(track: &mut SpeakerTrack, position: u64, samples: &[i16]) -> Result<(), Error> {
+    if position > track.samples_written {
+        track.file.seek(SeekFrom::Start(WAV_HEADER_LEN + position * 2))?;
+        track.samples_written = position;
+    }
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    track.file.write_all(&bytes)?;
+    track.samples_written += samples.len() as u64;
+    Ok(())
+}
+
+/// Extends the track with silence to `total_samples` and writes its header.
+fn finalise_track(track: SpeakerTrack, total_samples: u64) -> Result<(), Error> {
+    let data_len = u32::try_from(total_samples * 2)
+        .ok()
+        .filter(|len| *len <= u32::MAX - WAV_HEADER_LEN as u32)
+        .ok_or("the recording is too long for a WAV file")?;
+    let mut file = track.file.into_inner().map_err(|e| e.into_error())?;
+    file.set_len(WAV_HEADER_LEN + data_len as u64)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&wav_header(data_len))?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// The header of a mono 16-bit PCM WAV file holding `data_len` bytes of samples.
+fn wav_header(data_len: u32) -> [u8; WAV_HEADER_LEN as usize] {
+    let mut header = [0u8; WAV_HEADER_LEN as usize];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&(data_len + WAV_HEADER_LEN as u32 - 8).to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    header[22..24].copy_from_slice(&1u16.to_le_bytes()); // mono
+    header[24..28].copy_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header[28..32].copy_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    header[32..34].copy_from_slice(&2u16.to_le_bytes());
+    header[34..36].copy_from_slice(&16u16.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+fn level_db(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let mean_square = samples
+        .iter()
+        .map(|&s| (s as f64 / i16::MAX as f64).powi(2))
+        .sum::<f64>() / samples.len() as f64;
+    (10.0 * mean_square.max(1e-12).log10()) as f32
+}
+
+#[async_trait]
+impl EventHandler for Recorder {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if self.state.detached.load(Ordering::SeqCst) {
+            return Some(Event::Cancel);
+        }
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    self.state.ssrc_users.lock().unwrap().insert(speaking.ssrc, user_id.0);
+                }
+            }
+            EventContext::VoiceTick(tick) => {
+                if self.is_paused() {
+                    return None;
+                }
+                let speaking = tick
+                    .speaking
+                    .iter()
+                    .filter_map(|(ssrc, data)| data.decoded_voice.clone().map(|v| (*ssrc, v)))
+                    .collect();
+                self.record_tick(speaking);
+            }
+            EventContext::ClientDisconnect(disconnect) => {
+                debug!(user_id = disconnect.user_id.0, "Speaker left the call");
+            }
+            _ => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use poise::serenity_prelude::GuildId;
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+use crate::chronicle::config::{ChronicleConfig, RetentionConfig, RetentionDays};
+use crate::chronicle::glossary::CORRECTIONS_FILE;
+use crate::chronicle::live::{LIVE_COMPLETE_FILE, LIVE_DIR, LIVE_SEGMENTS_FILE};
+use crate::chronicle::pipeline::{PREPARED_DIR, SEGMENTS_FILE};
+use crate::chronicle::processor::CACHE_DIR;
+use crate::chronicle::session::unix_now;
+use crate::chronicle::transcript::TRANSCRIPT_STEM;
+use crate::db::chronicle::{
+    delete_transcript_index, fetch_campaign, fetch_finished_sessions, insert_deletion, CampaignRow, SessionRow,
+};
+use crate::definitions::Error;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// What a retention rule applies to. Summaries, notes and the mixdown archive are
+/// never deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RetainedKind {
+    /// Per-speaker tracks and audio prepared from them
+    RawAudio,
+    /// Transcripts and the model output cached from them
+    Transcript,
+}
+
+impl RetainedKind {
+    pub const ALL: [RetainedKind; 2] = [RetainedKind::RawAudio, RetainedKind::Transcript];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetainedKind::RawAudio   => "raw_audio",
+            RetainedKind::Transcript => "transcript",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RetainedKind::RawAudio   => "raw audio",
+            RetainedKind::Transcript => "transcript",
+        }
+    }
+
+    /// The files and folders of this kind in a session directory that still exist.
+    pub fn paths(&self, session_dir: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = match self {
+            RetainedKind::RawAudio => std::fs::read_dir(session_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|e| e.path())
+                        .filter(|p| p.extension().is_some_and(|ext| ext == "wav"))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            RetainedKind::Transcript => std::fs::read_dir(session_dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|e| e.path())
+                        .filter(|p| p.file_stem().is_some_and(|stem| stem == TRANSCRIPT_STEM))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        let extra: &[&str] = match self {
+            RetainedKind::RawAudio => &[LIVE_DIR, PREPARED_DIR],
+            RetainedKind::Transcript => &[LIVE_SEGMENTS_FILE, LIVE_COMPLETE_FILE, SEGMENTS_FILE, CORRECTIONS_FILE, CACHE_DIR],
+        };
+        paths.extend(extra.iter().map(|name| session_dir.join(name)).filter(|p| p.exists()));
+        paths.sort();
+        paths
+    }
+}
+
+/// The rules for one campaign: its overrides on top of the server's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub raw_audio: RetentionDays,
+    pub transcript: RetentionDays,
+}
+
+impl RetentionPolicy {
+    pub fn for_campaign(config: &RetentionConfig, campaign: Option<&CampaignRow>) -> Self {
+        let resolve = |stored: Option<i64>, default: RetentionDays| match stored {
+            Some(days) if days < 0 => RetentionDays::Forever,
+            Some(days) => RetentionDays::Days(days as u32),
+            None => default,
+        };
+        Self {
+            raw_audio: resolve(campaign.and_then(|c| c.audio_retention_days), config.raw_audio),
+            transcript: resolve(campaign.and_then(|c| c.transcript_retention_days), config.transcript),
+        }
+    }
+
+    pub fn rule(&self, kind: RetainedKind) -> RetentionDays {
+        match kind {
+            RetainedKind::RawAudio => self.raw_audio,
+            RetainedKind::Transcript => self.transcript,
+        }
+    }
+}
+
+/// Files of one kind in one session that a rule will delete.
+#[derive(Clone, Debug)]
+pub struct Expiry {
+    pub session: SessionRow,
+    pub kind: RetainedKind,
+    pub rule: RetentionDays,
+    pub expires_at: i64,
+    pub paths: Vec<PathBuf>,
+    pub bytes: u64,
+}
+
+/// What retention will do, for the upcoming-expiry view.
+#[derive(Clone, Debug, Default)]
+pub struct RetentionPlan {
+    /// Soonest first
+    pub expiries: Vec<Expiry>,
+    /// Finished sessions holding files that wait for their summary to be approved
+    pub awaiting_approval: Vec<SessionRow>,
+}
+
+/// Works out every pending deletion, optionally in one guild.
+pub async fn plan(
+    db_pool: &SqlitePool,
+    config: &RetentionConfig,
+    guild_id: Option<GuildId>,
+) -> Result<RetentionPlan, Error> {
+    let mut campaigns: HashMap<i64, Option<CampaignRow>> = HashMap::new();
+    let mut plan = RetentionPlan::default();
+
+    for session in fetch_finished_sessions(db_pool, guild_id).await? {
+        let dir = PathBuf::from(&session.storage_dir);
+        let Some(approved_at) = session.approved_at else {
+            if RetainedKind::ALL.iter().any(|kind| !kind.paths(&dir).is_empty()) {
+                plan.awaiting_approval.push(session);
+            }
+            continue;
+        };
+
+        let campaign = match session.campaign_id {
+            Some(id) => match campaigns.get(&id) {
+                Some(campaign) => campaign.clone(),
+                None => {
+                    let campaign = fetch_campaign(db_pool, id).await?;
+                    campaigns.insert(id, campaign.clone());
+                    campaign
+                }
+            },
+            None => None,
+        };
+        let policy = RetentionPolicy::for_campaign(config, campaign.as_ref());
+
+        for kind in RetainedKind::ALL {
+            let RetentionDays::Days(days) = policy.rule(kind) else { continue };
+            let paths = kind.paths(&dir);
+            if paths.is_empty() {
+                continue;
+            }
+            plan.expiries.push(Expiry {
+                session: session.clone(),
+                kind,
+                rule: policy.rule(kind),
+                expires_at: approved_at + days as i64 * SECONDS_PER_DAY,
+                bytes: paths.iter().map(|p| disk_usage(p)).sum(),
+                paths,
+            });
+        }
+    }
+
+    plan.expiries.sort_by_key(|e| e.expires_at);
+    Ok(plan)
+}
+
+/// Deletes everything whose time has come and logs each deletion. Returns what was deleted.
+pub async fn enforce(db_pool: &SqlitePool, config: &ChronicleConfig, now: i64) -> Result<Vec<Expiry>, Error> {
+    let plan = plan(db_pool, &config.retention, None).await?;
+    let mut deleted = Vec::new();
+
+    for expiry in plan.expiries.into_iter().filter(|e| e.expires_at <= now) {
+        for path in &expiry.paths {
+            // Never follow a corrupted storage_dir out of the chronicle storage root
+            if !path.starts_with(&config.storage_root) {
+                warn!(path = %path.display(), "Refusing to delete a file outside the chronicle storage root");
+                continue;
+            }
+
+            let bytes = disk_usage(path);
+            let removed = if path.is_dir() {
+                tokio::fs::remove_dir_all(path).await
+            } else {
+                tokio::fs::remove_file(path).await
+            };
+            if let Err(e) = removed {
+                warn!(path = %path.display(), error = %e, "Retention failed to delete a file");
+                continue;
+            }
+
+            insert_deletion(
+                db_pool,
+                &expiry.session,
+                expiry.kind.as_str(),
+                &path.to_string_lossy(),
+                bytes,
+                &expiry.rule.describe(),
+                now,
+            ).await?;
+        }
+        // The search index holds the transcript's text too
+        if expiry.kind == RetainedKind::Transcript {
+            delete_transcript_index(db_pool, expiry.session.id).await?;
+        }
+        info!(
+            session_id = expiry.session.id,
+            kind = expiry.kind.as_str(),
+            bytes = expiry.bytes,
+            "Retention deleted expired files",
+        );
+        deleted.push(expiry);
+    }
+    Ok(deleted)
+}
+
+pub async fn run_retention_schedule(db_pool: SqlitePool, config: ChronicleConfig) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.retention.check_interval_hours as u64 * 3600));
+    loop {
+        ticker.tick().await;
+
+        match enforce(&db_pool, &config, unix_now()).await {
+            Ok(deleted) if !deleted.is_empty() => info!(expired = deleted.len(), "Retention run complete"),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "Retention run failed"),
+        }
+    }
+}
+
+/// Size of a file, or of everything under a folder.
+fn disk_usage(path: &Path) -> u64 {
+    if path.is_dir() {
+        std::fs::read_dir(path)
+            .map(|entries| entries.filter_map(Result::ok).map(|e| disk_usage(&e.path())).sum())
+            .unwrap_or(0)
+    } else {
+        std::fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::transcript::Transcript;
+use crate::db::chronicle::{
+    fetch_campaign_sessions, index_transcript, is_transcript_indexed, NewTranscriptLine, SessionRow,
+};
+use crate::definitions::Error;
+
+/// Puts the session's transcript in the search index, replacing what was there.
+pub async fn index(db_pool: &SqlitePool, session: &SessionRow, transcript: &Transcript) -> Result<(), Error> {
+    let lines: Vec<NewTranscriptLine> = transcript
+        .entries
+        .iter()
+        .map(|entry| NewTranscriptLine {
+            speaker: entry.speaker,
+            speaker_name: entry.speaker_name.clone(),
+            start_ms: entry.start_ms,
+            text: entry.text.clone(),
+        })
+        .collect();
+    index_transcript(db_pool, session, &lines).await
+}
+
+/// Indexes the campaign's finished sessions that have a transcript on disk but
+/// nothing in the index, e.g. ones transcribed before search existed.
+pub async fn index_missing(db_pool: &SqlitePool, campaign_id: i64) -> Result<usize, Error> {
+    let roster = Roster::load(db_pool, Some(campaign_id)).await?;
+    let mut indexed = 0;
+    for session in fetch_campaign_sessions(db_pool, campaign_id).await? {
+        let path = Transcript::path(Path::new(&session.storage_dir));
+        if session.ended_at.is_none() || !path.is_file() || is_transcript_indexed(db_pool, session.id).await? {
+            continue;
+        }
+
+        match Transcript::load(&path, session.id, &roster) {
+            Ok(transcript) => {
+                index(db_pool, &session, &transcript).await?;
+                indexed += 1;
+            }
+            Err(e) => warn!(session_id = session.id, error = %e, "Couldn't index transcript"),
+        }
+    }
+
+    if indexed > 0 {
+        info!(campaign_id, sessions = indexed, "Indexed transcripts for search");
+    }
+    Ok(indexed)
+}
+
+/// Turns what someone typed into an FTS5 query that can't be a syntax error:
+/// `"quoted phrases"` stay phrases, every other word must appear, and a trailing
+/// `*` keeps its prefix meaning. `None` if there's nothing to search for.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    for (index, part) in input.split('"').enumerate() {
+        // Odd parts were inside quotes
+        if index % 2 == 1 {
+            let phrase = words(part).join(" ");
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase));
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            let pieces = words(word);
+            for (i, piece) in pieces.iter().enumerate() {
+                let prefix = word.ends_with('*') && i + 1 == pieces.len();
+                terms.push(if prefix { format!("\"{}\"*", piece) } else { format!("\"{}\"", piece) });
+            }
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// The letters-and-digits runs of `text`, which is how the index splits words.
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
@@ -0,0 +1,392 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sqlx::SqlitePool;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::ai::whisper::Transcriber;
+use crate::chronicle::campaign::Roster;
+use crate::chronicle::config::LiveConfig;
+use crate::chronicle::live::{Captions, LiveSummary, LiveTranscription};
+use crate::chronicle::recorder::{Recorder, RecordingManifest};
+use crate::db::chronicle::{
+    add_session_participants, end_session, fetch_consented_users, insert_session, set_session_state,
+    set_session_storage_dir,
+};
+use crate::definitions::Error;
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    Recording,
+    Paused,
+    Stopped,
+    Interrupted,
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionState::Recording   => "recording",
+            SessionState::Paused      => "paused",
+            SessionState::Stopped     => "stopped",
+            SessionState::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// A session that is currently recording (or paused) in a guild.
+pub struct ActiveSession {
+    pub id: i64,
+    pub voice_channel_id: ChannelId,
+    pub campaign_id: Option<i64>,
+    pub session_number: Option<i64>,
+    pub started_at: i64,
+    pub recorder: Recorder,
+    pub live: Option<LiveTranscription>,
+}
+
+/// Transcribe the session while it records, optionally posting captions.
+pub struct LiveOptions {
+    pub config: LiveConfig,
+    pub transcriber: Arc<dyn Transcriber>,
+    pub roster: Roster,
+    pub captions: Option<Captions>,
+    /// Primes the transcriber with the campaign's glossary
+    pub prompt: Option<String>,
+}
+
+/// What `/chronicle start` asks to record.
+pub struct StartRequest<'a> {
+    pub guild_id: GuildId,
+    pub vc_id: ChannelId,
+    /// Where start and stop are announced
+    pub text_channel_id: ChannelId,
+    pub campaign_id: Option<i64>,
+    /// Everyone in the channel when recording starts
+    pub participants: &'a [u64],
+    pub live: Option<LiveOptions>,
+}
+
+/// What `/chronicle start` created.
+pub struct StartedSession {
+    pub id: i64,
+    pub session_number: Option<i64>,
+}
+
+pub struct SessionStatus {
+    pub id: i64,
+    pub campaign_id: Option<i64>,
+    pub session_number: Option<i64>,
+    pub state: SessionState,
+    pub voice_channel_id: ChannelId,
+    pub started_at: i64,
+    pub recorded_secs: u64,
+    pub speakers: usize,
+    pub disk_bytes: u64,
+}
+
+pub struct StoppedSession {
+    pub id: i64,
+    pub campaign_id: Option<i64>,
+    pub session_number: Option<i64>,
+    pub manifest: RecordingManifest,
+    /// How live transcription went, if it ran
+    pub live: Option<LiveSummary>,
+}
+
+/// Tracks the chronicle recording in each guild; one per guild at a time.
+pub struct ChronicleService {
+    storage_root: PathBuf,
+    active: RwLock<HashMap<GuildId, ActiveSession>>,
+    /// Guilds whose `start` is still joining voice
+    starting: Mutex<HashSet<GuildId>>,
+}
+
+/// A guild's place in `starting`, given up when dropped.
+struct StartSlot<'a> {
+    starting: &'a Mutex<HashSet<GuildId>>,
+    guild_id: GuildId,
+}
+
+impl Drop for StartSlot<'_> {
+    fn drop(&mut self) {
+        self.starting.lock().unwrap_or_else(PoisonError::into_inner).remove(&self.guild_id);
+    }
+}
+
+impl ChronicleService {
+    pub fn new(storage_root: PathBuf) -> Self {
+        Self {
+            storage_root,
+            active: RwLock::new(HashMap::new()),
+            starting: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether a session is recording in the guild or still starting there.
+    pub async fn is_recording(&self, guild_id: GuildId) -> bool {
+        self.active.read().await.contains_key(&guild_id)
+            || self.starting.lock().unwrap_or_else(PoisonError::into_inner).contains(&guild_id)
+    }
+
+    /// Refuses a voice join that would move the bot out of a recorded channel.
+    pub async fn ensure_not_recording(&self, guild_id: GuildId) -> Result<(), Error> {
+        if self.is_recording(guild_id).await {
+            return Err("A chronicle session is recording in this server; use `/chronicle stop` first.".into());
+        }
+        Ok(())
+    }
+
+    /// Joins `vc_id`, creates the session record and starts capturing audio.
+    pub async fn start(
+        &self,
+        db_pool: &SqlitePool,
+        serenity_ctx: &poise::serenity_prelude::Context,
+        request: StartRequest<'_>,
+    ) -> Result<StartedSession, Error> {
+        let StartRequest { guild_id, vc_id, text_channel_id, campaign_id, participants, live } = request;
+        // Held until the session is in `active`, without blocking other guilds meanwhile
+        let slot = self.reserve(guild_id).await?;
+
+        let started_at = unix_now();
+        let (session_id, session_number) = insert_session(
+            db_pool,
+            guild_id,
+            vc_id.get() as i64,
+            text_channel_id.get() as i64,
+            campaign_id,
+            started_at,
+        )
+        .await?;
+
+        let dir = self.storage_root
+            .join("sessions")
+            .join(guild_id.to_string())
+            .join(session_id.to_string());
+        let prepared = async {
+            set_session_storage_dir(db_pool, session_id, &dir.to_string_lossy()).await?;
+            add_session_participants(db_pool, session_id, participants).await?;
+            Recorder::new(dir)
+        };
+        let recorder = match prepared.await {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                abandon_session(db_pool, session_id, started_at).await;
+                return Err(e);
+            }
+        };
+
+        let manager = songbird::get(serenity_ctx)
+            .await
+            .expect("Songbird was not initialized")
+            .clone();
+        let call = match manager.join(guild_id, vc_id).await {
+            Ok(call) => call,
+            Err(e) => {
+                abandon_session(db_pool, session_id, started_at).await;
+                return Err(e.into());
+            }
+        };
+        let live = live.map(|options| {
+            let (sender, live) = LiveTranscription::spawn(
+                options.transcriber,
+                options.roster,
+                recorder.dir().to_path_buf(),
+                options.captions,
+                options.prompt,
+            );
+            recorder.stream_utterances(sender, options.config.pause_ms, options.config.max_utterance_secs);
+            live
+        });
+        recorder.attach(&mut *call.lock().await);
+
+        self.active.write().await.insert(guild_id, ActiveSession {
+            id: session_id,
+            voice_channel_id: vc_id,
+            campaign_id,
+            session_number,
+            started_at,
+            recorder: recorder.clone(),
+            live,
+        });
+        drop(slot);
+
+        // Read once the session is in `active`, so a consent given meanwhile is applied by `set_consent`
+        match fetch_consented_users(db_pool, guild_id).await {
+            Ok(consented) => {
+                for user_id in consented {
+                    recorder.set_consent(user_id, true);
+                }
+            }
+            Err(e) => {
+                if let Err(stop_error) = self.stop(db_pool, serenity_ctx, guild_id).await {
+                    warn!(session_id, error = %stop_error, "Couldn't stop a session that failed to start");
+                }
+                abandon_session(db_pool, session_id, unix_now()).await;
+                return Err(e);
+            }
+        }
+
+        Ok(StartedSession { id: session_id, session_number })
+    }
+
+    /// Claims the guild's recording slot, failing if a session is recording or starting there.
+    async fn reserve(&self, guild_id: GuildId) -> Result<StartSlot<'_>, Error> {
+        let active = self.active.read().await;
+        let mut starting = self.starting.lock().unwrap_or_else(PoisonError::into_inner);
+        if active.contains_key(&guild_id) || !starting.insert(guild_id) {
+            return Err("A chronicle session is already recording in this server.".into());
+        }
+        Ok(StartSlot { starting: &self.starting, guild_id })
+    }
+
+    /// Pauses or resumes recording. Returns the session ID.
+    pub async fn set_paused(
+        &self,
+        db_pool: &SqlitePool,
+        guild_id: GuildId,
+        paused: bool,
+    ) -> Result<i64, Error> {
+        let active = self.active.read().await;
+        let session = active.get(&guild_id)
+            .ok_or("No chronicle session is recording in this server.")?;
+
+        if session.recorder.is_paused() == paused {
+            return Err(if paused {
+                "The recording is already paused.".into()
+            } else {
+                "The recording isn't paused.".into()
+            });
+        }
+
+        session.recorder.set_paused(paused);
+        let state = if paused { SessionState::Paused } else { SessionState::Recording };
+        set_session_state(db_pool, session.id, state.as_str()).await?;
+        Ok(session.id)
+    }
+
+    /// Stops capturing, finalises the per-speaker tracks and closes the session record.
+    pub async fn stop(
+        &self,
+        db_pool: &SqlitePool,
+        serenity_ctx: &poise::serenity_prelude::Context,
+        guild_id: GuildId,
+    ) -> Result<StoppedSession, Error> {
+        let session = self.active
+            .write()
+            .await
+            .remove(&guild_id)
+            .ok_or("No chronicle session is recording in this server.")?;
+
+        let manager = songbird::get(serenity_ctx)
+            .await
+            .expect("Songbird was not initialized")
+            .clone();
+        if let Some(call) = manager.get(guild_id) {
+            session.recorder.detach(&mut *call.lock().await);
+        }
+        manager.remove(guild_id).await.ok();
+
+        let session_id = session.id;
+        match self.finish(db_pool, session).await {
+            Ok(stopped) => Ok(stopped),
+            Err(e) => {
+                abandon_session(db_pool, session_id, unix_now()).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Finalises a session already detached from voice and closes its record.
+    async fn finish(&self, db_pool: &SqlitePool, session: ActiveSession) -> Result<StoppedSession, Error> {
+        let recorder = session.recorder;
+        let manifest = tokio::task::spawn_blocking({
+            let recorder = recorder.clone();
+            move || recorder.finish()
+        })
+        .await
+        .map_err(|e| format!("Recording finaliser panicked: {}", e))??;
+
+        // The recorder closed the utterance stream; wait for the last few to finish
+        let live = match session.live {
+            Some(live) => match live.finish().await {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    warn!(session_id = session.id, error = %e, "Live transcription didn't finish");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let speakers: Vec<u64> = manifest.speakers.iter().filter_map(|s| s.user_id).collect();
+        add_session_participants(db_pool, session.id, &speakers).await?;
+        end_session(db_pool, session.id, unix_now(), SessionState::Stopped.as_str()).await?;
+
+        Ok(StoppedSession {
+            id: session.id,
+            campaign_id: session.campaign_id,
+            session_number: session.session_number,
+            manifest,
+            live,
+        })
+    }
+
+    /// Applies a consent change to the guild's recording, if there is one. Returns
+    /// the session it applied to.
+    pub async fn set_consent(&self, guild_id: GuildId, user_id: u64, consented: bool) -> Option<i64> {
+        let active = self.active.read().await;
+        let session = active.get(&guild_id)?;
+        session.recorder.set_consent(user_id, consented);
+        Some(session.id)
+    }
+
+    pub async fn status(&self, guild_id: GuildId) -> Option<SessionStatus> {
+        let active = self.active.read().await;
+        let session = active.get(&guild_id)?;
+
+        Some(SessionStatus {
+            id: session.id,
+            campaign_id: session.campaign_id,
+            session_number: session.session_number,
+            state: if session.recorder.is_paused() { SessionState::Paused } else { SessionState::Recording },
+            voice_channel_id: session.voice_channel_id,
+            started_at: session.started_at,
+            recorded_secs: session.recorder.elapsed_secs(),
+            speakers: session.recorder.speakers().len(),
+            disk_bytes: dir_size(session.recorder.dir()),
+        })
+    }
+}
+
+/// Closes a session record that never recorded or didn't finish, so it isn't
+/// left `recording`. Failures are only logged; the caller is already failing.
+async fn abandon_session(db_pool: &SqlitePool, session_id: i64, ended_at: i64) {
+    if let Err(e) = end_session(db_pool, session_id, ended_at, SessionState::Interrupted.as_str()).await {
+        warn!(session_id, error = %e, "Couldn't mark the session interrupted");
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|e| e.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,388 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai::whisper::Segment;
+use crate::chronicle::campaign::Roster;
+use crate::definitions::Error;
+
+pub const TRANSCRIPT_STEM: &str = "transcript";
+const TRANSCRIPT_VERSION: u32 = 1;
+/// Consecutive fragments from one speaker closer than this are joined into one line.
+const MERGE_GAP_MS: u64 = 1_500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Json,
+    Text,
+    Srt,
+    Vtt,
+}
+
+impl TranscriptFormat {
+    pub const ALL: [TranscriptFormat; 4] = [
+        TranscriptFormat::Json,
+        TranscriptFormat::Text,
+        TranscriptFormat::Srt,
+        TranscriptFormat::Vtt,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TranscriptFormat::Json => "json",
+            TranscriptFormat::Text => "txt",
+            TranscriptFormat::Srt  => "srt",
+            TranscriptFormat::Vtt  => "vtt",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL.into_iter().find(|f| f.extension() == extension)
+    }
+}
+
+/// One speaker-labelled line of the transcript.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub speaker: Option<u64>,
+    /// Character name when the campaign has one for the speaker
+    pub speaker_name: String,
+    pub text: String,
+    pub confidence: f32,
+}
+
+/// The whole session in speaking order: the artifact that gets reviewed, searched
+/// and summarised. The JSON form is canonical; the others are for reading.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub version: u32,
+    pub session_id: i64,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+impl Transcript {
+    /// Orders every speaker's segments on the session timeline and joins fragments
+    /// one speaker split with short pauses.
+    pub fn from_segments(session_id: i64, mut segments: Vec<Segment>, roster: &Roster) -> Self {
+        segments.sort_by_key(|s| (s.start_ms, s.end_ms));
+
+        let mut entries: Vec<TranscriptEntry> = Vec::with_capacity(segments.len());
+        for segment in segments {
+            if let Some(last) = entries.last_mut()
+                && last.speaker == segment.speaker
+                && segment.start_ms.saturating_sub(last.end_ms) <= MERGE_GAP_MS
+            {
+                let last_len = last.end_ms.saturating_sub(last.start_ms).max(1) as f32;
+                let next_len = segment.end_ms.saturating_sub(segment.start_ms).max(1) as f32;
+                last.confidence = (last.confidence * last_len + segment.confidence * next_len)
+                    / (last_len + next_len);
+                last.end_ms = last.end_ms.max(segment.end_ms);
+                last.text.push(' ');
+                last.text.push_str(&segment.text);
+                continue;
+            }
+
+            entries.push(TranscriptEntry {
+                start_ms: segment.start_ms,
+                end_ms: segment.end_ms,
+                speaker: segment.speaker,
+                speaker_name: roster.speaker_name(segment.speaker),
+                text: segment.text,
+                confidence: segment.confidence,
+            });
+        }
+
+        Self { version: TRANSCRIPT_VERSION, session_id, entries }
+    }
+
+    pub fn render(&self, format: TranscriptFormat) -> Result<String, Error> {
+        Ok(match format {
+            TranscriptFormat::Json => serde_json::to_string_pretty(self)?,
+            TranscriptFormat::Text => self.to_text(),
+            TranscriptFormat::Srt  => self.to_srt(),
+            TranscriptFormat::Vtt  => self.to_vtt(),
+        })
+    }
+
+    /// `[01:02:03] Thalia: text`, one entry per line.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            let _ = writeln!(out, "[{}] {}: {}", clock(entry.start_ms), entry.speaker_name, entry.text);
+        }
+        out
+    }
+
+    pub fn to_srt(&self) -> String {
+        let mut out = String::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{}\n{} --> {}\n{}: {}\n",
+                index + 1,
+                cue_time(entry.start_ms, ','),
+                cue_time(entry.end_ms, ','),
+                entry.speaker_name,
+                entry.text,
+            );
+        }
+        out
+    }
+
+    pub fn to_vtt(&self) -> String {
+        let mut out = String::from("WEBVTT\n\n");
+        for entry in &self.entries {
+            let _ = writeln!(
+                out,
+                "{} --> {}\n<v {}>{}\n",
+                cue_time(entry.start_ms, '.'),
+                cue_time(entry.end_ms, '.'),
+                entry.speaker_name,
+                entry.text,
+            );
+        }
+        out
+    }
+
+    /// Writes every format next to each other as `transcript.<ext>` in `dir`.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        for format in TranscriptFormat::ALL {
+            let path = dir.join(format!("{TRANSCRIPT_STEM}.{}", format.extension()));
+            std::fs::write(&path, self.render(format)?)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        Ok(Self::path(dir))
+    }
+
+    /// The canonical JSON transcript in a session directory.
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(format!("{TRANSCRIPT_STEM}.{}", TranscriptFormat::Json.extension()))
+    }
+
+    /// Loads a transcript saved as JSON, or a hand-edited plain text one. Text lines
+    /// keep their speaker names; speaker IDs are recovered from `roster` where possible.
+    pub fn load(path: &Path, session_id: i64, roster: &Roster) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+        match TranscriptFormat::from_path(path) {
+            Some(TranscriptFormat::Json) => {
+                let transcript: Transcript = serde_json::from_str(&content)
+                    .map_err(|e| format!("Invalid transcript {}: {}", path.display(), e))?;
+                if transcript.version > TRANSCRIPT_VERSION {
+                    return Err(format!(
+                        "{} is transcript version {}; this build reads up to {}",
+                        path.display(),
+                        transcript.version,
+                        TRANSCRIPT_VERSION,
+                    ).into());
+                }
+                Ok(transcript)
+            }
+            Some(TranscriptFormat::Text) => Self::parse_text(&content, session_id, roster)
+                .map_err(|e| format!("Invalid transcript {}: {}", path.display(), e).into()),
+            _ => Err(format!("Can't load a transcript from {}; use the .json or .txt file", path.display()).into()),
+        }
+    }
+
+    fn parse_text(content: &str, session_id: i64, roster: &Roster) -> Result<Self, String> {
+        let mut entries: Vec<TranscriptEntry> = Vec::new();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once("] "))
+                .and_then(|(time, rest)| Some((parse_clock(time)?, rest.split_once(": ")?)));
+            let Some((start_ms, (speaker_name, text))) = parsed else {
+                // A line without a timestamp continues the previous entry
+                match entries.last_mut() {
+                    Some(last) => {
+                        last.text.push(' ');
+                        last.text.push_str(line);
+                        continue;
+                    }
+                    None => return Err(format!("line {}: expected `[hh:mm:ss] Speaker: text`", number + 1)),
+                }
+            };
+
+            if let Some(last) = entries.last_mut() {
+                last.end_ms = start_ms.max(last.start_ms);
+            }
+            let speaker = roster
+                .characters()
+                .find(|c| c.name == speaker_name)
+                .map(|c| c.user_id)
+                .or_else(|| speaker_name.strip_prefix("Player ").and_then(|id| id.parse().ok()));
+
+            entries.push(TranscriptEntry {
+                start_ms,
+                end_ms: start_ms,
+                speaker,
+                speaker_name: speaker_name.to_string(),
+                text: text.trim().to_string(),
+                confidence: 1.0,
+            });
+        }
+
+        Ok(Self { version: TRANSCRIPT_VERSION, session_id, entries })
+    }
+}
+
+/// `hh:mm:ss`
+pub fn clock(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+fn parse_clock(raw: &str) -> Option<u64> {
+    let mut parts = raw.split(':').map(|p| p.parse::<u64>().ok());
+    let (h, m, s) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || m >= 60 || s >= 60 {
+        return None;
+    }
+    Some((h * 3600 + m * 60 + s) * 1000)
+}
+
+/// `hh:mm:ss,mmm` for SRT, `hh:mm:ss.mmm` for WebVTT.
+fn cue_time(ms: u64, separator: char) -> String {
+    format!("{}{}{:03}", clock(ms), separator, ms % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chronicle::campaign::Character;
+
+    fn roster() -> Roster {
+        Roster::from_characters([Character { user_id: 1, name: "Thalia".to_string(), class: Some("bard".to_string()) }])
+    }
+
+    fn segment(start_ms: u64, end_ms: u64, speaker: Option<u64>, text: &str) -> Segment {
+        Segment { start_ms, end_ms, text: text.to_string(), confidence: 0.5, speaker }
+    }
+
+    fn sample() -> Transcript {
+        Transcript::from_segments(
+            9,
+            vec![
+                segment(3_723_456, 3_725_000, Some(2), "Roll for initiative."),
+                segment(1_000, 2_000, Some(1), "We should go"),
+                segment(2_500, 4_250, Some(1), "to the tavern."),
+                segment(5_000, 6_000, None, "Agreed."),
+            ],
+            &roster(),
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chester-transcript-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn segments_are_ordered_named_and_joined() {
+        let transcript = sample();
+        let lines: Vec<(u64, u64, &str, &str)> = transcript.entries
+            .iter()
+            .map(|e| (e.start_ms, e.end_ms, e.speaker_name.as_str(), e.text.as_str()))
+            .collect();
+        assert_eq!(lines, vec![
+            (1_000, 4_250, "Thalia", "We should go to the tavern."),
+            (5_000, 6_000, "Unknown speaker", "Agreed."),
+            (3_723_456, 3_725_000, "Player 2", "Roll for initiative."),
+        ]);
+    }
+
+    #[test]
+    fn text_srt_and_webvtt_render_every_entry() {
+        let transcript = sample();
+
+        assert_eq!(transcript.to_text(), "\
+[00:00:01] Thalia: We should go to the tavern.
+[00:00:05] Unknown speaker: Agreed.
+[01:02:03] Player 2: Roll for initiative.
+");
+        assert_eq!(transcript.to_srt(), "\
+1
+00:00:01,000 --> 00:00:04,250
+Thalia: We should go to the tavern.
+
+2
+00:00:05,000 --> 00:00:06,000
+Unknown speaker: Agreed.
+
+3
+01:02:03,456 --> 01:02:05,000
+Player 2: Roll for initiative.
+
+");
+        assert_eq!(transcript.to_vtt(), "\
+WEBVTT
+
+00:00:01.000 --> 00:00:04.250
+<v Thalia>We should go to the tavern.
+
+00:00:05.000 --> 00:00:06.000
+<v Unknown speaker>Agreed.
+
+01:02:03.456 --> 01:02:05.000
+<v Player 2>Roll for initiative.
+
+");
+    }
+
+    #[test]
+    fn saved_json_loads_back_unchanged() {
+        let dir = temp_dir("json");
+        let transcript = sample();
+        let path = transcript.save(&dir).unwrap();
+
+        assert_eq!(Transcript::load(&path, 9, &Roster::default()).unwrap(), transcript);
+        for format in TranscriptFormat::ALL {
+            assert!(dir.join(format!("{TRANSCRIPT_STEM}.{}", format.extension())).is_file());
+        }
+        let srt = dir.join(format!("{TRANSCRIPT_STEM}.srt"));
+        assert!(Transcript::load(&srt, 9, &roster()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn edited_text_loads_with_speakers_recovered() {
+        let dir = temp_dir("text");
+        let path = dir.join("transcript.txt");
+        let edited = format!("{}\nand then some.\n\n", sample().to_text().trim_end().replace("tavern", "inn"));
+        std::fs::write(&path, edited).unwrap();
+
+        let loaded = Transcript::load(&path, 9, &roster()).unwrap();
+        let lines: Vec<(u64, u64, Option<u64>, &str)> = loaded.entries
+            .iter()
+            .map(|e| (e.start_ms, e.end_ms, e.speaker, e.text.as_str()))
+            .collect();
+        // Ends are the next line's start, as the text form has none of its own
+        assert_eq!(lines, vec![
+            (1_000, 5_000, Some(1), "We should go to the inn."),
+            (5_000, 3_723_000, None, "Agreed."),
+            (3_723_000, 3_723_000, Some(2), "Roll for initiative. and then some."),
+        ]);
+        assert_eq!(loaded.to_text().lines().next(), Some("[00:00:01] Thalia: We should go to the inn."));
+
+        std::fs::write(&path, "no timestamp here\n").unwrap();
+        assert!(Transcript::load(&path, 9, &roster()).is_err());
+        std::fs::write(&path, "[00:61:00] Thalia: bad minutes\n").unwrap();
+        assert!(Transcript::load(&path, 9, &roster()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use tracing::{info, warn};
+
+use crate::definitions::Error;
+
+const SNAPSHOT_PREFIX: &str = "jester-";
+const SNAPSHOT_EXTENSION: &str = "sqlite3";
+const SECONDS_PER_DAY: u64 = 86_400;
+const SECONDS_PER_WEEK: u64 = 7 * SECONDS_PER_DAY;
+
+/// Snapshot schedule and rotation, read from the environment (`.env`).
+#[derive(Clone, Debug)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    pub keep_hourly: usize,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+}
+
+impl BackupConfig {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        Self {
+            dir: PathBuf::from(var("BACKUP_DIR", "database/backups".to_string())),
+            // A zero interval would make the schedule's ticker panic
+            interval: Duration::from_secs(60 * var("BACKUP_INTERVAL_MINUTES", 60u64).max(1)),
+            keep_hourly: var("BACKUP_KEEP_HOURLY", 24),
+            keep_daily: var("BACKUP_KEEP_DAILY", 7),
+            keep_weekly: var("BACKUP_KEEP_WEEKLY", 8),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub taken_at: u64,
+    pub size: u64,
+}
+
+impl Snapshot {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Writes a consistent copy of the live database with `VACUUM INTO`, which is safe
+/// while the bot is running, then checks the copy before keeping it.
+pub async fn take_snapshot(db_pool: &SqlitePool, dir: &Path) -> Result<Snapshot, Error> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create backup directory {}: {}", dir.display(), e))?;
+
+    // The nanoseconds keep two snapshots taken within the same second apart
+    let now = now();
+    let taken_at = now.as_secs();
+    let path = dir.join(format!("{SNAPSHOT_PREFIX}{taken_at}-{:09}.{SNAPSHOT_EXTENSION}", now.subsec_nanos()));
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Err(format!("Snapshot {} already exists", path.display()).into());
+    }
+
+    sqlx::query("VACUUM INTO ?1")
+        .bind(path.to_string_lossy().into_owned())
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Snapshot failed: {}", e))?;
+
+    if let Err(e) = check_integrity(&path).await {
+        tokio::fs::remove_file(&path).await.ok();
+        return Err(e);
+    }
+
+    let size = tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+    info!(path = %path.display(), size, "Database snapshot taken");
+
+    Ok(Snapshot { path, taken_at, size })
+}
+
+/// Runs `PRAGMA integrity_check` against a snapshot file without modifying it.
+pub async fn check_integrity(path: &Path) -> Result<(), Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true);
+    let mut conn = SqliteConnection::connect_with(&options)
+        .await
+        .map_err(|e| format!("Failed to open snapshot {}: {}", path.display(), e))?;
+
+    let results: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut conn)
+        .await
+        .map_err(|e| format!("Integrity check failed to run on {}: {}", path.display(), e))?;
+    conn.close().await.ok();
+
+    if results.len() == 1 && results[0] == "ok" {
+        Ok(())
+    } else {
+        Err(format!("Snapshot {} failed its integrity check: {}", path.display(), results.join("; ")).into())
+    }
+}
+
+/// Snapshots in `dir`, newest first.
+pub async fn list_snapshots(dir: &Path) -> Result<Vec<Snapshot>, Error> {
+    let mut snapshots = Vec::new();
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+        Err(e) => return Err(format!("Failed to read backup directory {}: {}", dir.display(), e).into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let taken_at = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(SNAPSHOT_PREFIX))
+            .and_then(|n| n.strip_suffix(&format!(".{SNAPSHOT_EXTENSION}")))
+            .and_then(|n| n.split_once('-').map_or(n, |(secs, _)| secs).parse::<u64>().ok());
+
+        if let Some(taken_at) = taken_at {
+            let size = entry.metadata().await.map(|m| m.len()).unwrap_or(0);
+            snapshots.push(Snapshot { path, taken_at, size });
+        }
+    }
+
+    snapshots.sort_by(|a, b| b.taken_at.cmp(&a.taken_at).then_with(|| b.path.cmp(&a.path)));
+    Ok(snapshots)
+}
+
+/// Picks which snapshots fall outside the rotation. Keeps the newest `keep_hourly`
+/// snapshots, plus the newest snapshot of each of the last `keep_daily` days and
+/// `keep_weekly` weeks. Expects `snapshots` newest first.
+pub fn expired_snapshots<'a>(snapshots: &'a [Snapshot], config: &BackupConfig) -> Vec<&'a Snapshot> {
+    let mut keep: HashSet<&Path> = snapshots
+        .iter()
+        .take(config.keep_hourly)
+        .map(|s| s.path.as_path())
+        .collect();
+
+    for (period, limit) in [(SECONDS_PER_DAY, config.keep_daily), (SECONDS_PER_WEEK, config.keep_weekly)] {
+        let mut seen: HashSet<u64> = HashSet::new();
+        for snapshot in snapshots {
+            if seen.len() >= limit {
+                break;
+            }
+            if seen.insert(snapshot.taken_at / period) {
+                keep.insert(snapshot.path.as_path());
+            }
+        }
+    }
+
+    snapshots
+        .iter()
+        .filter(|s| !keep.contains(s.path.as_path()))
+        .collect()
+}
+
+pub async fn prune_snapshots(config: &BackupConfig) -> Result<usize, Error> {
+    let snapshots = list_snapshots(&config.dir).await?;
+    let expired = expired_snapshots(&snapshots, config);
+
+    for snapshot in &expired {
+        tokio::fs::remove_file(&snapshot.path)
+            .await
+            .map_err(|e| format!("Failed to remove snapshot {}: {}", snapshot.path.display(), e))?;
+        info!(path = %snapshot.path.display(), "Expired snapshot removed");
+    }
+
+    Ok(expired.len())
+}
+
+/// Restores every table from a snapshot into the live database in one transaction.
+///
+/// The current state is snapshotted first, so a restore can itself be undone.
+/// Columns are copied by name, so snapshots from before a schema upgrade still load;
+/// tables the snapshot doesn't have are left empty.
+pub async fn restore_snapshot(
+    db_pool: &SqlitePool,
+    config: &BackupConfig,
+    name: &str,
+) -> Result<Snapshot, Error> {
+    let snapshot = list_snapshots(&config.dir)
+        .await?
+        .into_iter()
+        .find(|s| s.name() == name)
+        .ok_or_else(|| format!("No snapshot named `{}`", name))?;
+
+    check_integrity(&snapshot.path).await?;
+    let safety = take_snapshot(db_pool, &config.dir).await?;
+    info!(safety = %safety.path.display(), "Pre-restore snapshot taken");
+
+    let mut conn = db_pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+    sqlx::query("ATTACH DATABASE ?1 AS snapshot")
+        .bind(snapshot.path.to_string_lossy().into_owned())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to attach snapshot: {}", e))?;
+
+    let result = copy_attached_tables(&mut conn).await;
+
+    sqlx::query("DETACH DATABASE snapshot").execute(&mut *conn).await.ok();
+    sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await.ok();
+
+    result?;
+    info!(path = %snapshot.path.display(), "Database restored from snapshot");
+    Ok(snapshot)
+}
+
+async fn copy_attached_tables(conn: &mut SqliteConnection) -> Result<(), Error> {
+    // Virtual tables are copied through their columns, never through their shadow tables
+    let tables: Vec<(String, bool)> = sqlx::query_as(
+        "SELECT name, name IN (SELECT name FROM pragma_table_list WHERE schema = 'snapshot')
+         FROM pragma_table_list
+         WHERE schema = 'main' AND type IN ('table', 'virtual')
+           AND (name NOT LIKE 'sqlite_%' OR name = 'sqlite_sequence')",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut tx = conn.begin().await?;
+    for (table, in_snapshot) in &tables {
+        let quoted = format!("\"{}\"", table.replace('"', "\"\""));
+        sqlx::query(&format!("DELETE FROM main.{quoted}"))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to clear table `{}`: {}", table, e))?;
+        if !in_snapshot {
+            warn!(table, "Table isn't in the snapshot; left empty");
+            continue;
+        }
+
+        let columns: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM pragma_table_info(?1, 'snapshot')
+             WHERE name IN (SELECT name FROM pragma_table_info(?1, 'main'))",
+        )
+        .bind(table)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if columns.is_empty() {
+            warn!(table, "Snapshot table has no matching columns; left empty");
+            continue;
+        }
+        let columns = columns
+            .iter()
+            .map(|c| format!("\"{}\"", c.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        sqlx::query(&format!(
+            "INSERT INTO main.{quoted} ({columns}) SELECT {columns} FROM snapshot.{quoted}"
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to restore table `{}`: {}", table, e))?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Snapshots the database on every interval and rotates old snapshots out.
+/// Runs until the bot shuts down.
+pub async fn run_backup_schedule(db_pool: SqlitePool, config: BackupConfig) {
+    let mut ticker = tokio::time::interval(config.interval);
+    loop {
+        ticker.tick().await;
+
+        if let Err(e) = take_snapshot(&db_pool, &config.dir).await {
+            warn!(error = %e, "Scheduled snapshot failed");
+            continue;
+        }
+        match prune_snapshots(&config).await {
+            Ok(removed) if removed > 0 => info!(removed, "Snapshot rotation complete"),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "Snapshot rotation failed"),
+        }
+    }
+}
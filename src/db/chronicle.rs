@@ -0,0 +1,1005 @@
+use poise::serenity_prelude::GuildId;
+use sqlx::{FromRow, SqlitePool};
+
+use crate::db::repository::guild_key;
+use crate::definitions::Error;
+
+#[derive(Clone, Debug, FromRow)]
+pub struct SessionRow {
+    pub id: i64,
+    pub guild_id: i64,
+    pub text_channel_id: i64,
+    pub campaign_id: Option<i64>,
+    pub session_number: Option<i64>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+    pub storage_dir: String,
+    pub approved_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct CampaignRow {
+    pub id: i64,
+    pub name: String,
+    pub obsidian_folder: String,
+    pub channel_id: Option<i64>,
+    pub archived: bool,
+    /// Retention overrides in days; `None` inherits the server rule, negative keeps forever
+    pub audio_retention_days: Option<i64>,
+    pub transcript_retention_days: Option<i64>,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct ConsentLogRow {
+    pub user_id: i64,
+    pub consented: bool,
+    pub source: String,
+    pub session_id: Option<i64>,
+    pub changed_at: i64,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct DeletionRow {
+    pub session_id: i64,
+    pub kind: String,
+    pub path: String,
+    pub bytes: i64,
+    pub rule: String,
+    pub deleted_at: i64,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct GlossaryRow {
+    pub term: String,
+    pub kind: String,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct EntityRow {
+    pub id: i64,
+    pub kind: String,
+    pub name: String,
+    pub status: Option<String>,
+    pub description: Option<String>,
+    pub details: Option<String>,
+}
+
+/// An entity's appearance in one session, with the session it's from.
+#[derive(Clone, Debug, FromRow)]
+pub struct EntityMentionRow {
+    pub session_id: i64,
+    pub status: Option<String>,
+    pub description: Option<String>,
+    pub details: Option<String>,
+    pub session_number: Option<i64>,
+    pub started_at: i64,
+}
+
+/// An entity as one session's extraction described it.
+#[derive(Clone, Debug)]
+pub struct NewEntityMention {
+    pub kind: &'static str,
+    pub name: String,
+    pub status: Option<String>,
+    pub description: Option<String>,
+    pub details: Option<String>,
+}
+
+/// A transcript line matching a search, with the session it's from.
+#[derive(Clone, Debug, FromRow)]
+pub struct TranscriptHitRow {
+    pub session_id: i64,
+    pub session_number: Option<i64>,
+    pub speaker_name: String,
+    pub start_ms: i64,
+    /// The matching text around the hit, matches in bold
+    pub snippet: String,
+}
+
+/// A transcript line to index for search.
+#[derive(Clone, Debug)]
+pub struct NewTranscriptLine {
+    pub speaker: Option<u64>,
+    pub speaker_name: String,
+    pub start_ms: u64,
+    pub text: String,
+}
+
+/// One processing stage of a session.
+#[derive(Clone, Debug, FromRow)]
+pub struct JobRow {
+    pub stage: String,
+    pub status: String,
+    pub input_hash: Option<String>,
+    pub output_hash: Option<String>,
+    pub error: Option<String>,
+    pub finished_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, FromRow)]
+pub struct CharacterRow {
+    pub user_id: i64,
+    pub character_name: String,
+    pub character_class: Option<String>,
+}
+
+/// Creates a session, numbering it after the campaign's last one. Returns the
+/// session ID and its number within the campaign.
+pub async fn insert_session(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    voice_channel_id: i64,
+    text_channel_id: i64,
+    campaign_id: Option<i64>,
+    started_at: i64,
+) -> Result<(i64, Option<i64>), Error> {
+    let inserted = sqlx::query_as(
+        "INSERT INTO chronicle_sessions
+            (guild_id, voice_channel_id, text_channel_id, campaign_id, session_number, started_at, state, storage_dir)
+         VALUES (
+            ?1, ?2, ?3, ?4,
+            CASE WHEN ?4 IS NULL THEN NULL ELSE (
+                SELECT COALESCE(MAX(session_number), 0) + 1 FROM chronicle_sessions WHERE campaign_id = ?4
+            ) END,
+            ?5, 'recording', ''
+         )
+         RETURNING id, session_number",
+    )
+    .bind(guild_key(guild_id))
+    .bind(voice_channel_id)
+    .bind(text_channel_id)
+    .bind(campaign_id)
+    .bind(started_at)
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| format!("Failed to create chronicle session: {}", e))?;
+    Ok(inserted)
+}
+
+pub async fn set_session_storage_dir(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    storage_dir: &str,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE chronicle_sessions SET storage_dir = ?1 WHERE id = ?2")
+        .bind(storage_dir)
+        .bind(session_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to update session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+pub async fn set_session_state(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    state: &str,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE chronicle_sessions SET state = ?1 WHERE id = ?2")
+        .bind(state)
+        .bind(session_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to update session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+pub async fn end_session(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    ended_at: i64,
+    state: &str,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE chronicle_sessions SET state = ?1, ended_at = ?2 WHERE id = ?3")
+        .bind(state)
+        .bind(ended_at)
+        .bind(session_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to end session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+/// Sessions left recording by a crash or restart can't be resumed; mark them so.
+pub async fn mark_interrupted_sessions(db_pool: &SqlitePool, now: i64) -> Result<u64, Error> {
+    let result = sqlx::query(
+        "UPDATE chronicle_sessions SET state = 'interrupted', ended_at = ?1
+         WHERE state IN ('recording', 'paused')",
+    )
+    .bind(now)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to close interrupted sessions: {}", e))?;
+    Ok(result.rows_affected())
+}
+
+pub async fn fetch_session(
+    db_pool: &SqlitePool,
+    session_id: i64,
+) -> Result<Option<SessionRow>, Error> {
+    sqlx::query_as("SELECT * FROM chronicle_sessions WHERE id = ?1")
+        .bind(session_id)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Failed to fetch session {}: {}", session_id, e).into())
+}
+
+pub async fn add_session_participants(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    user_ids: &[u64],
+) -> Result<(), Error> {
+    for user_id in user_ids {
+        sqlx::query("INSERT OR IGNORE INTO chronicle_participants (session_id, user_id) VALUES (?1, ?2)")
+            .bind(session_id)
+            .bind(*user_id as i64)
+            .execute(db_pool)
+            .await
+            .map_err(|e| format!("Failed to add participant to session {}: {}", session_id, e))?;
+    }
+    Ok(())
+}
+
+pub async fn fetch_session_participants(
+    db_pool: &SqlitePool,
+    session_id: i64,
+) -> Result<Vec<u64>, Error> {
+    let ids: Vec<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM chronicle_participants WHERE session_id = ?1 ORDER BY user_id",
+    )
+    .bind(session_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch participants for session {}: {}", session_id, e))?;
+    Ok(ids.into_iter().map(|id| id as u64).collect())
+}
+
+pub async fn insert_campaign(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    name: &str,
+    obsidian_folder: &str,
+    channel_id: Option<i64>,
+    created_at: i64,
+) -> Result<i64, Error> {
+    let id = sqlx::query_scalar(
+        "INSERT INTO campaigns (guild_id, name, obsidian_folder, channel_id, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         RETURNING id",
+    )
+    .bind(guild_key(guild_id))
+    .bind(name)
+    .bind(obsidian_folder)
+    .bind(channel_id)
+    .bind(created_at)
+    .fetch_one(db_pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            format!("A campaign named `{}` already exists in this server.", name)
+        }
+        e => format!("Failed to create campaign `{}`: {}", name, e),
+    })?;
+    Ok(id)
+}
+
+pub async fn fetch_campaigns(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    include_archived: bool,
+) -> Result<Vec<CampaignRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM campaigns
+         WHERE guild_id = ?1 AND (?2 OR archived = 0)
+         ORDER BY archived, created_at DESC",
+    )
+    .bind(guild_key(guild_id))
+    .bind(include_archived)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch campaigns: {}", e).into())
+}
+
+pub async fn fetch_campaign(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+) -> Result<Option<CampaignRow>, Error> {
+    sqlx::query_as("SELECT * FROM campaigns WHERE id = ?1")
+        .bind(campaign_id)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Failed to fetch campaign {}: {}", campaign_id, e).into())
+}
+
+pub async fn lookup_campaign(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    name: &str,
+) -> Result<Option<CampaignRow>, Error> {
+    sqlx::query_as("SELECT * FROM campaigns WHERE guild_id = ?1 AND LOWER(name) = LOWER(?2)")
+        .bind(guild_key(guild_id))
+        .bind(name)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Failed to look up campaign `{}`: {}", name, e).into())
+}
+
+pub async fn require_campaign(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    name: &str,
+) -> Result<CampaignRow, Error> {
+    lookup_campaign(db_pool, guild_id, name)
+        .await?
+        .ok_or_else(|| format!("No campaign named `{}` in this server.", name).into())
+}
+
+/// The newest unarchived campaign played in either channel, if any.
+pub async fn find_active_campaign(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    text_channel_id: i64,
+    voice_channel_id: i64,
+) -> Result<Option<CampaignRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM campaigns
+         WHERE guild_id = ?1 AND archived = 0 AND channel_id IN (?2, ?3)
+         ORDER BY created_at DESC
+         LIMIT 1",
+    )
+    .bind(guild_key(guild_id))
+    .bind(text_channel_id)
+    .bind(voice_channel_id)
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| format!("Failed to find the channel's campaign: {}", e).into())
+}
+
+pub async fn set_campaign_archived(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    archived: bool,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE campaigns SET archived = ?1 WHERE id = ?2")
+        .bind(archived)
+        .bind(campaign_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to update campaign {}: {}", campaign_id, e))?;
+    Ok(())
+}
+
+pub async fn upsert_character(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    user_id: u64,
+    character_name: &str,
+    character_class: Option<&str>,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO campaign_characters (campaign_id, user_id, character_name, character_class)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (campaign_id, user_id)
+         DO UPDATE SET character_name = excluded.character_name, character_class = excluded.character_class",
+    )
+    .bind(campaign_id)
+    .bind(user_id as i64)
+    .bind(character_name)
+    .bind(character_class)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to set character for campaign {}: {}", campaign_id, e))?;
+    Ok(())
+}
+
+pub async fn fetch_characters(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+) -> Result<Vec<CharacterRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM campaign_characters WHERE campaign_id = ?1 ORDER BY character_name",
+    )
+    .bind(campaign_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch characters for campaign {}: {}", campaign_id, e).into())
+}
+
+/// The campaign's sessions in play order.
+pub async fn fetch_campaign_sessions(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+) -> Result<Vec<SessionRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM chronicle_sessions WHERE campaign_id = ?1 ORDER BY session_number",
+    )
+    .bind(campaign_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch sessions for campaign {}: {}", campaign_id, e).into())
+}
+
+/// The user's recording consent in the guild; `None` if they haven't been asked yet.
+pub async fn fetch_consent(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    user_id: u64,
+) -> Result<Option<bool>, Error> {
+    sqlx::query_scalar("SELECT consented FROM chronicle_consent WHERE guild_id = ?1 AND user_id = ?2")
+        .bind(guild_key(guild_id))
+        .bind(user_id as i64)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Failed to fetch recording consent: {}", e).into())
+}
+
+/// Users in the guild who have agreed to be recorded.
+pub async fn fetch_consented_users(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<u64>, Error> {
+    let users: Vec<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM chronicle_consent WHERE guild_id = ?1 AND consented = 1",
+    )
+    .bind(guild_key(guild_id))
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch recording consent: {}", e))?;
+    Ok(users.into_iter().map(|u| u as u64).collect())
+}
+
+/// Records a consent decision and its audit entry together. `source` says how it
+/// was made (`prompt` or `command`).
+pub async fn set_consent(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    user_id: u64,
+    consented: bool,
+    source: &str,
+    session_id: Option<i64>,
+    now: i64,
+) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO chronicle_consent (guild_id, user_id, consented, updated_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (guild_id, user_id)
+         DO UPDATE SET consented = excluded.consented, updated_at = excluded.updated_at",
+    )
+    .bind(guild_key(guild_id))
+    .bind(user_id as i64)
+    .bind(consented)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to save recording consent: {}", e))?;
+
+    sqlx::query(
+        "INSERT INTO chronicle_consent_log (guild_id, user_id, consented, source, session_id, changed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )
+    .bind(guild_key(guild_id))
+    .bind(user_id as i64)
+    .bind(consented)
+    .bind(source)
+    .bind(session_id)
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to log recording consent: {}", e))?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Consent changes in the guild, newest first, optionally for one user.
+pub async fn fetch_consent_log(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    user_id: Option<u64>,
+    limit: i64,
+) -> Result<Vec<ConsentLogRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM chronicle_consent_log
+         WHERE guild_id = ?1 AND (?2 IS NULL OR user_id = ?2)
+         ORDER BY changed_at DESC, id DESC
+         LIMIT ?3",
+    )
+    .bind(guild_key(guild_id))
+    .bind(user_id.map(|u| u as i64))
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch the consent log: {}", e).into())
+}
+
+/// Marks the session's summary as approved; retention rules count from here.
+pub async fn approve_session(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    approved_at: i64,
+) -> Result<(), Error> {
+    sqlx::query("UPDATE chronicle_sessions SET approved_at = ?1 WHERE id = ?2")
+        .bind(approved_at)
+        .bind(session_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to approve session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+/// Finished sessions, optionally in one guild, oldest first.
+pub async fn fetch_finished_sessions(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+) -> Result<Vec<SessionRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM chronicle_sessions
+         WHERE ended_at IS NOT NULL AND (?1 IS NULL OR guild_id = ?1)
+         ORDER BY ended_at",
+    )
+    .bind(guild_id.map(guild_key))
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch finished sessions: {}", e).into())
+}
+
+/// Sets both retention overrides; `None` inherits the server rule, negative keeps forever.
+pub async fn set_campaign_retention(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    audio_days: Option<i64>,
+    transcript_days: Option<i64>,
+) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE campaigns SET audio_retention_days = ?1, transcript_retention_days = ?2 WHERE id = ?3",
+    )
+    .bind(audio_days)
+    .bind(transcript_days)
+    .bind(campaign_id)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update retention for campaign {}: {}", campaign_id, e))?;
+    Ok(())
+}
+
+pub async fn insert_deletion(
+    db_pool: &SqlitePool,
+    session: &SessionRow,
+    kind: &str,
+    path: &str,
+    bytes: u64,
+    rule: &str,
+    deleted_at: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO chronicle_deletions (guild_id, session_id, kind, path, bytes, rule, deleted_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )
+    .bind(session.guild_id)
+    .bind(session.id)
+    .bind(kind)
+    .bind(path)
+    .bind(bytes as i64)
+    .bind(rule)
+    .bind(deleted_at)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to log deletion of {}: {}", path, e))?;
+    Ok(())
+}
+
+/// The guild's retention deletions, newest first.
+pub async fn fetch_deletions(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    limit: i64,
+) -> Result<Vec<DeletionRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM chronicle_deletions WHERE guild_id = ?1 ORDER BY deleted_at DESC, id DESC LIMIT ?2",
+    )
+    .bind(guild_key(guild_id))
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch the deletion log: {}", e).into())
+}
+
+/// Adds a term, or updates its kind if the campaign already has it.
+pub async fn upsert_glossary_term(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    term: &str,
+    kind: &str,
+    created_at: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO campaign_glossary (campaign_id, term, kind, created_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (campaign_id, term) DO UPDATE SET term = excluded.term, kind = excluded.kind",
+    )
+    .bind(campaign_id)
+    .bind(term)
+    .bind(kind)
+    .bind(created_at)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to add `{}` to the glossary: {}", term, e))?;
+    Ok(())
+}
+
+/// Returns whether the term was in the glossary.
+pub async fn delete_glossary_term(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    term: &str,
+) -> Result<bool, Error> {
+    let result = sqlx::query("DELETE FROM campaign_glossary WHERE campaign_id = ?1 AND term = ?2")
+        .bind(campaign_id)
+        .bind(term)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to remove `{}` from the glossary: {}", term, e))?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn fetch_glossary(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+) -> Result<Vec<GlossaryRow>, Error> {
+    sqlx::query_as("SELECT * FROM campaign_glossary WHERE campaign_id = ?1 ORDER BY kind, term")
+        .bind(campaign_id)
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Failed to fetch the glossary for campaign {}: {}", campaign_id, e).into())
+}
+
+/// Replaces everything recorded from the session, so reprocessing it doesn't
+/// leave stale mentions behind, then brings each entity's current state up to
+/// date from its latest mentions. Entities no session mentions any more are removed.
+pub async fn replace_session_entities(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    session_id: i64,
+    mentions: &[NewEntityMention],
+    now: i64,
+) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+    sqlx::query("DELETE FROM campaign_entity_mentions WHERE session_id = ?1")
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to clear entities of session {}: {}", session_id, e))?;
+
+    for mention in mentions {
+        let entity_id: i64 = sqlx::query_scalar(
+            "INSERT INTO campaign_entities (campaign_id, kind, name, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (campaign_id, kind, name) DO UPDATE SET updated_at = excluded.updated_at
+             RETURNING id",
+        )
+        .bind(campaign_id)
+        .bind(mention.kind)
+        .bind(&mention.name)
+        .bind(now)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to save entity `{}`: {}", mention.name, e))?;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO campaign_entity_mentions (entity_id, session_id, status, description, details)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )
+        .bind(entity_id)
+        .bind(session_id)
+        .bind(&mention.status)
+        .bind(&mention.description)
+        .bind(&mention.details)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to save a mention of `{}`: {}", mention.name, e))?;
+    }
+
+    sqlx::query(
+        "DELETE FROM campaign_entities
+         WHERE campaign_id = ?1
+           AND NOT EXISTS (SELECT 1 FROM campaign_entity_mentions m WHERE m.entity_id = campaign_entities.id)",
+    )
+    .bind(campaign_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to prune entities of campaign {}: {}", campaign_id, e))?;
+
+    // Each field comes from the latest session in play order that gave one
+    let latest = |column: &str| format!(
+        "(SELECT m.{column} FROM campaign_entity_mentions m
+          JOIN chronicle_sessions s ON s.id = m.session_id
+          WHERE m.entity_id = campaign_entities.id AND m.{column} IS NOT NULL
+          ORDER BY COALESCE(s.session_number, 0) DESC, s.started_at DESC
+          LIMIT 1)",
+    );
+    sqlx::query(&format!(
+        "UPDATE campaign_entities SET status = {}, description = {}, details = {} WHERE campaign_id = ?1",
+        latest("status"),
+        latest("description"),
+        latest("details"),
+    ))
+    .bind(campaign_id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| format!("Failed to update entities of campaign {}: {}", campaign_id, e))?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The campaign's entities, optionally of one kind, by name.
+pub async fn fetch_entities(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    kind: Option<&str>,
+) -> Result<Vec<EntityRow>, Error> {
+    sqlx::query_as(
+        "SELECT * FROM campaign_entities WHERE campaign_id = ?1 AND (?2 IS NULL OR kind = ?2) ORDER BY name",
+    )
+    .bind(campaign_id)
+    .bind(kind)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch entities for campaign {}: {}", campaign_id, e).into())
+}
+
+/// Entities named exactly `name`, ignoring case; one per kind at most.
+pub async fn lookup_entities(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    name: &str,
+) -> Result<Vec<EntityRow>, Error> {
+    sqlx::query_as("SELECT * FROM campaign_entities WHERE campaign_id = ?1 AND name = ?2 ORDER BY kind")
+        .bind(campaign_id)
+        .bind(name.trim())
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Failed to look up `{}`: {}", name, e).into())
+}
+
+/// Entity names containing `partial` in the guild's unarchived campaigns, or in
+/// one campaign, for autocomplete and suggestions.
+pub async fn search_entity_names(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    campaign_id: Option<i64>,
+    partial: &str,
+    limit: i64,
+) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar(
+        "SELECT DISTINCT e.name FROM campaign_entities e
+         JOIN campaigns c ON c.id = e.campaign_id
+         WHERE c.guild_id = ?1
+           AND ((?2 IS NULL AND c.archived = 0) OR e.campaign_id = ?2)
+           AND INSTR(LOWER(e.name), LOWER(?3)) > 0
+         ORDER BY e.name
+         LIMIT ?4",
+    )
+    .bind(guild_key(guild_id))
+    .bind(campaign_id)
+    .bind(partial.trim())
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to search entities: {}", e).into())
+}
+
+/// Every session the entity appeared in, in play order.
+pub async fn fetch_entity_mentions(
+    db_pool: &SqlitePool,
+    entity_id: i64,
+) -> Result<Vec<EntityMentionRow>, Error> {
+    sqlx::query_as(
+        "SELECT m.*, s.session_number, s.started_at
+         FROM campaign_entity_mentions m
+         JOIN chronicle_sessions s ON s.id = m.session_id
+         WHERE m.entity_id = ?1
+         ORDER BY COALESCE(s.session_number, 0), s.started_at",
+    )
+    .bind(entity_id)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch mentions of entity {}: {}", entity_id, e).into())
+}
+
+/// Replaces the session's lines in the search index.
+pub async fn index_transcript(
+    db_pool: &SqlitePool,
+    session: &SessionRow,
+    lines: &[NewTranscriptLine],
+) -> Result<(), Error> {
+    let mut tx = db_pool.begin().await?;
+    sqlx::query("DELETE FROM chronicle_transcript_fts WHERE session_id = ?1")
+        .bind(session.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to clear the search index for session {}: {}", session.id, e))?;
+
+    for line in lines {
+        sqlx::query(
+            "INSERT INTO chronicle_transcript_fts (text, speaker_name, session_id, campaign_id, speaker, start_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(&line.text)
+        .bind(&line.speaker_name)
+        .bind(session.id)
+        .bind(session.campaign_id)
+        .bind(line.speaker.map(|s| s as i64))
+        .bind(line.start_ms as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to index session {}: {}", session.id, e))?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_transcript_index(db_pool: &SqlitePool, session_id: i64) -> Result<(), Error> {
+    sqlx::query("DELETE FROM chronicle_transcript_fts WHERE session_id = ?1")
+        .bind(session_id)
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to remove session {} from the search index: {}", session_id, e))?;
+    Ok(())
+}
+
+pub async fn is_transcript_indexed(db_pool: &SqlitePool, session_id: i64) -> Result<bool, Error> {
+    let indexed: i64 = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM chronicle_transcript_fts WHERE session_id = ?1)")
+        .bind(session_id)
+        .fetch_one(db_pool)
+        .await
+        .map_err(|e| format!("Failed to check the search index for session {}: {}", session_id, e))?;
+    Ok(indexed != 0)
+}
+
+/// Best matches first. `query` is an FTS5 expression; `session_number` is the
+/// session's position in the campaign.
+pub async fn search_transcripts(
+    db_pool: &SqlitePool,
+    campaign_id: i64,
+    query: &str,
+    speaker: Option<u64>,
+    session_number: Option<i64>,
+    limit: i64,
+) -> Result<Vec<TranscriptHitRow>, Error> {
+    sqlx::query_as(
+        "SELECT chronicle_transcript_fts.session_id, s.session_number, speaker_name, start_ms,
+                snippet(chronicle_transcript_fts, 0, '**', '**', '…', 24) AS snippet
+         FROM chronicle_transcript_fts
+         JOIN chronicle_sessions s ON s.id = chronicle_transcript_fts.session_id
+         WHERE chronicle_transcript_fts MATCH ?1
+           AND chronicle_transcript_fts.campaign_id = ?2
+           AND (?3 IS NULL OR speaker = ?3)
+           AND (?4 IS NULL OR s.session_number = ?4)
+         ORDER BY rank
+         LIMIT ?5",
+    )
+    .bind(query)
+    .bind(campaign_id)
+    .bind(speaker.map(|s| s as i64))
+    .bind(session_number)
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to search transcripts: {}", e).into())
+}
+
+pub async fn fetch_jobs(db_pool: &SqlitePool, session_id: i64) -> Result<Vec<JobRow>, Error> {
+    sqlx::query_as("SELECT * FROM chronicle_jobs WHERE session_id = ?1")
+        .bind(session_id)
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Failed to fetch jobs for session {}: {}", session_id, e).into())
+}
+
+/// Marks `stages` as waiting to run. What their last run recorded is kept, so an
+/// unchanged stage can still be recognised as up to date and a failed one can't.
+pub async fn queue_jobs(db_pool: &SqlitePool, session_id: i64, stages: &[&str]) -> Result<(), Error> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to queue jobs for session {}: {}", session_id, e))?;
+    for stage in stages {
+        sqlx::query(
+            "INSERT INTO chronicle_jobs (session_id, stage, status) VALUES (?1, ?2, 'pending')
+             ON CONFLICT (session_id, stage) DO UPDATE SET status = 'pending'",
+        )
+        .bind(session_id)
+        .bind(stage)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to queue `{}` for session {}: {}", stage, session_id, e))?;
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to queue jobs for session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+pub async fn start_job(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    stage: &str,
+    inputs: &str,
+    input_hash: &str,
+    now: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE chronicle_jobs
+         SET status = 'running', inputs = ?3, input_hash = ?4, output_hash = NULL, error = NULL,
+             started_at = ?5, finished_at = NULL
+         WHERE session_id = ?1 AND stage = ?2",
+    )
+    .bind(session_id)
+    .bind(stage)
+    .bind(inputs)
+    .bind(input_hash)
+    .bind(now)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to start `{}` for session {}: {}", stage, session_id, e))?;
+    Ok(())
+}
+
+/// Records how a stage ended: `done` or `skipped` with its output's hash, or
+/// `failed` with the error.
+pub async fn finish_job(
+    db_pool: &SqlitePool,
+    session_id: i64,
+    stage: &str,
+    status: &str,
+    output_hash: Option<&str>,
+    error: Option<&str>,
+    now: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE chronicle_jobs SET status = ?3, output_hash = ?4, error = ?5, finished_at = ?6
+         WHERE session_id = ?1 AND stage = ?2",
+    )
+    .bind(session_id)
+    .bind(stage)
+    .bind(status)
+    .bind(output_hash)
+    .bind(error)
+    .bind(now)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to finish `{}` for session {}: {}", stage, session_id, e))?;
+    Ok(())
+}
+
+/// Fails every stage still waiting or running, for a run that ended before reaching them.
+pub async fn fail_unfinished_jobs(db_pool: &SqlitePool, session_id: i64, error: &str, now: i64) -> Result<(), Error> {
+    sqlx::query(
+        "UPDATE chronicle_jobs SET status = 'failed', error = ?2, finished_at = ?3
+         WHERE session_id = ?1 AND status IN ('pending', 'running')",
+    )
+    .bind(session_id)
+    .bind(error)
+    .bind(now)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fail the jobs of session {}: {}", session_id, e))?;
+    Ok(())
+}
+
+/// Sessions with stages left to run, e.g. after a restart cut processing short.
+/// Sessions stopped by a failed stage wait for `/chronicle reprocess` instead.
+pub async fn fetch_unfinished_job_sessions(db_pool: &SqlitePool) -> Result<Vec<i64>, Error> {
+    sqlx::query_scalar(
+        "SELECT DISTINCT session_id FROM chronicle_jobs
+         WHERE status IN ('pending', 'running')
+           AND session_id NOT IN (SELECT session_id FROM chronicle_jobs WHERE status = 'failed')
+         ORDER BY session_id",
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|e| format!("Failed to fetch unfinished jobs: {}", e).into())
+}
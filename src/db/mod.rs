@@ -1 +1,4 @@
-pub mod repository;
\ No newline at end of file
+pub mod backup;
+pub mod chronicle;
+pub mod repository;
+pub mod schema;
\ No newline at end of file
@@ -1,8 +1,48 @@
+use poise::serenity_prelude::GuildId;
 use serde_json::Value;
 use sqlx::{SqlitePool, Row};
 
 use crate::definitions::{Error, MetadataKind, TrackInfo, VideoId};
 
+/// The tracks a guild can see, with its metadata overrides applied, plus the tags
+/// that apply to them there. Queries prefix this with `WITH` and bind the guild as `?1`.
+///
+/// A track is visible when the guild has it in its own library, or when it is in the
+/// shared pool and the guild hasn't opted out of that pool. Hidden tracks never show.
+/// Overrides only change how a visible track looks, never whether it's visible.
+const GUILD_LIBRARY: &str = "
+    library AS (
+        SELECT tracks.id,
+               tracks.upload_date,
+               tracks.yt_title,
+               COALESCE(overrides.track_title, tracks.track_title) AS track_title,
+               COALESCE(overrides.artist_id, tracks.artist_id) AS artist_id,
+               COALESCE(overrides.origin_id, tracks.origin_id) AS origin_id,
+               COALESCE(overrides.tags_overridden, 0) AS tags_overridden
+        FROM tracks
+        LEFT JOIN guild_tracks
+               ON guild_tracks.track_id = tracks.id AND guild_tracks.guild_id = ?1
+        LEFT JOIN guild_track_overrides AS overrides
+               ON overrides.track_id = tracks.id AND overrides.guild_id = ?1
+        LEFT JOIN guild_settings ON guild_settings.guild_id = ?1
+        WHERE COALESCE(overrides.hidden, 0) = 0
+          AND (guild_tracks.track_id IS NOT NULL
+               OR (tracks.shared = 1 AND COALESCE(guild_settings.share_global, 1) = 1))
+    ),
+    library_tags AS (
+        SELECT track_tags.track_id, track_tags.tag_id
+        FROM track_tags
+        JOIN library ON library.id = track_tags.track_id
+        WHERE library.tags_overridden = 0
+        UNION
+        SELECT track_id, tag_id FROM guild_track_tags WHERE guild_id = ?1
+    )";
+
+/// Guild IDs are stored as SQLite integers; snowflakes fit comfortably in an i64.
+pub fn guild_key(guild_id: GuildId) -> i64 {
+    guild_id.get() as i64
+}
+
 pub async fn get_or_insert_metadata_id(
     db_pool: &SqlitePool,
     kind: MetadataKind,
@@ -33,6 +73,8 @@ pub async fn get_or_insert_metadata_id(
     }
 }
 
+/// Adds a track to the base library. A track with an owner stays private to that
+/// guild until it shares it; one without goes straight into the shared pool.
 pub async fn insert_new_track(
     db_pool: &SqlitePool,
     video_id: &VideoId,
@@ -40,6 +82,7 @@ pub async fn insert_new_track(
     title: &str,
     artist_id: i64,
     origin_id: i64,
+    owner: Option<GuildId>,
 ) -> Result<(), Error> {
     sqlx::query(
         "INSERT INTO tracks (
@@ -48,9 +91,11 @@ pub async fn insert_new_track(
             yt_title,
             track_title,
             artist_id,
-            origin_id
+            origin_id,
+            shared,
+            owner_guild_id
         )
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
     )
     .bind(video_id.as_str())
     .bind(
@@ -66,24 +111,105 @@ pub async fn insert_new_track(
     .bind(title)
     .bind(artist_id)
     .bind(origin_id)
+    .bind(owner.is_none())
+    .bind(owner.map(guild_key))
     .execute(db_pool)
     .await?;
 
     Ok(())
 }
 
-pub async fn fetch_library_all(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>, Error> {
-    let rows = sqlx::query(
-        "SELECT tracks.track_title, artists.artist, origins.origin,
-                GROUP_CONCAT(tags.tag, ', ') AS tags
+pub async fn track_exists(
+    db_pool: &SqlitePool,
+    video_id: &VideoId,
+) -> Result<bool, Error> {
+    let found: Option<String> = sqlx::query_scalar("SELECT id FROM tracks WHERE id = ?1")
+        .bind(video_id.as_str())
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Database select failed: {}", e))?;
+    Ok(found.is_some())
+}
+
+/// Whether the guild can see the track, or could if it hadn't hidden it.
+pub async fn track_available(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    video_id: &VideoId,
+) -> Result<bool, Error> {
+    let found: Option<String> = sqlx::query_scalar(
+        "SELECT tracks.id
          FROM tracks
+         LEFT JOIN guild_tracks
+                ON guild_tracks.track_id = tracks.id AND guild_tracks.guild_id = ?1
+         LEFT JOIN guild_settings ON guild_settings.guild_id = ?1
+         WHERE tracks.id = ?2
+           AND (guild_tracks.track_id IS NOT NULL
+                OR (tracks.shared = 1 AND COALESCE(guild_settings.share_global, 1) = 1))",
+    )
+    .bind(guild_key(guild_id))
+    .bind(video_id.as_str())
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| format!("Database select failed: {}", e))?;
+    Ok(found.is_some())
+}
+
+/// The guild that added the track, or `None` for the base library. Errors if the
+/// track doesn't exist.
+pub async fn fetch_track_owner(
+    db_pool: &SqlitePool,
+    video_id: &VideoId,
+) -> Result<Option<GuildId>, Error> {
+    let owner: Option<i64> = sqlx::query_scalar("SELECT owner_guild_id FROM tracks WHERE id = ?1")
+        .bind(video_id.as_str())
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Database select failed: {}", e))?
+        .ok_or("Track could not be found in the database.")?;
+    Ok(owner.map(|id| GuildId::new(id as u64)))
+}
+
+/// Puts the track in the guild's own library, unhiding it if the guild had hidden it.
+pub async fn add_guild_track(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    video_id: &VideoId,
+) -> Result<(), Error> {
+    sqlx::query("INSERT OR IGNORE INTO guild_tracks (guild_id, track_id) VALUES (?1, ?2)")
+        .bind(guild_key(guild_id))
+        .bind(video_id.as_str())
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to add track {} to guild library: {}", video_id.as_str(), e))?;
+
+    sqlx::query("UPDATE guild_track_overrides SET hidden = 0 WHERE guild_id = ?1 AND track_id = ?2")
+        .bind(guild_key(guild_id))
+        .bind(video_id.as_str())
+        .execute(db_pool)
+        .await
+        .map_err(|e| format!("Failed to add track {} to guild library: {}", video_id.as_str(), e))?;
+    Ok(())
+}
+
+pub async fn fetch_library_all(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<Vec<String>>, Error> {
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT tracks.track_title, artists.artist, origins.origin,
+                GROUP_CONCAT(tags.tag, ', ') AS tags
+         FROM library AS tracks
          LEFT JOIN artists ON tracks.artist_id = artists.id
          LEFT JOIN origins ON tracks.origin_id = origins.id
-         LEFT JOIN track_tags ON tracks.id = track_tags.track_id
+         LEFT JOIN library_tags AS track_tags ON tracks.id = track_tags.track_id
          LEFT JOIN tags ON track_tags.tag_id = tags.id
          GROUP BY tracks.id
-         ORDER BY tracks.track_title",
-    )
+         ORDER BY tracks.track_title"
+    );
+    let rows = sqlx::query(&sql)
+    .bind(guild_key(guild_id))
     .fetch_all(db_pool)
     .await
     .map_err(|e| format!("Database query failed: {}", e))?;
@@ -96,13 +222,19 @@ pub async fn fetch_library_all(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>,
     ]).collect())
 }
 
-pub async fn fetch_library_by_artist(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>, Error> {
-    let rows = sqlx::query(
-        "SELECT artists.artist, tracks.track_title
-         FROM tracks
+pub async fn fetch_library_by_artist(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<Vec<String>>, Error> {
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT artists.artist, tracks.track_title
+         FROM library AS tracks
          LEFT JOIN artists ON tracks.artist_id = artists.id
-         ORDER BY artists.artist",
-    )
+         ORDER BY artists.artist"
+    );
+    let rows = sqlx::query(&sql)
+    .bind(guild_key(guild_id))
     .fetch_all(db_pool)
     .await
     .map_err(|e| format!("Database query failed: {}", e))?;
@@ -113,13 +245,19 @@ pub async fn fetch_library_by_artist(db_pool: &SqlitePool) -> Result<Vec<Vec<Str
     ]).collect())
 }
 
-pub async fn fetch_library_by_origin(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>, Error> {
-    let rows = sqlx::query(
-        "SELECT origins.origin, tracks.track_title
-         FROM tracks
+pub async fn fetch_library_by_origin(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<Vec<String>>, Error> {
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT origins.origin, tracks.track_title
+         FROM library AS tracks
          LEFT JOIN origins ON tracks.origin_id = origins.id
-         ORDER BY origins.origin",
-    )
+         ORDER BY origins.origin"
+    );
+    let rows = sqlx::query(&sql)
+    .bind(guild_key(guild_id))
     .fetch_all(db_pool)
     .await
     .map_err(|e| format!("Database query failed: {}", e))?;
@@ -130,17 +268,23 @@ pub async fn fetch_library_by_origin(db_pool: &SqlitePool) -> Result<Vec<Vec<Str
     ]).collect())
 }
 
-pub async fn fetch_library_by_tag(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>, Error> {
-    let rows = sqlx::query(
-        "SELECT COALESCE(tags.tag, 'No tags') AS tag, tracks.track_title
-         FROM tracks
-         LEFT JOIN track_tags ON tracks.id = track_tags.track_id
+pub async fn fetch_library_by_tag(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<Vec<String>>, Error> {
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT COALESCE(tags.tag, 'No tags') AS tag, tracks.track_title
+         FROM library AS tracks
+         LEFT JOIN library_tags AS track_tags ON tracks.id = track_tags.track_id
          LEFT JOIN tags ON track_tags.tag_id = tags.id
          ORDER BY
              CASE WHEN tags.tag IS NULL THEN 1 ELSE 0 END,
              tag,
-             tracks.track_title",
-    )
+             tracks.track_title"
+    );
+    let rows = sqlx::query(&sql)
+    .bind(guild_key(guild_id))
     .fetch_all(db_pool)
     .await
     .map_err(|e| format!("Database query failed: {}", e))?;
@@ -151,16 +295,22 @@ pub async fn fetch_library_by_tag(db_pool: &SqlitePool) -> Result<Vec<Vec<String
     ]).collect())
 }
 
-pub async fn fetch_library_by_incomplete(db_pool: &SqlitePool) -> Result<Vec<Vec<String>>, Error> {
-    let rows = sqlx::query(
-        "SELECT tracks.track_title, artists.artist, origins.origin
-            FROM tracks
+pub async fn fetch_library_by_incomplete(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+) -> Result<Vec<Vec<String>>, Error> {
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT tracks.track_title, artists.artist, origins.origin
+            FROM library AS tracks
             LEFT JOIN artists ON tracks.artist_id = artists.id
             LEFT JOIN origins ON tracks.origin_id = origins.id
             WHERE artists.artist = 'No artist provided'
             OR origins.origin = 'No origin provided'
             ORDER BY artists.artist, origins.origin, tracks.track_title"
-    )
+    );
+    let rows = sqlx::query(&sql)
+    .bind(guild_key(guild_id))
     .fetch_all(db_pool)
     .await
     .map_err(|e| format!("Database query failed: {}", e))?;
@@ -174,17 +324,21 @@ pub async fn fetch_library_by_incomplete(db_pool: &SqlitePool) -> Result<Vec<Vec
 
 pub async fn lookup_track(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     video_id: &VideoId,
 ) -> Result<Option<TrackInfo>, Error> {
-    let result: Option<(String, String, String)> = sqlx::query_as(
-        "SELECT tracks.track_title,
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT tracks.track_title,
                 artists.artist,
                 origins.origin
-         FROM tracks
+         FROM library AS tracks
          LEFT JOIN artists ON tracks.artist_id = artists.id
          LEFT JOIN origins ON tracks.origin_id = origins.id
-         WHERE tracks.id = ?1",
-    )
+         WHERE tracks.id = ?2"
+    );
+    let result: Option<(String, String, String)> = sqlx::query_as(&sql)
+    .bind(guild_key(guild_id))
     .bind(video_id.as_str())
     .fetch_optional(db_pool)
     .await?;
@@ -199,26 +353,37 @@ pub async fn lookup_track(
 
 pub async fn require_track(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     id: &VideoId,
 ) -> Result<TrackInfo, Error> {
-    lookup_track(db_pool, id)
+    lookup_track(db_pool, guild_id, id)
         .await?
         .ok_or_else(|| "Track could not be found in the database.".into())
 }
 
 pub async fn search_metadata(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     kind: MetadataKind,
     needle: &str,
     limit: i64,
 ) -> Result<Vec<String>, Error> {
+    // Only offer values already used somewhere in this guild's library
     let query = match kind {
-        MetadataKind::Artist => "SELECT DISTINCT artist FROM artists WHERE LOWER(artist) LIKE ?1 LIMIT ?2",
-        MetadataKind::Origin => "SELECT DISTINCT origin FROM origins WHERE LOWER(origin) LIKE ?1 LIMIT ?2",
-        MetadataKind::Tag    => "SELECT DISTINCT tag FROM tags WHERE LOWER(tag) LIKE ?1 LIMIT ?2",
+        MetadataKind::Artist => "SELECT DISTINCT artist FROM artists
+                                 JOIN library ON library.artist_id = artists.id
+                                 WHERE LOWER(artist) LIKE ?2 LIMIT ?3",
+        MetadataKind::Origin => "SELECT DISTINCT origin FROM origins
+                                 JOIN library ON library.origin_id = origins.id
+                                 WHERE LOWER(origin) LIKE ?2 LIMIT ?3",
+        MetadataKind::Tag    => "SELECT DISTINCT tag FROM tags
+                                 JOIN library_tags ON library_tags.tag_id = tags.id
+                                 WHERE LOWER(tag) LIKE ?2 LIMIT ?3",
     };
+    let sql = format!("WITH {GUILD_LIBRARY} {query}");
 
-    sqlx::query_scalar(query)
+    sqlx::query_scalar(&sql)
+        .bind(guild_key(guild_id))
         .bind(format!("%{}%", needle))
         .bind(limit)
         .fetch_all(db_pool)
@@ -228,24 +393,28 @@ pub async fn search_metadata(
 
 pub async fn search_tracks(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     needle: &str,
     limit: i64,
 ) -> Result<Vec<(String, String, String, String, Option<String>)>, Error> {
-    sqlx::query_as(
-        "SELECT DISTINCT tracks.id, tracks.track_title, artists.artist, origins.origin,
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT DISTINCT tracks.id, tracks.track_title, artists.artist, origins.origin,
                 GROUP_CONCAT(tags.tag, ', ') AS tags
-         FROM tracks
-         LEFT JOIN track_tags ON tracks.id = track_tags.track_id
+         FROM library AS tracks
+         LEFT JOIN library_tags AS track_tags ON tracks.id = track_tags.track_id
          LEFT JOIN tags ON track_tags.tag_id = tags.id
          LEFT JOIN artists ON tracks.artist_id = artists.id
          LEFT JOIN origins ON tracks.origin_id = origins.id
-         WHERE LOWER(tracks.track_title) LIKE ?1
-            OR LOWER(artists.artist) LIKE ?1
-            OR LOWER(origins.origin) LIKE ?1
-            OR LOWER(tags.tag) LIKE ?1
+         WHERE LOWER(tracks.track_title) LIKE ?2
+            OR LOWER(artists.artist) LIKE ?2
+            OR LOWER(origins.origin) LIKE ?2
+            OR LOWER(tags.tag) LIKE ?2
          GROUP BY tracks.id, tracks.track_title, artists.artist, origins.origin
-         LIMIT ?2",
-    )
+         LIMIT ?3"
+    );
+    sqlx::query_as(&sql)
+    .bind(guild_key(guild_id))
     .bind(format!("%{}%", needle))
     .bind(limit)
     .fetch_all(db_pool)
@@ -255,25 +424,29 @@ pub async fn search_tracks(
 
 pub async fn search_incomplete_tracks(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     needle: &str,
     limit: i64,
 ) -> Result<Vec<(String, String, String, String, Option<String>)>, Error> {
-    sqlx::query_as(
-        "SELECT DISTINCT tracks.id, tracks.track_title, artists.artist, origins.origin,
+    let sql = format!(
+        "WITH {GUILD_LIBRARY}
+         SELECT DISTINCT tracks.id, tracks.track_title, artists.artist, origins.origin,
                 GROUP_CONCAT(tags.tag, ', ') AS tags
-         FROM tracks
+         FROM library AS tracks
          LEFT JOIN artists ON tracks.artist_id = artists.id
          LEFT JOIN origins ON tracks.origin_id = origins.id
-         LEFT JOIN track_tags ON tracks.id = track_tags.track_id
+         LEFT JOIN library_tags AS track_tags ON tracks.id = track_tags.track_id
          LEFT JOIN tags ON track_tags.tag_id = tags.id
          WHERE (artists.artist = 'No artist provided'
             OR origins.origin = 'No origin provided')
-           AND (LOWER(tracks.track_title) LIKE ?1
-            OR LOWER(artists.artist) LIKE ?1
-            OR LOWER(origins.origin) LIKE ?1)
+           AND (LOWER(tracks.track_title) LIKE ?2
+            OR LOWER(artists.artist) LIKE ?2
+            OR LOWER(origins.origin) LIKE ?2)
          GROUP BY tracks.id
-         LIMIT ?2",
-    )
+         LIMIT ?3"
+    );
+    sqlx::query_as(&sql)
+    .bind(guild_key(guild_id))
     .bind(format!("%{}%", needle))
     .bind(limit)
     .fetch_all(db_pool)
@@ -283,9 +456,22 @@ pub async fn search_incomplete_tracks(
 
 pub async fn delete_track_tags(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     track_id: &VideoId,
 ) -> Result<(), Error> {
-    sqlx::query("DELETE FROM track_tags WHERE track_id = ?1")
+    // Shared tags can't be deleted from one guild, so they're masked out instead
+    sqlx::query(
+        "INSERT INTO guild_track_overrides (guild_id, track_id, tags_overridden) VALUES (?1, ?2, 1)
+         ON CONFLICT (guild_id, track_id) DO UPDATE SET tags_overridden = 1",
+    )
+    .bind(guild_key(guild_id))
+    .bind(track_id.as_str())
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to delete tags for track {}: {}", track_id.as_str(), e))?;
+
+    sqlx::query("DELETE FROM guild_track_tags WHERE guild_id = ?1 AND track_id = ?2")
+        .bind(guild_key(guild_id))
         .bind(track_id.as_str())
         .execute(db_pool)
         .await
@@ -295,10 +481,12 @@ pub async fn delete_track_tags(
 
 pub async fn insert_track_tag(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     track_id: &VideoId,
     tag_id: i64,
 ) -> Result<(), Error> {
-    sqlx::query("INSERT OR IGNORE INTO track_tags (track_id, tag_id) VALUES (?1, ?2)")
+    sqlx::query("INSERT OR IGNORE INTO guild_track_tags (guild_id, track_id, tag_id) VALUES (?1, ?2, ?3)")
+        .bind(guild_key(guild_id))
         .bind(track_id.as_str())
         .bind(tag_id)
         .execute(db_pool)
@@ -309,42 +497,272 @@ pub async fn insert_track_tag(
 
 pub async fn update_track_title(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     track_id: &VideoId,
     new_title: &str,
 ) -> Result<(), Error> {
-    sqlx::query("UPDATE tracks SET track_title = ?1 WHERE id = ?2")
-        .bind(new_title)
+    sqlx::query(
+        "INSERT INTO guild_track_overrides (guild_id, track_id, track_title) VALUES (?1, ?2, ?3)
+         ON CONFLICT (guild_id, track_id) DO UPDATE SET track_title = excluded.track_title",
+    )
+    .bind(guild_key(guild_id))
+    .bind(track_id.as_str())
+    .bind(new_title)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update title for track {}: {}", track_id.as_str(), e))?;
+    Ok(())
+}
+
+pub async fn update_track_artist(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    track_id: &VideoId,
+    artist_id: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO guild_track_overrides (guild_id, track_id, artist_id) VALUES (?1, ?2, ?3)
+         ON CONFLICT (guild_id, track_id) DO UPDATE SET artist_id = excluded.artist_id",
+    )
+    .bind(guild_key(guild_id))
+    .bind(track_id.as_str())
+    .bind(artist_id)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update artist for track {}: {}", track_id.as_str(), e))?;
+    Ok(())
+}
+
+pub async fn update_track_origin(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    track_id: &VideoId,
+    origin_id: i64,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO guild_track_overrides (guild_id, track_id, origin_id) VALUES (?1, ?2, ?3)
+         ON CONFLICT (guild_id, track_id) DO UPDATE SET origin_id = excluded.origin_id",
+    )
+    .bind(guild_key(guild_id))
+    .bind(track_id.as_str())
+    .bind(origin_id)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update origin for track {}: {}", track_id.as_str(), e))?;
+    Ok(())
+}
+
+/// Moves the track in or out of the shared pool. Only its owner may; errors for
+/// any other guild.
+pub async fn set_track_shared(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    track_id: &VideoId,
+    shared: bool,
+) -> Result<(), Error> {
+    let updated = sqlx::query("UPDATE tracks SET shared = ?1 WHERE id = ?2 AND owner_guild_id = ?3")
+        .bind(shared)
         .bind(track_id.as_str())
+        .bind(guild_key(guild_id))
         .execute(db_pool)
         .await
-        .map_err(|e| format!("Failed to update title for track {}: {}", track_id.as_str(), e))?;
+        .map_err(|e| format!("Failed to update sharing for track {}: {}", track_id.as_str(), e))?
+        .rows_affected();
+    if updated == 0 {
+        return Err(format!("Track {} isn't owned by this server.", track_id.as_str()).into());
+    }
     Ok(())
 }
 
-pub async fn update_track_artist(
+pub async fn set_track_hidden(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     track_id: &VideoId,
+    hidden: bool,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO guild_track_overrides (guild_id, track_id, hidden) VALUES (?1, ?2, ?3)
+         ON CONFLICT (guild_id, track_id) DO UPDATE SET hidden = excluded.hidden",
+    )
+    .bind(guild_key(guild_id))
+    .bind(track_id.as_str())
+    .bind(hidden)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update visibility for track {}: {}", track_id.as_str(), e))?;
+    Ok(())
+}
+
+pub async fn set_guild_share_global(
+    db_pool: &SqlitePool,
+    guild_id: GuildId,
+    share_global: bool,
+) -> Result<(), Error> {
+    sqlx::query(
+        "INSERT INTO guild_settings (guild_id, share_global) VALUES (?1, ?2)
+         ON CONFLICT (guild_id) DO UPDATE SET share_global = excluded.share_global",
+    )
+    .bind(guild_key(guild_id))
+    .bind(share_global)
+    .execute(db_pool)
+    .await
+    .map_err(|e| format!("Failed to update guild settings: {}", e))?;
+    Ok(())
+}
+
+/// Every track in scope with its full metadata, for bulk export. With no guild this
+/// reads the base library exactly as stored, ignoring visibility and overrides.
+pub async fn fetch_library_records(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+) -> Result<Vec<(String, String, String, String, String, String)>, Error> {
+    let source = match guild_id {
+        Some(_) => format!("WITH {GUILD_LIBRARY} SELECT * FROM library"),
+        None => "SELECT * FROM tracks".to_string(),
+    };
+    let sql = format!(
+        "SELECT tracks.id, tracks.upload_date, tracks.yt_title, tracks.track_title,
+                artists.artist, origins.origin
+         FROM ({source}) AS tracks
+         LEFT JOIN artists ON tracks.artist_id = artists.id
+         LEFT JOIN origins ON tracks.origin_id = origins.id
+         ORDER BY tracks.track_title"
+    );
+
+    sqlx::query_as(&sql)
+        .bind(guild_id.map(guild_key))
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Library export query failed: {}", e).into())
+}
+
+/// (track_id, tag) pairs for every track in scope, matching `fetch_library_records`.
+pub async fn fetch_library_tag_pairs(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+) -> Result<Vec<(String, String)>, Error> {
+    let sql = match guild_id {
+        Some(_) => format!(
+            "WITH {GUILD_LIBRARY}
+             SELECT library_tags.track_id, tags.tag
+             FROM library_tags
+             JOIN tags ON library_tags.tag_id = tags.id
+             ORDER BY library_tags.track_id, tags.tag"
+        ),
+        None => "SELECT track_tags.track_id, tags.tag
+                 FROM track_tags
+                 JOIN tags ON track_tags.tag_id = tags.id
+                 ORDER BY track_tags.track_id, tags.tag"
+            .to_string(),
+    };
+
+    sqlx::query_as(&sql)
+        .bind(guild_id.map(guild_key))
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Tag export query failed: {}", e).into())
+}
+
+/// Every artist, origin or tag name in scope, for bulk export. With no guild this is
+/// every stored name, including ones no track uses any more.
+pub async fn fetch_library_metadata(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+    kind: MetadataKind,
+) -> Result<Vec<String>, Error> {
+    let sql = match (guild_id, kind) {
+        (Some(_), MetadataKind::Artist) => format!(
+            "WITH {GUILD_LIBRARY} SELECT DISTINCT artist FROM artists
+             JOIN library ON library.artist_id = artists.id ORDER BY artist"
+        ),
+        (Some(_), MetadataKind::Origin) => format!(
+            "WITH {GUILD_LIBRARY} SELECT DISTINCT origin FROM origins
+             JOIN library ON library.origin_id = origins.id ORDER BY origin"
+        ),
+        (Some(_), MetadataKind::Tag) => format!(
+            "WITH {GUILD_LIBRARY} SELECT DISTINCT tag FROM tags
+             JOIN library_tags ON library_tags.tag_id = tags.id ORDER BY tag"
+        ),
+        (None, MetadataKind::Artist) => "SELECT artist FROM artists ORDER BY artist".to_string(),
+        (None, MetadataKind::Origin) => "SELECT origin FROM origins ORDER BY origin".to_string(),
+        (None, MetadataKind::Tag)    => "SELECT tag FROM tags ORDER BY tag".to_string(),
+    };
+
+    sqlx::query_scalar(&sql)
+        .bind(guild_id.map(guild_key))
+        .fetch_all(db_pool)
+        .await
+        .map_err(|e| format!("Metadata export query failed: {}", e).into())
+}
+
+/// Whether an artist, origin or tag with exactly this name is stored.
+pub async fn metadata_exists(
+    db_pool: &SqlitePool,
+    kind: MetadataKind,
+    value: &str,
+) -> Result<bool, Error> {
+    let id: Option<i64> = sqlx::query_scalar(kind.select_sql())
+        .bind(value)
+        .fetch_optional(db_pool)
+        .await
+        .map_err(|e| format!("Database select failed: {}", e))?;
+    Ok(id.is_some())
+}
+
+/// A track's metadata as stored in the base library, ignoring any guild overrides.
+pub async fn lookup_base_track(
+    db_pool: &SqlitePool,
+    video_id: &VideoId,
+) -> Result<Option<TrackInfo>, Error> {
+    let result: Option<(String, String, String)> = sqlx::query_as(
+        "SELECT tracks.track_title,
+                artists.artist,
+                origins.origin
+         FROM tracks
+         LEFT JOIN artists ON tracks.artist_id = artists.id
+         LEFT JOIN origins ON tracks.origin_id = origins.id
+         WHERE tracks.id = ?1",
+    )
+    .bind(video_id.as_str())
+    .fetch_optional(db_pool)
+    .await?;
+
+    Ok(result.map(|(title, artist, origin)| TrackInfo {
+        id: video_id.clone(),
+        title,
+        artist,
+        origin,
+    }))
+}
+
+pub async fn update_base_track(
+    db_pool: &SqlitePool,
+    track_id: &VideoId,
+    title: &str,
     artist_id: i64,
+    origin_id: i64,
 ) -> Result<(), Error> {
-    sqlx::query("UPDATE tracks SET artist_id = ?1 WHERE id = ?2")
+    sqlx::query("UPDATE tracks SET track_title = ?1, artist_id = ?2, origin_id = ?3 WHERE id = ?4")
+        .bind(title)
         .bind(artist_id)
+        .bind(origin_id)
         .bind(track_id.as_str())
         .execute(db_pool)
         .await
-        .map_err(|e| format!("Failed to update artist for track {}: {}", track_id.as_str(), e))?;
+        .map_err(|e| format!("Failed to update track {}: {}", track_id.as_str(), e))?;
     Ok(())
 }
 
-pub async fn update_track_origin(
+pub async fn insert_base_track_tag(
     db_pool: &SqlitePool,
     track_id: &VideoId,
-    origin_id: i64,
+    tag_id: i64,
 ) -> Result<(), Error> {
-    sqlx::query("UPDATE tracks SET origin_id = ?1 WHERE id = ?2")
-        .bind(origin_id)
+    sqlx::query("INSERT OR IGNORE INTO track_tags (track_id, tag_id) VALUES (?1, ?2)")
         .bind(track_id.as_str())
+        .bind(tag_id)
         .execute(db_pool)
         .await
-        .map_err(|e| format!("Failed to update origin for track {}: {}", track_id.as_str(), e))?;
+        .map_err(|e| format!("Failed to insert tag for track {}: {}", track_id.as_str(), e))?;
     Ok(())
-}
\ No newline at end of file
+}
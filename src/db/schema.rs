@@ -0,0 +1,196 @@
+use sqlx::SqlitePool;
+
+use crate::definitions::Error;
+
+/// Tables added after the original `schema.sql`, created on startup so existing
+/// databases pick them up without being rebuilt.
+const UPGRADE_TABLES: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS guild_settings (
+        guild_id INTEGER PRIMARY KEY,
+        share_global INTEGER NOT NULL DEFAULT 1
+    )",
+    "CREATE TABLE IF NOT EXISTS guild_tracks (
+        guild_id INTEGER NOT NULL,
+        track_id TEXT NOT NULL,
+        PRIMARY KEY (guild_id, track_id),
+        FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS guild_track_overrides (
+        guild_id INTEGER NOT NULL,
+        track_id TEXT NOT NULL,
+        track_title TEXT,
+        artist_id INTEGER,
+        origin_id INTEGER,
+        tags_overridden INTEGER NOT NULL DEFAULT 0,
+        hidden INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (guild_id, track_id),
+        FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE,
+        FOREIGN KEY (artist_id) REFERENCES artists (id) ON DELETE SET NULL,
+        FOREIGN KEY (origin_id) REFERENCES origins (id) ON DELETE SET NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS guild_track_tags (
+        guild_id INTEGER NOT NULL,
+        track_id TEXT NOT NULL,
+        tag_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, track_id, tag_id),
+        FOREIGN KEY (track_id) REFERENCES tracks (id) ON DELETE CASCADE,
+        FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+    )",
+    "CREATE INDEX IF NOT EXISTS idx_guild_tracks_track ON guild_tracks(track_id)",
+    "CREATE TABLE IF NOT EXISTS chronicle_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        voice_channel_id INTEGER NOT NULL,
+        text_channel_id INTEGER NOT NULL,
+        campaign_id INTEGER,
+        session_number INTEGER,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER,
+        state TEXT NOT NULL,
+        storage_dir TEXT NOT NULL,
+        approved_at INTEGER
+    )",
+    "CREATE TABLE IF NOT EXISTS chronicle_participants (
+        session_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        PRIMARY KEY (session_id, user_id),
+        FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS campaigns (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL COLLATE NOCASE,
+        obsidian_folder TEXT NOT NULL,
+        channel_id INTEGER,
+        archived INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL,
+        audio_retention_days INTEGER,
+        transcript_retention_days INTEGER,
+        UNIQUE (guild_id, name)
+    )",
+    "CREATE TABLE IF NOT EXISTS campaign_characters (
+        campaign_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        character_name TEXT NOT NULL,
+        character_class TEXT,
+        PRIMARY KEY (campaign_id, user_id),
+        FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS chronicle_consent (
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        consented INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, user_id)
+    )",
+    "CREATE TABLE IF NOT EXISTS chronicle_consent_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        consented INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        session_id INTEGER,
+        changed_at INTEGER NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS idx_chronicle_consent_log_user ON chronicle_consent_log(guild_id, user_id)",
+    "CREATE TABLE IF NOT EXISTS chronicle_deletions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER NOT NULL,
+        session_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        path TEXT NOT NULL,
+        bytes INTEGER NOT NULL,
+        rule TEXT NOT NULL,
+        deleted_at INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS campaign_glossary (
+        campaign_id INTEGER NOT NULL,
+        term TEXT NOT NULL COLLATE NOCASE,
+        kind TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        PRIMARY KEY (campaign_id, term),
+        FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS campaign_entities (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        campaign_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        name TEXT NOT NULL COLLATE NOCASE,
+        status TEXT,
+        description TEXT,
+        details TEXT,
+        updated_at INTEGER NOT NULL,
+        UNIQUE (campaign_id, kind, name),
+        FOREIGN KEY (campaign_id) REFERENCES campaigns (id) ON DELETE CASCADE
+    )",
+    "CREATE TABLE IF NOT EXISTS campaign_entity_mentions (
+        entity_id INTEGER NOT NULL,
+        session_id INTEGER NOT NULL,
+        status TEXT,
+        description TEXT,
+        details TEXT,
+        PRIMARY KEY (entity_id, session_id),
+        FOREIGN KEY (entity_id) REFERENCES campaign_entities (id) ON DELETE CASCADE,
+        FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+    )",
+    "CREATE INDEX IF NOT EXISTS idx_campaign_entity_mentions_session ON campaign_entity_mentions(session_id)",
+    "CREATE VIRTUAL TABLE IF NOT EXISTS chronicle_transcript_fts USING fts5(
+        text,
+        speaker_name,
+        session_id UNINDEXED,
+        campaign_id UNINDEXED,
+        speaker UNINDEXED,
+        start_ms UNINDEXED,
+        tokenize = 'porter unicode61'
+    )",
+    "CREATE TABLE IF NOT EXISTS chronicle_jobs (
+        session_id INTEGER NOT NULL,
+        stage TEXT NOT NULL,
+        status TEXT NOT NULL,
+        inputs TEXT,
+        input_hash TEXT,
+        output_hash TEXT,
+        error TEXT,
+        started_at INTEGER,
+        finished_at INTEGER,
+        PRIMARY KEY (session_id, stage),
+        FOREIGN KEY (session_id) REFERENCES chronicle_sessions (id) ON DELETE CASCADE
+    )",
+];
+
+/// Columns added to pre-existing tables: (table, column, definition).
+const UPGRADE_COLUMNS: &[(&str, &str, &str)] = &[
+    ("tracks", "shared", "INTEGER NOT NULL DEFAULT 1"),
+    ("tracks", "owner_guild_id", "INTEGER"),
+];
+
+/// Brings an existing database up to the current schema. Safe to run on every startup.
+pub async fn ensure_schema(db_pool: &SqlitePool) -> Result<(), Error> {
+    for statement in UPGRADE_TABLES {
+        sqlx::query(statement)
+            .execute(db_pool)
+            .await
+            .map_err(|e| format!("Schema upgrade failed: {}", e))?;
+    }
+
+    for (table, column, definition) in UPGRADE_COLUMNS {
+        let exists: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+        )
+        .bind(table)
+        .bind(column)
+        .fetch_one(db_pool)
+        .await
+        .map_err(|e| format!("Failed to inspect table `{}`: {}", table, e))?;
+
+        if exists == 0 {
+            tracing::info!("Adding column `{}.{}`", table, column);
+            sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+                .execute(db_pool)
+                .await
+                .map_err(|e| format!("Failed to add column `{}.{}`: {}", table, column, e))?;
+        }
+    }
+
+    Ok(())
+}
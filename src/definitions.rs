@@ -1,7 +1,14 @@
+use std::sync::Arc;
+
 use sqlx::SqlitePool;
 use songbird::tracks::TrackHandle;
 use crate::jester::service::PlayerService;
+use crate::db::backup::BackupConfig;
+use crate::chronicle::config::ChronicleConfig;
+use crate::chronicle::pipeline::Pipeline;
+use crate::chronicle::session::ChronicleService;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MetadataKind {
     Artist,
     Origin,
@@ -63,14 +70,22 @@ impl From<&str> for VideoId {
 // Defines user data; this is always available in the Serenity context of an invocation
 pub struct Data {
     pub db_pool: SqlitePool,
-    pub player: PlayerService,
+    pub player: Arc<PlayerService>,
+    pub backup: BackupConfig,
+    pub chronicle: Arc<ChronicleService>,
+    pub chronicle_config: ChronicleConfig,
+    pub pipeline: Pipeline,
 }
 
 impl Data {
-    pub fn new(db_pool: SqlitePool) -> Self {
+    pub fn new(db_pool: SqlitePool, backup: BackupConfig, chronicle_config: ChronicleConfig) -> Self {
         Self {
             db_pool,
-            player: PlayerService::new(),
+            player: Arc::new(PlayerService::new()),
+            backup,
+            chronicle: Arc::new(ChronicleService::new(chronicle_config.storage_root.clone())),
+            pipeline: Pipeline::new(chronicle_config.pipeline.max_jobs),
+            chronicle_config,
         }
     }
 }
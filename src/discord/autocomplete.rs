@@ -1,5 +1,7 @@
 use crate::definitions::{PoiseContext, MetadataKind};
 use crate::db::repository::{search_incomplete_tracks, search_metadata, search_tracks};
+use crate::db::backup::list_snapshots;
+use crate::db::chronicle::{fetch_campaigns, search_entity_names};
 use poise::serenity_prelude::AutocompleteChoice;
 use crate::utils::format::{lightweight_trim, build_autocomplete_display};
 
@@ -34,10 +36,13 @@ async fn autocomplete_metadata(
     partial: &str,
     kind: MetadataKind,
 ) -> impl Iterator<Item = String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![].into_iter();
+    };
     let needle = partial.to_lowercase();
     let db_pool = &ctx.data().db_pool;
 
-    let results = match search_metadata(db_pool, kind, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
+    let results = match search_metadata(db_pool, guild_id, kind, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
         Ok(r) => r,
         Err(e) => {
             tracing::error!("Autocomplete metadata query failed: {}", e);
@@ -57,10 +62,13 @@ pub async fn autocomplete_track(
     ctx: PoiseContext<'_>,
     partial: &str,
 ) -> impl Iterator<Item = AutocompleteChoice> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![].into_iter();
+    };
     let needle = partial.to_lowercase();
     let db_pool = &ctx.data().db_pool;
 
-    let results = match search_tracks(db_pool, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
+    let results = match search_tracks(db_pool, guild_id, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
         Ok(r) => r,
         Err(e) => {
             tracing::error!("Autocomplete track query failed: {}", e);
@@ -90,10 +98,13 @@ pub async fn autocomplete_incomplete_track(
     ctx: PoiseContext<'_>,
     partial: &str,
 ) -> impl Iterator<Item = AutocompleteChoice> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![].into_iter();
+    };
     let needle = partial.to_lowercase();
     let db_pool = &ctx.data().db_pool;
 
-    let results = match search_incomplete_tracks(db_pool, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
+    let results = match search_incomplete_tracks(db_pool, guild_id, &needle, AUTOCOMPLETE_MAX_CHOICES as i64).await {
         Ok(r) => r,
         Err(e) => {
             tracing::error!("Incomplete track autocomplete query failed: {}", e);
@@ -117,4 +128,68 @@ pub async fn autocomplete_incomplete_track(
         .map(|(display, video_id)| AutocompleteChoice::new(display, video_id))
         .collect::<Vec<_>>()
         .into_iter()
-}
\ No newline at end of file
+}
+
+pub async fn autocomplete_snapshot(
+    ctx: PoiseContext<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let snapshots = match list_snapshots(&ctx.data().backup.dir).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("Snapshot autocomplete failed: {}", e);
+            return vec![].into_iter();
+        }
+    };
+
+    snapshots
+        .into_iter()
+        .map(|s| s.name())
+        .filter(|name| name.contains(partial))
+        .take(AUTOCOMPLETE_MAX_CHOICES)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+pub async fn autocomplete_campaign(
+    ctx: PoiseContext<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![].into_iter();
+    };
+
+    let campaigns = match fetch_campaigns(&ctx.data().db_pool, guild_id, false).await {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Campaign autocomplete failed: {}", e);
+            return vec![].into_iter();
+        }
+    };
+
+    let needle = partial.to_lowercase();
+    campaigns
+        .into_iter()
+        .map(|c| c.name)
+        .filter(|name| name.to_lowercase().contains(&needle))
+        .take(AUTOCOMPLETE_MAX_CHOICES)
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+pub async fn autocomplete_entity(
+    ctx: PoiseContext<'_>,
+    partial: &str,
+) -> impl Iterator<Item = String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return vec![].into_iter();
+    };
+
+    match search_entity_names(&ctx.data().db_pool, guild_id, None, partial, AUTOCOMPLETE_MAX_CHOICES as i64).await {
+        Ok(names) => names.into_iter(),
+        Err(e) => {
+            tracing::error!("Entity autocomplete failed: {}", e);
+            vec![].into_iter()
+        }
+    }
+}
@@ -1,4 +1,8 @@
 use crate::definitions::{PoiseContext, Error};
+use crate::db::backup::{list_snapshots, prune_snapshots, restore_snapshot, take_snapshot};
+use crate::discord::autocomplete::autocomplete_snapshot;
+
+const SNAPSHOTS_LISTED: usize = 20;
 
 /// Force-register commands - only invokes with ">"
 #[poise::command(prefix_command)]
@@ -25,4 +29,59 @@ pub async fn help(
     )
     .await?;
     Ok(())
+}
+
+/// Manage database snapshots
+#[poise::command(slash_command, owners_only, subcommands("list", "now", "restore"), subcommand_required)]
+pub async fn backup(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// List the stored database snapshots
+#[poise::command(slash_command, owners_only)]
+async fn list(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let snapshots = list_snapshots(&ctx.data().backup.dir).await?;
+
+    if snapshots.is_empty() {
+        ctx.say("No snapshots have been taken yet.").await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = snapshots
+        .iter()
+        .take(SNAPSHOTS_LISTED)
+        .map(|s| format!("`{}` · <t:{}:f> · {} KiB", s.name(), s.taken_at, s.size / 1024))
+        .collect();
+    ctx.say(format!("{} snapshot(s):\n{}", snapshots.len(), lines.join("\n"))).await?;
+    Ok(())
+}
+
+/// Take a database snapshot immediately
+#[poise::command(slash_command, owners_only)]
+async fn now(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let snapshot = take_snapshot(&ctx.data().db_pool, &ctx.data().backup.dir).await?;
+    prune_snapshots(&ctx.data().backup).await?;
+
+    ctx.say(format!("Snapshot `{}` taken and verified.", snapshot.name())).await?;
+    Ok(())
+}
+
+/// Restore the database from a snapshot
+#[poise::command(slash_command, owners_only)]
+async fn restore(
+    ctx: PoiseContext<'_>,
+    #[description = "Snapshot to restore"]
+    #[autocomplete = "autocomplete_snapshot"]
+    snapshot: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let restored = restore_snapshot(&ctx.data().db_pool, &ctx.data().backup, &snapshot).await?;
+
+    ctx.say(format!(
+        "Restored the database from `{}` (taken <t:{}:f>). The previous state was snapshotted first.",
+        restored.name(),
+        restored.taken_at,
+    )).await?;
+    Ok(())
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use crate::definitions::{PoiseContext, Error};
+use crate::utils::context::require_guild;
 use crate::db::repository::{
     fetch_library_all, fetch_library_by_artist, fetch_library_by_incomplete,
     fetch_library_by_origin, fetch_library_by_tag,
@@ -163,14 +164,15 @@ fn paginate(lines: Vec<String>, mode: &str) -> Vec<String> {
 // ─── dispatcher ──────────────────────────────────────────────────────────────
 
 async fn library_dynamic(ctx: PoiseContext<'_>, mode: &str) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
 
     let (raw_data, grouped) = match mode {
-        "artist"     => (fetch_library_by_artist(db_pool).await?,   true),
-        "origin"     => (fetch_library_by_origin(db_pool).await?,   true),
-        "tags"       => (fetch_library_by_tag(db_pool).await?,      true),
-        "incomplete" => (fetch_library_by_incomplete(db_pool).await?, false),
-        _            => (fetch_library_all(db_pool).await?,          false),
+        "artist"     => (fetch_library_by_artist(db_pool, guild_id).await?,   true),
+        "origin"     => (fetch_library_by_origin(db_pool, guild_id).await?,   true),
+        "tags"       => (fetch_library_by_tag(db_pool, guild_id).await?,      true),
+        "incomplete" => (fetch_library_by_incomplete(db_pool, guild_id).await?, false),
+        _            => (fetch_library_all(db_pool, guild_id).await?,          false),
     };
 
     if raw_data.is_empty() {
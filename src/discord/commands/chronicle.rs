@@ -0,0 +1,1091 @@
+use std::path::Path;
+use std::time::Duration;
+
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateAttachment, GuildId, Mentionable};
+
+use crate::chronicle::campaign::{default_obsidian_folder, Character, Roster};
+use crate::chronicle::entities::EntityKind;
+use crate::chronicle::glossary::{Glossary, TermKind};
+use crate::chronicle::live::Captions;
+use crate::chronicle::obsidian::{format_date, format_duration};
+use crate::chronicle::pipeline::Stage;
+use crate::chronicle::processor::{Processor, SessionSummary, SummaryContext};
+use crate::chronicle::retention::{plan as retention_plan, RetainedKind, RetentionPolicy};
+use crate::chronicle::search::{fts_query, index_missing};
+use crate::chronicle::session::{unix_now, LiveOptions, SessionState, StartRequest};
+use crate::chronicle::transcript::clock;
+use crate::db::chronicle::{
+    fetch_campaign, fetch_campaign_sessions, fetch_campaigns, fetch_characters,
+    approve_session, delete_glossary_term, fetch_consent, fetch_consent_log, fetch_deletions, fetch_session,
+    fetch_entity_mentions, lookup_entities, search_entity_names, search_transcripts,
+    set_campaign_retention, find_active_campaign, insert_campaign, require_campaign, set_campaign_archived,
+    upsert_character, upsert_glossary_term, CampaignRow,
+};
+use crate::definitions::{PoiseContext, Error};
+use crate::discord::autocomplete::{autocomplete_campaign, autocomplete_entity};
+use crate::discord::consent::{apply_consent, prompt_for_consent, ConsentSource};
+use crate::discord::playback::{play_button, play_file};
+use crate::utils::context::{get_vc_id, require_guild};
+
+const CONSENT_LOG_LISTED: i64 = 20;
+const DELETIONS_LISTED: i64 = 20;
+const EXPIRIES_LISTED: usize = 20;
+const GLOSSARY_TERM_MAX_CHARS: usize = 60;
+/// Discord rejects messages over 2000 characters.
+const GLOSSARY_LIST_MAX_CHARS: usize = 1_900;
+const LOOKUP_MAX_CHARS: usize = 1_900;
+const LOOKUP_SUGGESTIONS: i64 = 5;
+const RECAP_DEFAULT_SESSIONS: u32 = 3;
+const RECAP_MAX_CHARS: usize = 1_900;
+/// One row of play buttons holds at most five.
+const SEARCH_RESULTS: i64 = 5;
+const SEARCH_MAX_CHARS: usize = 1_900;
+/// The latest spoken recap of each campaign, under the storage root.
+const RECAPS_DIR: &str = "recaps";
+
+/// Record and manage tabletop sessions
+#[poise::command(slash_command, guild_only, subcommands("start", "pause", "resume", "stop", "status", "approve", "reprocess", "lookup", "search", "recap", "campaign", "glossary", "consent", "retention"), subcommand_required)]
+pub async fn chronicle(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Start recording your voice channel
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn start(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    if !ctx.data().chronicle_config.enabled {
+        return Err("Chronicle is disabled in `config/chronicle.toml`.".into());
+    }
+
+    let guild_id = require_guild(ctx)?;
+    let vc_id = get_vc_id(ctx).await?;
+
+    if ctx.data().player.get_now_playing(guild_id).await.is_some() {
+        return Err("Stop the music before recording; both need the voice connection.".into());
+    }
+
+    let db_pool = &ctx.data().db_pool;
+    let campaign = find_active_campaign(
+        db_pool,
+        guild_id,
+        ctx.channel_id().get() as i64,
+        vc_id.get() as i64,
+    ).await?;
+
+    let participants = voice_channel_members(ctx, guild_id, vc_id);
+    let live_config = &ctx.data().chronicle_config.live;
+    let live = if live_config.transcribe {
+        let captions = if live_config.captions {
+            open_captions_thread(ctx, campaign.as_ref().map(|c| c.name.as_str())).await
+        } else {
+            None
+        };
+        let roster = Roster::load(db_pool, campaign.as_ref().map(|c| c.id)).await?;
+        let prompt = match &campaign {
+            Some(campaign) => {
+                let vault = ctx.data().chronicle_config.obsidian.vault.as_deref();
+                Glossary::load(db_pool, campaign, vault, &roster).await?.prompt()
+            }
+            None => None,
+        };
+        Some(LiveOptions {
+            config: live_config.clone(),
+            transcriber: crate::ai::transcriber(&ctx.data().chronicle_config.ai),
+            roster,
+            captions,
+            prompt,
+        })
+    } else {
+        None
+    };
+    let captions_thread = live.as_ref().and_then(|l| l.captions.as_ref()).map(|c| c.thread);
+
+    let started = ctx.data().chronicle.start(db_pool, ctx.serenity_context(), StartRequest {
+        guild_id,
+        vc_id,
+        text_channel_id: ctx.channel_id(),
+        campaign_id: campaign.as_ref().map(|c| c.id),
+        participants: &participants,
+        live,
+    }).await?;
+
+    let mut message = format!(
+        "🔴 **Recording started** in {} ({}). Only members who have agreed to be recorded are captured.",
+        vc_id.mention(),
+        session_label(started.id, campaign.as_ref().map(|c| c.name.as_str()), started.session_number),
+    );
+    if let Some(thread) = captions_thread {
+        message.push_str(&format!("\nLive captions: {}", thread.mention()));
+    }
+    announce(ctx, vc_id, message).await?;
+
+    let mut undecided = Vec::new();
+    for user_id in participants {
+        if fetch_consent(db_pool, guild_id, user_id).await?.is_none() {
+            undecided.push(user_id);
+        }
+    }
+    let unreached = prompt_for_consent(ctx.serenity_context(), guild_id, &undecided).await?;
+    if !unreached.is_empty() {
+        let mentions: Vec<String> = unreached.iter().map(|u| serenity::UserId::new(*u).mention().to_string()).collect();
+        ctx.send(poise::CreateReply::default()
+            .content(format!(
+                "Couldn't DM the consent prompt to {}. They aren't recorded until they use `/chronicle consent`.",
+                mentions.join(", "),
+            ))
+            .ephemeral(true),
+        ).await?;
+    }
+    Ok(())
+}
+
+/// Pause the current recording
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn pause(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let session_id = ctx.data().chronicle.set_paused(&ctx.data().db_pool, guild_id, true).await?;
+    ctx.say(format!("⏸️ Recording of session #{} paused.", session_id)).await?;
+    Ok(())
+}
+
+/// Resume a paused recording
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn resume(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let session_id = ctx.data().chronicle.set_paused(&ctx.data().db_pool, guild_id, false).await?;
+    ctx.say(format!("🔴 Recording of session #{} resumed.", session_id)).await?;
+    Ok(())
+}
+
+/// Stop recording and save the session
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn stop(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    ctx.defer().await?;
+
+    let vc_id = ctx.data().chronicle.status(guild_id).await.map(|s| s.voice_channel_id);
+    let stopped = ctx.data().chronicle.stop(&ctx.data().db_pool, ctx.serenity_context(), guild_id).await?;
+
+    let campaign_name = match stopped.campaign_id {
+        Some(id) => fetch_campaign(&ctx.data().db_pool, id).await?.map(|c| c.name),
+        None => None,
+    };
+    let speakers = stopped.manifest.speakers.len();
+    let mut message = format!(
+        "⏹️ **Recording stopped** ({}). Captured {} from {} speaker(s).",
+        session_label(stopped.id, campaign_name.as_deref(), stopped.session_number),
+        format_duration(stopped.manifest.duration_secs()),
+        speakers,
+    );
+    if let Some(live) = stopped.live {
+        if live.is_complete() {
+            message.push_str(&format!(" Live transcript: {} line(s).", live.segments));
+        } else {
+            message.push_str(&format!(
+                " {} of {} utterance(s) couldn't be transcribed live; the full transcript will be made from the recording.",
+                live.failed,
+                live.utterances,
+            ));
+        }
+    }
+    let data = ctx.data();
+    match data.pipeline.enqueue(data.db_pool.clone(), data.chronicle_config.clone(), ctx.serenity_context().http.clone(), stopped.id, None).await {
+        Ok(()) => message.push_str(" The transcript and notes will be posted once processing finishes."),
+        Err(e) => message.push_str(&format!(" Processing couldn't be queued: {}", e)),
+    }
+    match vc_id {
+        Some(vc_id) => announce(ctx, vc_id, message).await?,
+        None => { ctx.say(message).await?; }
+    }
+    Ok(())
+}
+
+/// Show the state of the current recording
+#[poise::command(slash_command, guild_only)]
+async fn status(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+
+    let Some(status) = ctx.data().chronicle.status(guild_id).await else {
+        ctx.say("No chronicle session is recording in this server.").await?;
+        return Ok(());
+    };
+
+    let campaign_name = match status.campaign_id {
+        Some(id) => fetch_campaign(&ctx.data().db_pool, id).await?.map(|c| c.name),
+        None => None,
+    };
+    let state = match status.state {
+        SessionState::Paused => "⏸️ Paused",
+        _ => "🔴 Recording",
+    };
+    ctx.say(format!(
+        "**{}** · {}\n**Channel:** {}\n**Started:** <t:{}:f>\n**Recorded:** {}\n**Speakers detected:** {}\n**Disk usage:** {:.1} MiB",
+        session_label(status.id, campaign_name.as_deref(), status.session_number),
+        state,
+        status.voice_channel_id.mention(),
+        status.started_at,
+        format_duration(status.recorded_secs),
+        status.speakers,
+        status.disk_bytes as f64 / (1024.0 * 1024.0),
+    )).await?;
+    Ok(())
+}
+
+/// Approve a session's summary; retention rules count from now
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn approve(
+    ctx: PoiseContext<'_>,
+    #[description = "Session number shown by /chronicle status, e.g. 12 for session #12"]
+    session: i64,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let row = fetch_session(db_pool, session)
+        .await?
+        .filter(|row| row.guild_id == guild_id.get() as i64)
+        .ok_or_else(|| format!("No session #{} in this server.", session))?;
+    if row.ended_at.is_none() {
+        return Err("That session is still recording.".into());
+    }
+    if let Some(approved_at) = row.approved_at {
+        return Err(format!("Session #{} was already approved <t:{}:R>.", session, approved_at).into());
+    }
+
+    approve_session(db_pool, row.id, unix_now()).await?;
+
+    let campaign = match row.campaign_id {
+        Some(id) => fetch_campaign(db_pool, id).await?,
+        None => None,
+    };
+    let policy = RetentionPolicy::for_campaign(&ctx.data().chronicle_config.retention, campaign.as_ref());
+    ctx.say(format!(
+        "✅ Approved {}. Raw audio will be {}; the transcript will be {}.",
+        session_label(row.id, campaign.as_ref().map(|c| c.name.as_str()), row.session_number),
+        policy.raw_audio.describe(),
+        policy.transcript.describe(),
+    )).await?;
+    Ok(())
+}
+
+/// Run a session's processing again, e.g. after editing a prompt or swapping the model
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+async fn reprocess(
+    ctx: PoiseContext<'_>,
+    #[description = "Session number shown by /chronicle status, e.g. 12 for session #12"]
+    session: i64,
+    #[description = "Stage to run again from (default: only what's out of date)"]
+    from_stage: Option<Stage>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let data = ctx.data();
+    let row = fetch_session(&data.db_pool, session)
+        .await?
+        .filter(|row| row.guild_id == guild_id.get() as i64)
+        .ok_or_else(|| format!("No session #{} in this server.", session))?;
+    if row.ended_at.is_none() {
+        return Err("That session is still recording.".into());
+    }
+
+    data.pipeline.enqueue(data.db_pool.clone(), data.chronicle_config.clone(), ctx.serenity_context().http.clone(), row.id, from_stage).await?;
+    let from = match from_stage {
+        Some(stage) => format!("from **{}**", stage.as_str()),
+        None => "wherever its inputs changed".to_string(),
+    };
+    ctx.say(format!(
+        "🔁 Reprocessing session #{} {}. The result will be posted in <#{}>.",
+        row.id,
+        from,
+        row.text_channel_id,
+    )).await?;
+    Ok(())
+}
+
+/// Show everything the chronicle knows about an NPC, place, item or quest
+#[poise::command(slash_command, guild_only)]
+async fn lookup(
+    ctx: PoiseContext<'_>,
+    #[description = "Name of the NPC, location, item or quest"]
+    #[autocomplete = "autocomplete_entity"]
+    name: String,
+    #[description = "Campaign (defaults to the one played here)"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = resolve_campaign(ctx, campaign).await?;
+
+    let entities = lookup_entities(db_pool, campaign.id, &name).await?;
+    if entities.is_empty() {
+        let suggestions = search_entity_names(db_pool, guild_id, Some(campaign.id), &name, LOOKUP_SUGGESTIONS).await?;
+        let mut message = format!("Nothing named **{}** has come up in **{}**.", name.trim(), campaign.name);
+        if !suggestions.is_empty() {
+            message.push_str(&format!(" Did you mean {}?", suggestions.join(", ")));
+        }
+        ctx.send(poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+        ).await?;
+        return Ok(());
+    }
+
+    let mut blocks = Vec::with_capacity(entities.len());
+    for entity in entities {
+        let kind: EntityKind = entity.kind.parse()?;
+        let mut block = format!("**{}** · {}", entity.name, kind.label());
+        let state = entity_state(kind, entity.status.as_deref(), entity.details.as_deref());
+        if !state.is_empty() {
+            block.push_str(&format!(" · {}", state));
+        }
+        if let Some(description) = &entity.description {
+            block.push_str(&format!("\n{}", description));
+        }
+
+        let mentions = fetch_entity_mentions(db_pool, entity.id).await?;
+        block.push_str(&format!("\nAppeared in {} session(s):", mentions.len()));
+        for mention in mentions {
+            let session = match mention.session_number {
+                Some(number) => format!("Session {}", number),
+                None => format!("Session #{}", mention.session_id),
+            };
+            let mut line = format!("\n- {} · <t:{}:D>", session, mention.started_at);
+            let state = entity_state(kind, mention.status.as_deref(), mention.details.as_deref());
+            if !state.is_empty() {
+                line.push_str(&format!(" · {}", state));
+            }
+            if let Some(description) = &mention.description {
+                line.push_str(&format!(": {}", description));
+            }
+            block.push_str(&line);
+        }
+        blocks.push(block);
+    }
+
+    let mut content = blocks.join("\n\n");
+    if content.chars().count() > LOOKUP_MAX_CHARS {
+        content = content.chars().take(LOOKUP_MAX_CHARS - 1).collect::<String>() + "…";
+    }
+    ctx.send(poise::CreateReply::default()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// "dead · hostile", "held by Thalia" or "completed · given by Vajra", as known.
+fn entity_state(kind: EntityKind, status: Option<&str>, details: Option<&str>) -> String {
+    let parts: Vec<String> = match kind {
+        EntityKind::Npc | EntityKind::Location => status.into_iter().chain(details).map(str::to_string).collect(),
+        EntityKind::Item => match (status, details) {
+            (Some("lost"), _) => vec!["lost".to_string()],
+            (_, Some(holder)) => vec![format!("held by {}", holder)],
+            (status, None) => status.into_iter().map(str::to_string).collect(),
+        },
+        EntityKind::Quest => status
+            .map(str::to_string)
+            .into_iter()
+            .chain(details.map(|giver| format!("given by {}", giver)))
+            .collect(),
+    };
+    parts.join(" · ")
+}
+
+/// Search the campaign's transcripts for something said at the table
+#[poise::command(slash_command, guild_only)]
+async fn search(
+    ctx: PoiseContext<'_>,
+    #[description = "Words to find; \"quote\" a phrase, end a word with * to match its start"]
+    query: String,
+    #[description = "Only lines spoken by this member"]
+    speaker: Option<serenity::User>,
+    #[description = "Only this session number"]
+    #[min = 1]
+    session: Option<i64>,
+    #[description = "Campaign (defaults to the one played here)"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: Option<String>,
+) -> Result<(), Error> {
+    let campaign = resolve_campaign(ctx, campaign).await?;
+    let fts = fts_query(&query).ok_or("Search for at least one word.")?;
+    ctx.defer().await?;
+
+    let db_pool = &ctx.data().db_pool;
+    index_missing(db_pool, campaign.id).await?;
+    let hits = search_transcripts(db_pool, campaign.id, &fts, speaker.map(|u| u.id.get()), session, SEARCH_RESULTS).await?;
+    if hits.is_empty() {
+        ctx.say(format!("Nothing in **{}**'s transcripts matches that.", campaign.name)).await?;
+        return Ok(());
+    }
+
+    let mut content = format!("🔎 **{}**", campaign.name);
+    let mut buttons = Vec::with_capacity(hits.len());
+    for (index, hit) in hits.iter().enumerate() {
+        let session = match hit.session_number {
+            Some(number) => format!("Session {}", number),
+            None => format!("Session #{}", hit.session_id),
+        };
+        content.push_str(&format!(
+            "\n**{}.** {} `[{}]` **{}**: {}",
+            index + 1,
+            session,
+            clock(hit.start_ms.max(0) as u64),
+            hit.speaker_name,
+            hit.snippet,
+        ));
+        buttons.push(play_button(hit.session_id, hit.start_ms, format!("▶ {}", index + 1)));
+    }
+    if content.chars().count() > SEARCH_MAX_CHARS {
+        content = content.chars().take(SEARCH_MAX_CHARS - 1).collect::<String>() + "…";
+    }
+
+    ctx.send(poise::CreateReply::default()
+        .content(content)
+        .components(vec![serenity::CreateActionRow::Buttons(buttons)])
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// Post a "previously on" recap of the campaign's last few sessions
+#[poise::command(slash_command, guild_only)]
+async fn recap(
+    ctx: PoiseContext<'_>,
+    #[description = "How many recent sessions to cover (default 3)"]
+    #[min = 1]
+    #[max = 10]
+    sessions: Option<u32>,
+    #[description = "Campaign (defaults to the one played here)"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: Option<String>,
+    #[description = "Also read it aloud in your voice channel"]
+    read_aloud: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let config = &ctx.data().chronicle_config;
+    let campaign = resolve_campaign(ctx, campaign).await?;
+    let synthesizer = if read_aloud.unwrap_or(false) {
+        Some(crate::ai::synthesizer(&config.ai)
+            .ok_or("Reading recaps aloud needs a Piper voice; set `chronicle.ai.tts.model`.")?)
+    } else {
+        None
+    };
+    ctx.defer().await?;
+
+    // Newest first until there are enough, skipping sessions never summarised
+    let db_pool = &ctx.data().db_pool;
+    let count = sessions.unwrap_or(RECAP_DEFAULT_SESSIONS) as usize;
+    let mut recent: Vec<_> = fetch_campaign_sessions(db_pool, campaign.id)
+        .await?
+        .into_iter()
+        .rev()
+        .filter_map(|session| {
+            let summary = SessionSummary::load(Path::new(&session.storage_dir)).ok()?;
+            Some((session, summary))
+        })
+        .take(count)
+        .collect();
+    recent.reverse();
+    let Some((latest, _)) = recent.last() else {
+        return Err(format!("**{}** has no summarised sessions to recap yet.", campaign.name).into());
+    };
+
+    let context = SummaryContext::load(db_pool, config, latest).await?;
+    let summaries: Vec<(String, SessionSummary)> = recent
+        .into_iter()
+        .map(|(session, summary)| {
+            let label = match session.session_number {
+                Some(number) => format!("Session {}", number),
+                None => format!("Session #{}", session.id),
+            };
+            (label, summary)
+        })
+        .collect();
+    let text = Processor::new(crate::ai::llm(&config.ai)).recap(&context, &summaries).await?;
+
+    let mut content = format!("📜 **Previously on {}…**\n\n{}", campaign.name, text);
+    if content.chars().count() > RECAP_MAX_CHARS {
+        content = content.chars().take(RECAP_MAX_CHARS - 1).collect::<String>() + "…";
+    }
+    let mut reply = poise::CreateReply::default().allowed_mentions(serenity::CreateAllowedMentions::new());
+
+    if let Some(synthesizer) = synthesizer {
+        let dir = config.storage_root.join(RECAPS_DIR);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        // Only the newest recap of a campaign is kept
+        let prefix = format!("{}-", campaign.id);
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            for entry in entries.filter_map(Result::ok) {
+                if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    std::fs::remove_file(entry.path()).ok();
+                }
+            }
+        }
+
+        let path = dir.join(format!("{}{}.wav", prefix, unix_now()));
+        synthesizer
+            .synthesize(&text, &path)
+            .await
+            .map_err(|e| format!("{} couldn't read the recap: {}", synthesizer.name(), e))?;
+        reply = reply.attachment(CreateAttachment::path(&path).await?);
+
+        match get_vc_id(ctx).await {
+            Ok(vc_id) => {
+                if let Err(e) = play_file(ctx.serenity_context(), ctx.data(), guild_id, vc_id, &path, Duration::ZERO, None).await {
+                    content.push_str(&format!("\n\n*Couldn't read it aloud: {}*", e));
+                }
+            }
+            Err(_) => content.push_str("\n\n*Join a voice channel to hear it read aloud.*"),
+        }
+    }
+
+    ctx.send(reply.content(content)).await?;
+    Ok(())
+}
+
+/// Choose whether chronicle may record your voice in this server
+#[poise::command(slash_command, guild_only, subcommands("consent_give", "consent_revoke", "consent_status", "consent_log"), subcommand_required)]
+async fn consent(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Agree to have your voice recorded in chronicle sessions
+#[poise::command(slash_command, guild_only, rename = "give", ephemeral)]
+async fn consent_give(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let session = apply_consent(ctx.data(), guild_id, ctx.author().id.get(), true, ConsentSource::Command).await?;
+    ctx.say(match session {
+        Some(_) => "You're being recorded from now on.",
+        None => "You'll be recorded in future chronicle sessions.",
+    }).await?;
+    Ok(())
+}
+
+/// Stop chronicle recording your voice, starting immediately
+#[poise::command(slash_command, guild_only, rename = "revoke", ephemeral)]
+async fn consent_revoke(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let session = apply_consent(ctx.data(), guild_id, ctx.author().id.get(), false, ConsentSource::Command).await?;
+    ctx.say(match session {
+        Some(_) => "Your voice is no longer being recorded.",
+        None => "Your voice won't be recorded in chronicle sessions.",
+    }).await?;
+    Ok(())
+}
+
+/// Show your recording consent and its recent changes
+#[poise::command(slash_command, guild_only, rename = "status", ephemeral)]
+async fn consent_status(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let user_id = ctx.author().id.get();
+    let db_pool = &ctx.data().db_pool;
+
+    let decision = match fetch_consent(db_pool, guild_id, user_id).await? {
+        Some(true) => "✅ You've agreed to be recorded.",
+        Some(false) => "🚫 You've opted out of recording.",
+        None => "You haven't decided yet, so you aren't recorded.",
+    };
+    let history = fetch_consent_log(db_pool, guild_id, Some(user_id), CONSENT_LOG_LISTED).await?;
+    let mut lines = vec![decision.to_string()];
+    lines.extend(history.iter().map(|entry| format!(
+        "<t:{}:f> · {} · via {}",
+        entry.changed_at,
+        if entry.consented { "agreed" } else { "opted out" },
+        entry.source,
+    )));
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Show recent consent changes in this server
+#[poise::command(slash_command, guild_only, rename = "log", required_permissions = "MANAGE_GUILD", ephemeral)]
+async fn consent_log(
+    ctx: PoiseContext<'_>,
+    #[description = "Only this member's changes"]
+    member: Option<serenity::User>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let entries = fetch_consent_log(
+        &ctx.data().db_pool,
+        guild_id,
+        member.as_ref().map(|m| m.id.get()),
+        CONSENT_LOG_LISTED,
+    ).await?;
+
+    if entries.is_empty() {
+        ctx.say("No consent changes recorded.").await?;
+        return Ok(());
+    }
+    let lines: Vec<String> = entries.iter().map(|entry| format!(
+        "<t:{}:f> · {} {} · via {}{}",
+        entry.changed_at,
+        serenity::UserId::new(entry.user_id as u64).mention(),
+        if entry.consented { "agreed" } else { "opted out" },
+        entry.source,
+        entry.session_id.map(|id| format!(" during session #{}", id)).unwrap_or_default(),
+    )).collect();
+    ctx.send(poise::CreateReply::default()
+        .content(lines.join("\n"))
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// See and change how long session files are kept
+#[poise::command(slash_command, guild_only, subcommands("retention_upcoming", "retention_log", "retention_set"), subcommand_required)]
+async fn retention(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show session files that are about to be deleted
+#[poise::command(slash_command, guild_only, rename = "upcoming")]
+async fn retention_upcoming(
+    ctx: PoiseContext<'_>,
+    #[description = "Look this many days ahead (default 14)"]
+    #[min = 1]
+    #[max = 365]
+    days: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let horizon = unix_now() + days.unwrap_or(14) as i64 * 86_400;
+    let plan = retention_plan(&ctx.data().db_pool, &ctx.data().chronicle_config.retention, Some(guild_id)).await?;
+
+    let upcoming: Vec<_> = plan.expiries.iter().filter(|e| e.expires_at <= horizon).collect();
+    let mut lines: Vec<String> = upcoming
+        .iter()
+        .take(EXPIRIES_LISTED)
+        .map(|expiry| format!(
+            "<t:{}:R> · session #{} · {} · {:.1} MiB",
+            expiry.expires_at,
+            expiry.session.id,
+            expiry.kind.label(),
+            expiry.bytes as f64 / (1024.0 * 1024.0),
+        ))
+        .collect();
+    if upcoming.len() > EXPIRIES_LISTED {
+        lines.push(format!("…and {} more", upcoming.len() - EXPIRIES_LISTED));
+    }
+    if lines.is_empty() {
+        lines.push(format!("Nothing expires in the next {} day(s).", days.unwrap_or(14)));
+    }
+    if !plan.awaiting_approval.is_empty() {
+        let ids: Vec<String> = plan.awaiting_approval.iter().map(|s| format!("#{}", s.id)).collect();
+        lines.push(format!(
+            "\nKept until their summary is approved with `/chronicle approve`: {}",
+            ids.join(", "),
+        ));
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Show what retention has deleted
+#[poise::command(slash_command, guild_only, rename = "log")]
+async fn retention_log(ctx: PoiseContext<'_>) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let deletions = fetch_deletions(&ctx.data().db_pool, guild_id, DELETIONS_LISTED).await?;
+    if deletions.is_empty() {
+        ctx.say("Retention hasn't deleted anything yet.").await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = deletions.iter().map(|d| format!(
+        "<t:{}:f> · session #{} · {} `{}` ({:.1} MiB) · {}",
+        d.deleted_at,
+        d.session_id,
+        RetainedKind::ALL.into_iter().find(|k| k.as_str() == d.kind).map_or(d.kind.as_str(), |k| k.label()),
+        std::path::Path::new(&d.path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+        d.bytes as f64 / (1024.0 * 1024.0),
+        d.rule,
+    )).collect();
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Override how long a campaign's session files are kept
+#[poise::command(slash_command, guild_only, rename = "set", required_permissions = "MANAGE_GUILD")]
+async fn retention_set(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+    #[description = "Days to keep raw audio after approval, `forever` or `default`"]
+    raw_audio: Option<String>,
+    #[description = "Days to keep transcripts after approval, `forever` or `default`"]
+    transcript: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    let audio_days = match raw_audio {
+        Some(value) => parse_retention_override(&value)?,
+        None => campaign.audio_retention_days,
+    };
+    let transcript_days = match transcript {
+        Some(value) => parse_retention_override(&value)?,
+        None => campaign.transcript_retention_days,
+    };
+    set_campaign_retention(db_pool, campaign.id, audio_days, transcript_days).await?;
+
+    let updated = fetch_campaign(db_pool, campaign.id).await?;
+    let policy = RetentionPolicy::for_campaign(&ctx.data().chronicle_config.retention, updated.as_ref());
+    ctx.say(format!(
+        "In **{}**, raw audio is {} and transcripts are {}.",
+        campaign.name,
+        policy.raw_audio.describe(),
+        policy.transcript.describe(),
+    )).await?;
+    Ok(())
+}
+
+/// Stored form of a campaign override: `None` inherits, -1 keeps forever.
+fn parse_retention_override(value: &str) -> Result<Option<i64>, Error> {
+    match value.trim().to_lowercase().as_str() {
+        "default" => Ok(None),
+        "forever" => Ok(Some(-1)),
+        days => days
+            .parse::<u32>()
+            .map(|d| Some(d as i64))
+            .map_err(|_| format!("`{}` isn't a number of days, `forever` or `default`.", value).into()),
+    }
+}
+
+/// Manage campaigns and their characters
+#[poise::command(slash_command, guild_only, subcommands("campaign_create", "campaign_list", "campaign_set_character", "campaign_archive"), subcommand_required)]
+async fn campaign(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Create a campaign; recordings in its channel attach to it
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "create")]
+async fn campaign_create(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign name"]
+    name: String,
+    #[description = "Folder in the Obsidian vault (defaults to the name)"]
+    obsidian_folder: Option<String>,
+    #[description = "Channel the campaign is played in (defaults to this one)"]
+    #[channel_types("Text", "Voice")]
+    channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("The campaign name can't be empty.".into());
+    }
+
+    let folder = obsidian_folder
+        .map(|f| f.trim().trim_matches('/').to_string())
+        .filter(|f| !f.is_empty())
+        .unwrap_or_else(|| default_obsidian_folder(name));
+    if folder.is_empty() {
+        return Err("Give the campaign an Obsidian folder; its name has no usable characters.".into());
+    }
+    if folder.split('/').any(|part| part == "..") {
+        return Err("The Obsidian folder must stay inside the vault.".into());
+    }
+
+    let channel_id = channel.map(|c| c.id).unwrap_or_else(|| ctx.channel_id());
+    insert_campaign(
+        &ctx.data().db_pool,
+        guild_id,
+        name,
+        &folder,
+        Some(channel_id.get() as i64),
+        unix_now(),
+    ).await?;
+
+    ctx.say(format!(
+        "Created campaign **{}**, played in {} and filed under `{}`.",
+        name,
+        channel_id.mention(),
+        folder,
+    )).await?;
+    Ok(())
+}
+
+/// List this server's campaigns
+#[poise::command(slash_command, guild_only, rename = "list")]
+async fn campaign_list(
+    ctx: PoiseContext<'_>,
+    #[description = "Include archived campaigns"]
+    archived: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaigns = fetch_campaigns(db_pool, guild_id, archived.unwrap_or(false)).await?;
+
+    if campaigns.is_empty() {
+        ctx.say("No campaigns yet. Create one with `/chronicle campaign create`.").await?;
+        return Ok(());
+    }
+
+    let mut lines = Vec::with_capacity(campaigns.len());
+    for campaign in campaigns {
+        let sessions = fetch_campaign_sessions(db_pool, campaign.id).await?.len();
+        let characters: Vec<String> = fetch_characters(db_pool, campaign.id)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let user_id = row.user_id as u64;
+                format!("{} ({})", Character::from(row).describe(), serenity::UserId::new(user_id).mention())
+            })
+            .collect();
+
+        lines.push(format!(
+            "**{}**{} · {} · `{}` · {} session(s)\n{}",
+            campaign.name,
+            if campaign.archived { " (archived)" } else { "" },
+            campaign.channel_id
+                .map(|id| ChannelId::new(id as u64).mention().to_string())
+                .unwrap_or_else(|| "no channel".to_string()),
+            campaign.obsidian_folder,
+            sessions,
+            if characters.is_empty() {
+                "No characters yet".to_string()
+            } else {
+                characters.join(", ")
+            },
+        ));
+    }
+
+    ctx.send(poise::CreateReply::default()
+        .content(lines.join("\n\n"))
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// Set the character a player plays in a campaign
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "set-character")]
+async fn campaign_set_character(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+    #[description = "The player"]
+    player: serenity::User,
+    #[description = "Character name, e.g. Thalia"]
+    character: String,
+    #[description = "Class or role, e.g. bard"]
+    class: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    let character = character.trim();
+    if character.is_empty() {
+        return Err("The character name can't be empty.".into());
+    }
+    let class = class.as_deref().map(str::trim).filter(|c| !c.is_empty());
+
+    upsert_character(db_pool, campaign.id, player.id.get(), character, class).await?;
+
+    let described = Character {
+        user_id: player.id.get(),
+        name: character.to_string(),
+        class: class.map(str::to_string),
+    }.describe();
+    ctx.send(poise::CreateReply::default()
+        .content(format!("{} plays **{}** in **{}**.", player.mention(), described, campaign.name))
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// Archive a finished campaign so new recordings no longer attach to it
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "archive")]
+async fn campaign_archive(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    if campaign.archived {
+        return Err(format!("**{}** is already archived.", campaign.name).into());
+    }
+    set_campaign_archived(db_pool, campaign.id, true).await?;
+    ctx.say(format!("Archived **{}**.", campaign.name)).await?;
+    Ok(())
+}
+
+/// Names and terms transcripts are corrected towards
+#[poise::command(slash_command, guild_only, subcommands("glossary_add", "glossary_remove", "glossary_list"), subcommand_required)]
+async fn glossary(_ctx: PoiseContext<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Teach the transcriber how a name or term is spelled
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "add")]
+async fn glossary_add(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+    #[description = "As it should be spelled, e.g. Zanathar"]
+    term: String,
+    #[description = "What it names"]
+    kind: Option<TermKind>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !term.chars().any(char::is_alphanumeric) {
+        return Err("The term needs at least one letter or digit.".into());
+    }
+    if term.chars().count() > GLOSSARY_TERM_MAX_CHARS {
+        return Err(format!("Keep terms under {} characters.", GLOSSARY_TERM_MAX_CHARS).into());
+    }
+
+    let kind = kind.unwrap_or(TermKind::Other);
+    upsert_glossary_term(db_pool, campaign.id, &term, kind.as_str(), unix_now()).await?;
+    ctx.send(poise::CreateReply::default()
+        .content(format!("Added **{}** ({}) to the glossary of **{}**.", term, kind.as_str(), campaign.name))
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// Remove a term added with `/chronicle glossary add`
+#[poise::command(slash_command, guild_only, required_permissions = "MANAGE_GUILD", rename = "remove")]
+async fn glossary_remove(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+    #[description = "The term to remove"]
+    term: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !delete_glossary_term(db_pool, campaign.id, &term).await? {
+        return Err(format!(
+            "**{}** isn't in the glossary of **{}**. Character names and Obsidian notes are \
+             added automatically and can only be removed at their source.",
+            term, campaign.name,
+        ).into());
+    }
+    ctx.send(poise::CreateReply::default()
+        .content(format!("Removed **{}** from the glossary of **{}**.", term, campaign.name))
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// Show every term a campaign's transcripts are corrected towards
+#[poise::command(slash_command, guild_only, rename = "list")]
+async fn glossary_list(
+    ctx: PoiseContext<'_>,
+    #[description = "Campaign"]
+    #[autocomplete = "autocomplete_campaign"]
+    campaign: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let campaign = require_campaign(db_pool, guild_id, &campaign).await?;
+
+    let roster = Roster::load(db_pool, Some(campaign.id)).await?;
+    let vault = ctx.data().chronicle_config.obsidian.vault.as_deref();
+    let glossary = Glossary::load(db_pool, &campaign, vault, &roster).await?;
+    if glossary.is_empty() {
+        ctx.say(format!(
+            "**{}** has no glossary yet. Add characters, Obsidian notes or `/chronicle glossary add` terms.",
+            campaign.name,
+        )).await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![format!("**Glossary of {}**", campaign.name)];
+    let mut kinds: Vec<TermKind> = glossary.terms().iter().map(|t| t.kind).collect();
+    kinds.dedup();
+    for kind in kinds {
+        let terms: Vec<&str> = glossary.terms().iter().filter(|t| t.kind == kind).map(|t| t.text.as_str()).collect();
+        lines.push(format!("{}: {}", kind.label(), terms.join(", ")));
+    }
+    let mut content = lines.join("\n");
+    if content.chars().count() > GLOSSARY_LIST_MAX_CHARS {
+        content = content.chars().take(GLOSSARY_LIST_MAX_CHARS - 1).collect::<String>() + "…";
+    }
+
+    ctx.send(poise::CreateReply::default()
+        .content(content)
+        .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    ).await?;
+    Ok(())
+}
+
+/// The named campaign, or else the one played in this channel or the caller's voice channel.
+async fn resolve_campaign(ctx: PoiseContext<'_>, name: Option<String>) -> Result<CampaignRow, Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    if let Some(name) = name {
+        return require_campaign(db_pool, guild_id, &name).await;
+    }
+
+    let channel_id = ctx.channel_id();
+    let vc_id = get_vc_id(ctx).await.unwrap_or(channel_id);
+    find_active_campaign(db_pool, guild_id, channel_id.get() as i64, vc_id.get() as i64)
+        .await?
+        .ok_or_else(|| "No campaign is played in this channel; choose one with the `campaign` option.".into())
+}
+
+fn session_label(id: i64, campaign: Option<&str>, number: Option<i64>) -> String {
+    match (campaign, number) {
+        (Some(campaign), Some(number)) => format!("{} session {}", campaign, number),
+        _ => format!("session #{}", id),
+    }
+}
+
+/// Posts `message` as the command reply and, best effort, in the voice channel's chat.
+async fn announce(ctx: PoiseContext<'_>, vc_id: ChannelId, message: String) -> Result<(), Error> {
+    ctx.say(&message).await?;
+    if vc_id != ctx.channel_id()
+        && let Err(e) = vc_id.say(ctx.http(), &message).await
+    {
+        tracing::debug!("Couldn't announce in voice channel chat: {}", e);
+    }
+    Ok(())
+}
+
+/// Creates the thread live captions are posted to. Captions are a nicety, so a
+/// channel that can't hold threads just means recording without them.
+async fn open_captions_thread(ctx: PoiseContext<'_>, campaign_name: Option<&str>) -> Option<Captions> {
+    let name = format!("Live captions · {} · {}", campaign_name.unwrap_or("Session"), format_date(unix_now()));
+    let builder = serenity::CreateThread::new(name)
+        .kind(serenity::ChannelType::PublicThread)
+        .auto_archive_duration(serenity::AutoArchiveDuration::OneDay);
+    match ctx.channel_id().create_thread(ctx.http(), builder).await {
+        Ok(thread) => Some(Captions { http: ctx.serenity_context().http.clone(), thread: thread.id }),
+        Err(e) => {
+            tracing::warn!("Couldn't create a live captions thread: {}", e);
+            None
+        }
+    }
+}
+
+/// Users (other than the bot) currently connected to `vc_id`.
+fn voice_channel_members(ctx: PoiseContext<'_>, guild_id: GuildId, vc_id: ChannelId) -> Vec<u64> {
+    let bot_id = ctx.serenity_context().cache.current_user().id;
+    ctx.serenity_context()
+        .cache
+        .guild(guild_id)
+        .map(|g| {
+            g.voice_states
+                .values()
+                .filter(|vs| vs.channel_id == Some(vc_id) && vs.user_id != bot_id)
+                .map(|vs| vs.user_id.get())
+                .collect()
+        })
+        .unwrap_or_default()
+}
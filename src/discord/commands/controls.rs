@@ -8,6 +8,7 @@ use crate::utils::track_resolver::resolve_track;
 pub async fn join(ctx: PoiseContext<'_>) -> Result<(), Error> {
     let guild = ctx.guild().ok_or("Must be in a guild")?.clone();
     let vc_id = get_vc_id(ctx).await?;
+    ctx.data().chronicle.ensure_not_recording(guild.id).await?;
     join_vc(ctx, guild, vc_id).await?;
     ctx.say("Joined your voice channel! 🎶").await?;
     Ok(())
@@ -23,7 +24,8 @@ pub async fn play(
 ) -> Result<(), Error> {
     let guild_id = require_guild(ctx)?;
     let vc_id = get_vc_id(ctx).await?;
-    let track_info = resolve_track(&ctx.data().db_pool, track).await?;
+    ctx.data().chronicle.ensure_not_recording(guild_id).await?;
+    let track_info = resolve_track(&ctx.data().db_pool, guild_id, track).await?;
 
     ctx.data().player.play(
         guild_id,
@@ -95,6 +97,8 @@ pub async fn leave(ctx: PoiseContext<'_>) -> Result<(), Error> {
     let guild_id = require_guild(ctx)?;
     let _ = get_vc_id(ctx).await?; // verify user is in a voice channel
 
+    ctx.data().chronicle.ensure_not_recording(guild_id).await?;
+
     ctx.data().player.leave(guild_id, ctx.serenity_context()).await?;
 
     ctx.say("Left the voice channel.").await?;
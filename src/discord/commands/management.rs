@@ -1,3 +1,11 @@
+use std::time::Duration;
+
+use poise::CreateReply;
+use poise::serenity_prelude::{
+    ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, GuildId,
+};
+
 use crate::definitions::{Error, MetadataKind, PoiseContext, TrackInfo, VideoId};
 use crate::discord::autocomplete::{
     autocomplete_track,
@@ -6,12 +14,20 @@ use crate::discord::autocomplete::{
     autocomplete_artist,
     autocomplete_incomplete_track
 };
+use crate::utils::context::require_guild;
 use crate::utils::downloader::download_track;
+use crate::utils::metadata_archive::load_info_json;
+use crate::utils::metadata_suggest::{suggest_metadata, MetadataSuggestion};
 use crate::db::repository::{
     get_or_insert_metadata_id, require_track,
     delete_track_tags, insert_track_tag,
     update_track_title, update_track_artist, update_track_origin,
+    set_track_shared, set_track_hidden, set_guild_share_global,
+    add_guild_track, fetch_track_owner, track_available,
 };
+use crate::utils::track_resolver::normalise_track_input;
+
+const SUGGESTION_TIMEOUT: Duration = Duration::from_secs(300);
 
 pub async fn download_direct(
     ctx: PoiseContext<'_>,
@@ -19,23 +35,100 @@ pub async fn download_direct(
     track_artist: Option<String>,
     track_origin: Option<String>,
     track_title: Option<String>,
+    accept_suggestions: bool,
 ) -> Result<TrackInfo, Error> {
+    let guild_id = require_guild(ctx)?;
     ctx.defer().await?;
 
-    let track = download_track(
+    let outcome = download_track(
         &ctx.data().db_pool,
+        guild_id,
         yt_link,
         track_artist,
         track_origin,
         track_title,
+        accept_suggestions,
     )
     .await?;
 
-    ctx.say(format!(
+    let message = format!(
         "File downloaded and added to the library: `{}`",
-        track.title
-    ))
-    .await?;
+        outcome.track.title
+    );
+
+    if outcome.pending.is_empty() {
+        ctx.say(message).await?;
+        return Ok(outcome.track);
+    }
+
+    offer_suggestions(ctx, guild_id, message, outcome.track, outcome.pending).await
+}
+
+/// Shows unapplied metadata suggestions with buttons to apply or dismiss them.
+async fn offer_suggestions(
+    ctx: PoiseContext<'_>,
+    guild_id: GuildId,
+    message: String,
+    track: TrackInfo,
+    suggestion: MetadataSuggestion,
+) -> Result<TrackInfo, Error> {
+    let ctx_id = ctx.id();
+    let apply_id = format!("{}apply", ctx_id);
+    let keep_id = format!("{}keep", ctx_id);
+
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&apply_id).label("Apply suggestions").style(ButtonStyle::Primary),
+        CreateButton::new(&keep_id).label("Keep as is").style(ButtonStyle::Secondary),
+    ]);
+    let reply = CreateReply::default()
+        .content(format!("{}\nSuggested metadata: {}", message, suggestion.describe()))
+        .components(vec![buttons]);
+    let handle = ctx.send(reply).await?;
+
+    let press = ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(SUGGESTION_TIMEOUT)
+        .await;
+
+    let Some(press) = press else {
+        handle.edit(ctx, CreateReply::default().content(message).components(vec![])).await?;
+        return Ok(track);
+    };
+
+    let db_pool = &ctx.data().db_pool;
+    let mut track = track;
+    let outcome = if press.data.custom_id == apply_id {
+        if let Some(title) = suggestion.title {
+            update_track_title(db_pool, guild_id, &track.id, &title).await?;
+            track.title = title;
+        }
+        if let Some(artist) = suggestion.artist {
+            let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, &artist).await?;
+            update_track_artist(db_pool, guild_id, &track.id, artist_id).await?;
+            track.artist = artist;
+        }
+        if let Some(origin) = suggestion.origin {
+            let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, &origin).await?;
+            update_track_origin(db_pool, guild_id, &track.id, origin_id).await?;
+            track.origin = origin;
+        }
+        format!(
+            "{}\nApplied: `{}` by `{}`, from `{}`.",
+            message, track.title, track.artist, track.origin
+        )
+    } else {
+        message
+    };
+
+    press
+        .create_response(
+            ctx.serenity_context(),
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new().content(outcome).components(vec![]),
+            ),
+        )
+        .await?;
 
     Ok(track)
 }
@@ -54,8 +147,17 @@ pub async fn download(
     track_origin: Option<String>,
     #[description = "The actual title of the track"]
     track_title: Option<String>,
+    #[description = "Fill blank fields from the video's metadata without asking"]
+    accept_suggestions: Option<bool>,
 ) -> Result<(), Error> {
-    download_direct(ctx, yt_link, track_artist, track_origin, track_title).await?;
+    download_direct(
+        ctx,
+        yt_link,
+        track_artist,
+        track_origin,
+        track_title,
+        accept_suggestions.unwrap_or(false),
+    ).await?;
     Ok(())
 }
 
@@ -67,10 +169,11 @@ pub async fn reset_tags(
     #[autocomplete = "autocomplete_track"]
     track: String,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
-    let info = require_track(db_pool, &VideoId::from(track)).await?;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
 
-    delete_track_tags(db_pool, &info.id).await?;
+    delete_track_tags(db_pool, guild_id, &info.id).await?;
 
     ctx.say(format!("Reset tags for track `{}`", info.title)).await?;
     Ok(())
@@ -87,11 +190,12 @@ pub async fn add_tag(
     #[autocomplete = "autocomplete_tag"]
     tag: String,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
-    let info = require_track(db_pool, &VideoId::from(track)).await?;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
     let tag_id = get_or_insert_metadata_id(db_pool, MetadataKind::Tag, &tag).await?;
 
-    insert_track_tag(db_pool, &info.id, tag_id).await?;
+    insert_track_tag(db_pool, guild_id, &info.id, tag_id).await?;
 
     ctx.say(format!("Tag `{}` added to track `{}`", tag, info.title)).await?;
     Ok(())
@@ -115,11 +219,12 @@ pub async fn title(
     #[description = "The new title to give the track"]
     new_title: String,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
     let track_id = VideoId::from(track);
-    let info = require_track(db_pool, &track_id).await?;
+    let info = require_track(db_pool, guild_id, &track_id).await?;
 
-    update_track_title(db_pool, &info.id, &new_title).await?;
+    update_track_title(db_pool, guild_id, &info.id, &new_title).await?;
 
     ctx.say(format!(
         "Set new title `{}` for track `{}`",
@@ -141,11 +246,12 @@ pub async fn artist(
     #[autocomplete = "autocomplete_artist"]
     new_artist: String,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
-    let info = require_track(db_pool, &VideoId::from(track)).await?;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
     let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, &new_artist).await?;
 
-    update_track_artist(db_pool, &info.id, artist_id).await?;
+    update_track_artist(db_pool, guild_id, &info.id, artist_id).await?;
 
     ctx.say(format!(
         "Set new artist `{}` for track `{}`",
@@ -167,11 +273,12 @@ pub async fn origin(
     #[autocomplete = "autocomplete_origin"]
     new_origin: String,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
     let db_pool = &ctx.data().db_pool;
-    let info = require_track(db_pool, &VideoId::from(track)).await?;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
     let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, &new_origin).await?;
 
-    update_track_origin(db_pool, &info.id, origin_id).await?;
+    update_track_origin(db_pool, guild_id, &info.id, origin_id).await?;
 
     ctx.say(format!(
         "Set new origin `{}` for track `{}`",
@@ -198,31 +305,42 @@ pub async fn fix(
     #[autocomplete = "autocomplete_origin"]
     new_origin: Option<String>,
 ) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let track_id = VideoId::from(track);
+    let info = require_track(db_pool, guild_id, &track_id).await?;
+
     if new_title.is_none() && new_artist.is_none() && new_origin.is_none() {
-        ctx.say("Please provide at least one field to update.").await?;
+        // Fall back to suggestions from the archived yt-dlp metadata
+        let suggestion = load_info_json(track_id.as_str())?
+            .map(|v| suggest_metadata(&v))
+            .unwrap_or_default();
+
+        if suggestion.is_empty() {
+            ctx.say("Please provide at least one field to update.").await?;
+        } else {
+            let message = format!("No fields given for `{}`.", info.title);
+            offer_suggestions(ctx, guild_id, message, info, suggestion).await?;
+        }
         return Ok(());
     }
 
-    let db_pool = &ctx.data().db_pool;
-    let track_id = VideoId::from(track);
-    let info = require_track(db_pool, &track_id).await?;
-
     let mut updated_fields: Vec<String> = Vec::new();
 
     if let Some(ref title) = new_title {
-        update_track_title(db_pool, &info.id, title).await?;
+        update_track_title(db_pool, guild_id, &info.id, title).await?;
         updated_fields.push(format!("title → `{}`", title));
     }
 
     if let Some(ref artist) = new_artist {
         let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, artist).await?;
-        update_track_artist(db_pool, &info.id, artist_id).await?;
+        update_track_artist(db_pool, guild_id, &info.id, artist_id).await?;
         updated_fields.push(format!("artist → `{}`", artist));
     }
 
     if let Some(ref origin) = new_origin {
         let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, origin).await?;
-        update_track_origin(db_pool, &info.id, origin_id).await?;
+        update_track_origin(db_pool, guild_id, &info.id, origin_id).await?;
         updated_fields.push(format!("origin → `{}`", origin));
     }
 
@@ -232,5 +350,136 @@ pub async fn fix(
         updated_fields.join(", ")
     )).await?;
 
+    Ok(())
+}
+
+/// Control which tracks this server shares with, or hides from, other servers
+#[poise::command(
+    slash_command,
+    subcommands("share", "unshare", "hide", "unhide", "global_pool"),
+    subcommand_required
+)]
+pub async fn sharing(
+    _ctx: PoiseContext<'_>,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Put a track in the shared pool so every server can see it
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn share(
+    ctx: PoiseContext<'_>,
+    #[description = "The track to share"]
+    #[autocomplete = "autocomplete_track"]
+    track: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
+    require_track_owner(db_pool, guild_id, &info).await?;
+
+    set_track_shared(db_pool, guild_id, &info.id, true).await?;
+
+    ctx.say(format!("Track `{}` is now in the shared pool", info.title)).await?;
+    Ok(())
+}
+
+/// Take a track out of the shared pool
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn unshare(
+    ctx: PoiseContext<'_>,
+    #[description = "The track to stop sharing"]
+    #[autocomplete = "autocomplete_track"]
+    track: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
+    require_track_owner(db_pool, guild_id, &info).await?;
+
+    // Keep the track in this server's own library so it doesn't vanish here too
+    add_guild_track(db_pool, guild_id, &info.id).await?;
+    set_track_shared(db_pool, guild_id, &info.id, false).await?;
+
+    ctx.say(format!("Track `{}` is no longer shared", info.title)).await?;
+    Ok(())
+}
+
+/// Hide a track from this server's library
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn hide(
+    ctx: PoiseContext<'_>,
+    #[description = "The track to hide"]
+    #[autocomplete = "autocomplete_track"]
+    track: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let info = require_track(db_pool, guild_id, &VideoId::from(track)).await?;
+
+    set_track_hidden(db_pool, guild_id, &info.id, true).await?;
+
+    ctx.say(format!("Track `{}` is now hidden in this server", info.title)).await?;
+    Ok(())
+}
+
+/// Restore a hidden track to this server's library
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn unhide(
+    ctx: PoiseContext<'_>,
+    #[description = "YouTube link or ID of the hidden track"]
+    track: String,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let db_pool = &ctx.data().db_pool;
+    let track_id = normalise_track_input(&track);
+
+    // Unhiding only undoes a hide; it can't reach another server's private tracks
+    if !track_available(db_pool, guild_id, &track_id).await? {
+        return Err("Track could not be found in the database.".into());
+    }
+
+    set_track_hidden(db_pool, guild_id, &track_id, false).await?;
+    let info = require_track(db_pool, guild_id, &track_id).await?;
+
+    ctx.say(format!("Track `{}` is visible in this server again", info.title)).await?;
+    Ok(())
+}
+
+/// Errors unless `guild_id` added the track; only that server decides whether it's shared.
+async fn require_track_owner(
+    db_pool: &sqlx::SqlitePool,
+    guild_id: GuildId,
+    info: &TrackInfo,
+) -> Result<(), Error> {
+    match fetch_track_owner(db_pool, &info.id).await? {
+        Some(owner) if owner == guild_id => Ok(()),
+        Some(_) => Err(format!(
+            "Track `{}` was added by another server; only that server can change whether it's shared.",
+            info.title
+        ).into()),
+        None => Err(format!(
+            "Track `{}` is part of the base library, which every server shares.",
+            info.title
+        ).into()),
+    }
+}
+
+/// Choose whether this server sees tracks shared by other servers
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn global_pool(
+    ctx: PoiseContext<'_>,
+    #[description = "Show shared tracks from other servers"]
+    enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+
+    set_guild_share_global(&ctx.data().db_pool, guild_id, enabled).await?;
+
+    ctx.say(if enabled {
+        "This server now sees the shared track pool."
+    } else {
+        "This server now only sees its own tracks."
+    }).await?;
     Ok(())
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
 pub mod admin;
 pub mod browse;
+pub mod chronicle;
 pub mod controls;
-pub mod management;
\ No newline at end of file
+pub mod management;
+pub mod transfer;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use poise::CreateReply;
+use poise::serenity_prelude::{Attachment, CreateAttachment};
+
+use crate::definitions::{Error, PoiseContext};
+use crate::library_transfer::{export_library, import_library, parse_dump, LibraryFormat};
+use crate::utils::context::require_guild;
+
+/// Export this server's library as JSON, CSV or M3U playlists
+#[poise::command(slash_command)]
+pub async fn export(
+    ctx: PoiseContext<'_>,
+    #[description = "File format to export"]
+    format: LibraryFormat,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    ctx.defer().await?;
+
+    let files = export_library(&ctx.data().db_pool, Some(guild_id), format).await?;
+
+    let mut reply = CreateReply::default().content(format!(
+        "Exported the library as {} file(s).",
+        files.len()
+    ));
+    for file in files {
+        reply = reply.attachment(CreateAttachment::bytes(file.contents, file.name));
+    }
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// Merge a JSON or CSV library export into this server's library
+#[poise::command(slash_command, required_permissions = "MANAGE_GUILD")]
+pub async fn import(
+    ctx: PoiseContext<'_>,
+    #[description = "A library.json or library.csv export"]
+    file: Attachment,
+    #[description = "Only report what would change"]
+    dry_run: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = require_guild(ctx)?;
+    let dry_run = dry_run.unwrap_or(false);
+    let format = LibraryFormat::from_path(&file.filename)
+        .ok_or("Import files must end in .json or .csv")?;
+    ctx.defer().await?;
+
+    let dump = parse_dump(format, &file.download().await?)?;
+    let report = import_library(&ctx.data().db_pool, Some(guild_id), &dump, dry_run).await?;
+
+    ctx.say(report.summary(dry_run)).await?;
+    Ok(())
+}
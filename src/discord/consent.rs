@@ -0,0 +1,142 @@
+use std::num::NonZeroU64;
+
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, GuildId, UserId,
+    VoiceState,
+};
+use tracing::warn;
+
+use crate::chronicle::session::unix_now;
+use crate::db::chronicle::{fetch_consent, set_consent};
+use crate::definitions::{Data, Error};
+
+/// Custom ID prefix of the buttons on consent prompts. The prompts outlive any
+/// command, so presses are handled from the event handler rather than a collector.
+pub const CONSENT_BUTTON_PREFIX: &str = "chronicle-consent:";
+const CONSENT_YES: &str = "chronicle-consent:yes";
+const CONSENT_NO: &str = "chronicle-consent:no";
+
+/// Where a consent change came from, as written to the audit log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsentSource {
+    Prompt,
+    Command,
+}
+
+impl ConsentSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentSource::Prompt  => "prompt",
+            ConsentSource::Command => "command",
+        }
+    }
+}
+
+/// Saves and audits the decision, then applies it to the guild's recording at
+/// once. Returns the session it applied to, if one is running.
+pub async fn apply_consent(
+    data: &Data,
+    guild_id: GuildId,
+    user_id: u64,
+    consented: bool,
+    source: ConsentSource,
+) -> Result<Option<i64>, Error> {
+    let session_id = data.chronicle.status(guild_id).await.map(|s| s.id);
+    set_consent(&data.db_pool, guild_id, user_id, consented, source.as_str(), session_id, unix_now()).await?;
+    Ok(data.chronicle.set_consent(guild_id, user_id, consented).await)
+}
+
+/// DMs each of `users` asking whether they agree to be recorded, so nobody else
+/// sees who was asked or what they answered. Returns those who couldn't be
+/// reached, e.g. because they don't accept DMs from server members.
+pub async fn prompt_for_consent(ctx: &serenity::Context, guild_id: GuildId, users: &[u64]) -> Result<Vec<u64>, Error> {
+    let guild_name = ctx.cache
+        .guild(guild_id)
+        .map(|g| g.name.clone())
+        .unwrap_or_else(|| "this server".to_string());
+    // The presses arrive without a guild, so the buttons carry it
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}:{}", CONSENT_YES, guild_id)).label("Record me").style(ButtonStyle::Success),
+        CreateButton::new(format!("{}:{}", CONSENT_NO, guild_id)).label("Don't record me").style(ButtonStyle::Danger),
+    ]);
+
+    let mut unreached = Vec::new();
+    for user_id in users {
+        let message = CreateMessage::new()
+            .content(format!(
+                "🎙️ A session in **{}** is being recorded for the campaign chronicle. \
+                 Your voice is only recorded once you agree, and you can change your mind \
+                 at any time with `/chronicle consent`.",
+                guild_name,
+            ))
+            .components(vec![buttons.clone()]);
+        if let Err(e) = UserId::new(*user_id).direct_message(ctx, message).await {
+            warn!(user_id, error = %e, "Couldn't DM the consent prompt");
+            unreached.push(*user_id);
+        }
+    }
+    Ok(unreached)
+}
+
+/// Prompts someone joining the recorded channel who hasn't decided yet.
+pub async fn handle_voice_state(
+    ctx: &serenity::Context,
+    old: Option<&VoiceState>,
+    new: &VoiceState,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(guild_id) = new.guild_id else { return Ok(()) };
+    let Some(status) = data.chronicle.status(guild_id).await else { return Ok(()) };
+
+    let joined = new.channel_id == Some(status.voice_channel_id)
+        && old.and_then(|o| o.channel_id) != Some(status.voice_channel_id);
+    if !joined || new.user_id == ctx.cache.current_user().id {
+        return Ok(());
+    }
+    if new.member.as_ref().is_some_and(|m| m.user.bot) {
+        return Ok(());
+    }
+
+    if fetch_consent(&data.db_pool, guild_id, new.user_id.get()).await?.is_none() {
+        prompt_for_consent(ctx, guild_id, &[new.user_id.get()]).await?;
+    }
+    Ok(())
+}
+
+/// Records the press of a consent prompt button for whoever pressed it.
+pub async fn handle_consent_button(
+    ctx: &serenity::Context,
+    press: &ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    // Prompts are DMed, so the guild they're for comes from the custom ID
+    let Some((answer, guild_id)) = press.data.custom_id
+        .rsplit_once(':')
+        .and_then(|(answer, guild)| Some((answer, GuildId::from(guild.parse::<NonZeroU64>().ok()?))))
+    else {
+        return Ok(());
+    };
+    let consented = match answer {
+        CONSENT_YES => true,
+        CONSENT_NO => false,
+        _ => return Ok(()),
+    };
+
+    let session = apply_consent(data, guild_id, press.user.id.get(), consented, ConsentSource::Prompt).await?;
+    let reply = match (consented, session) {
+        (true, Some(_)) => "Thanks. You're being recorded from now on.",
+        (true, None) => "Thanks. You'll be recorded in future chronicle sessions.",
+        (false, Some(_)) => "Understood. Your voice isn't being recorded.",
+        (false, None) => "Understood. Your voice won't be recorded.",
+    };
+    press
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new().content(reply).ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
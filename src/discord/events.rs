@@ -0,0 +1,23 @@
+use poise::serenity_prelude::{self as serenity, FullEvent, Interaction};
+
+use crate::definitions::{Data, Error};
+use crate::discord::consent::{handle_consent_button, handle_voice_state, CONSENT_BUTTON_PREFIX};
+use crate::discord::playback::{handle_play_button, PLAY_BUTTON_PREFIX};
+
+/// Gateway events the bot reacts to outside of commands.
+pub async fn handle_event(ctx: &serenity::Context, event: &FullEvent, data: &Data) -> Result<(), Error> {
+    match event {
+        FullEvent::VoiceStateUpdate { old, new } => handle_voice_state(ctx, old.as_ref(), new, data).await,
+        FullEvent::InteractionCreate { interaction: Interaction::Component(press) }
+            if press.data.custom_id.starts_with(CONSENT_BUTTON_PREFIX) =>
+        {
+            handle_consent_button(ctx, press, data).await
+        }
+        FullEvent::InteractionCreate { interaction: Interaction::Component(press) }
+            if press.data.custom_id.starts_with(PLAY_BUTTON_PREFIX) =>
+        {
+            handle_play_button(ctx, press, data).await
+        }
+        _ => Ok(()),
+    }
+}
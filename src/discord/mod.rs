@@ -1,2 +1,5 @@
 pub mod autocomplete;
-pub mod commands;
\ No newline at end of file
+pub mod commands;
+pub mod consent;
+pub mod events;
+pub mod playback;
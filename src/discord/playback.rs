@@ -0,0 +1,190 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude::{
+    self as serenity, async_trait, ButtonStyle, ChannelId, ComponentInteraction, CreateButton,
+    EditInteractionResponse, GuildId, Mentionable,
+};
+use songbird::input::File as SongbirdFile;
+use songbird::tracks::TrackHandle;
+use songbird::{Call, Event, EventContext, EventHandler, Songbird, TrackEvent};
+use tokio::sync::Mutex;
+
+use crate::chronicle::mixdown::archive_path;
+use crate::chronicle::obsidian::session_title;
+use crate::chronicle::session::ChronicleService;
+use crate::chronicle::transcript::clock;
+use crate::db::chronicle::{fetch_campaign, fetch_session};
+use crate::definitions::{Data, Error};
+use crate::jester::service::PlayerService;
+
+/// Custom ID prefix of the search results' play buttons, followed by
+/// `<session id>:<start ms>`. Like the consent prompts, they outlive the command.
+pub const PLAY_BUTTON_PREFIX: &str = "chronicle-play:";
+/// How much earlier than the matched line playback starts, for context
+const PLAY_LEAD: Duration = Duration::from_secs(3);
+const PLAY_CLIP: Duration = Duration::from_secs(30);
+
+/// Plays a local audio file in the voice channel from `start`, stopping after
+/// `limit` if given. It mixes in alongside a recording on the same connection,
+/// since the bot never hears itself. If the bot had to join for it, it leaves
+/// again once the file ends, unless music or a recording took the connection over.
+pub async fn play_file(
+    serenity_ctx: &serenity::Context,
+    data: &Data,
+    guild_id: GuildId,
+    vc_id: ChannelId,
+    path: &Path,
+    start: Duration,
+    limit: Option<Duration>,
+) -> Result<TrackHandle, Error> {
+    if data.player.get_now_playing(guild_id).await.is_some() {
+        return Err("Stop the music first; it shares the voice connection.".into());
+    }
+    if let Some(status) = data.chronicle.status(guild_id).await
+        && status.voice_channel_id != vc_id
+    {
+        return Err("Chronicle is recording in another voice channel.".into());
+    }
+
+    let manager = songbird::get(serenity_ctx)
+        .await
+        .expect("Songbird was not initialized")
+        .clone();
+    let connected = match manager.get(guild_id) {
+        Some(call) if call.lock().await.current_channel() == Some(vc_id.into()) => Some(call),
+        _ => None,
+    };
+    let joined = connected.is_none();
+    let call = match connected {
+        Some(call) => call,
+        None => manager.join(guild_id, vc_id).await?,
+    };
+
+    let handle = call.lock().await.play_input(SongbirdFile::new(PathBuf::from(path)).into());
+    data.player.file_started(guild_id, joined).await;
+    let ended = FileEnded {
+        manager: manager.clone(),
+        call: call.clone(),
+        guild_id,
+        player: data.player.clone(),
+        chronicle: data.chronicle.clone(),
+    };
+    if handle.add_event(Event::Track(TrackEvent::End), ended).is_err() {
+        data.player.file_finished(guild_id).await;
+    }
+    if !start.is_zero() {
+        let _ = handle.seek(start);
+    }
+    if let Some(limit) = limit {
+        let clip = handle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(limit).await;
+            let _ = clip.stop();
+        });
+    }
+    Ok(handle)
+}
+
+/// Leaves voice after the last file `play_file` joined for.
+struct FileEnded {
+    manager: Arc<Songbird>,
+    call: Arc<Mutex<Call>>,
+    guild_id: GuildId,
+    player: Arc<PlayerService>,
+    chronicle: Arc<ChronicleService>,
+}
+
+#[async_trait]
+impl EventHandler for FileEnded {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        if !self.player.file_finished(self.guild_id).await {
+            return None;
+        }
+        // Music replaces the file while holding the call, so look only once it's free
+        let call = self.call.lock().await;
+        let in_use = self.player.get_now_playing(self.guild_id).await.is_some()
+            || self.chronicle.is_recording(self.guild_id).await;
+        drop(call);
+        if !in_use && let Err(e) = self.manager.remove(self.guild_id).await {
+            tracing::debug!("Couldn't leave voice after playback: {}", e);
+        }
+        None
+    }
+}
+
+/// A button that plays the session's archived recording around `start_ms`.
+pub fn play_button(session_id: i64, start_ms: i64, label: String) -> CreateButton {
+    CreateButton::new(format!("{}{}:{}", PLAY_BUTTON_PREFIX, session_id, start_ms))
+        .label(label)
+        .style(ButtonStyle::Secondary)
+}
+
+/// Plays a short clip of the archived recording in the presser's voice channel.
+/// Only the presser sees the reply.
+pub async fn handle_play_button(
+    ctx: &serenity::Context,
+    press: &ComponentInteraction,
+    data: &Data,
+) -> Result<(), Error> {
+    let Some(guild_id) = press.guild_id else { return Ok(()) };
+    let Some((session_id, start_ms)) = press.data.custom_id
+        .strip_prefix(PLAY_BUTTON_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .and_then(|(session, start)| Some((session.parse::<i64>().ok()?, start.parse::<u64>().ok()?)))
+    else {
+        return Ok(());
+    };
+
+    // Joining voice can take longer than Discord waits for an answer
+    press.defer_ephemeral(ctx).await?;
+    let reply = match play_clip(ctx, press, data, guild_id, session_id, start_ms).await {
+        Ok(reply) => reply,
+        Err(e) => e.to_string(),
+    };
+    press.edit_response(ctx, EditInteractionResponse::new().content(reply)).await?;
+    Ok(())
+}
+
+async fn play_clip(
+    ctx: &serenity::Context,
+    press: &ComponentInteraction,
+    data: &Data,
+    guild_id: GuildId,
+    session_id: i64,
+    start_ms: u64,
+) -> Result<String, Error> {
+    let session = fetch_session(&data.db_pool, session_id)
+        .await?
+        .filter(|s| s.guild_id == guild_id.get() as i64)
+        .ok_or("That session no longer exists.")?;
+    let Some(vault) = data.chronicle_config.obsidian.vault.as_deref() else {
+        return Err("No Obsidian vault is configured, so there's no archived recording to play.".into());
+    };
+    let campaign = match session.campaign_id {
+        Some(campaign_id) => fetch_campaign(&data.db_pool, campaign_id).await?,
+        None => None,
+    };
+    let Some(campaign) = campaign else {
+        return Err("That session isn't part of a campaign, so it has no archived recording.".into());
+    };
+    let path = archive_path(vault, &campaign.obsidian_folder, &session_title(&session), data.chronicle_config.archive_format);
+    if !path.is_file() {
+        return Err("That session's recording hasn't been archived, or has been deleted.".into());
+    }
+
+    let vc_id = ctx.cache.guild(guild_id)
+        .and_then(|guild| guild.voice_states.get(&press.user.id).and_then(|v| v.channel_id))
+        .ok_or("Join a voice channel first.")?;
+
+    let start = Duration::from_millis(start_ms).saturating_sub(PLAY_LEAD);
+    play_file(ctx, data, guild_id, vc_id, &path, start, Some(PLAY_CLIP)).await?;
+    Ok(format!(
+        "▶️ Playing {} from `{}` in {} for {} seconds.",
+        session_title(&session),
+        clock(start.as_millis() as u64),
+        vc_id.mention(),
+        PLAY_CLIP.as_secs(),
+    ))
+}
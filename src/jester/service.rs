@@ -10,13 +10,42 @@ use crate::definitions::{Error, NowPlaying, TrackInfo};
 
 pub struct PlayerService {
     now_playing: RwLock<HashMap<GuildId, NowPlaying>>,
+    files: RwLock<HashMap<GuildId, FilePlayback>>,
+}
+
+/// Local files playing in a guild outside the music player, such as recaps and clips.
+struct FilePlayback {
+    playing: usize,
+    /// Whether the bot joined voice to play the first of them
+    joined: bool,
 }
 
 impl PlayerService {
     pub fn new() -> Self {
         Self {
             now_playing: RwLock::new(HashMap::new()),
+            files: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub async fn file_started(&self, guild_id: GuildId, joined: bool) {
+        self.files
+            .write()
+            .await
+            .entry(guild_id)
+            .or_insert(FilePlayback { playing: 0, joined })
+            .playing += 1;
+    }
+
+    /// Returns whether that was the last file playing and the bot joined voice for them.
+    pub async fn file_finished(&self, guild_id: GuildId) -> bool {
+        let mut files = self.files.write().await;
+        let Some(playback) = files.get_mut(&guild_id) else { return false };
+        playback.playing = playback.playing.saturating_sub(1);
+        if playback.playing > 0 {
+            return false;
         }
+        files.remove(&guild_id).is_some_and(|p| p.joined)
     }
 
     pub async fn play(
@@ -47,7 +76,7 @@ impl PlayerService {
         if let Some(handler_lock) = manager.get(guild_id) {
             let mut handler = handler_lock.lock().await;
             let track_handle = handler.play_only_input(song_src.into());
-            let _ = track_handle.enable_loop()?;
+            track_handle.enable_loop()?;
 
             let mut state = self.now_playing.write().await;
             state.insert(guild_id, NowPlaying {
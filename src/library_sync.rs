@@ -5,6 +5,8 @@ use tokio::process::Command;
 use tracing::{debug, info, instrument, warn};
 use futures::stream::{self, StreamExt as FuturesStreamExt};
 
+use crate::utils::metadata_archive::{archive_info_json, has_info_json};
+
 const AUDIO_DIR: &str = "audio";
 const DOWNLOAD_CONCURRENCY: usize = 4;
 const MAX_RETRIES: usize = 3;
@@ -170,8 +172,13 @@ async fn download_track(id: &str) -> Result<bool> {
         .arg("0")
         .arg("--no-playlist")
         .arg("--no-progress")
+        .arg("--write-info-json")
         .arg("-o")
         .arg(&tmp_path)
+        // The audio template has no `%(ext)s`, so yt-dlp would name the info JSON after
+        // the whole of it; pin the name `archive_downloaded_info` reads instead
+        .arg("-o")
+        .arg(format!("infojson:{}", info_json_stem(id)))
         .arg(format!("https://www.youtube.com/watch?v={id}"))
         .output()
         .await
@@ -186,6 +193,8 @@ async fn download_track(id: &str) -> Result<bool> {
         return Ok(false);
     }
 
+    archive_downloaded_info(id).await;
+
     if tokio::fs::try_exists(&tmp_path).await.unwrap_or(false) {
         tokio::fs::rename(&tmp_path, &final_path)
             .await
@@ -195,4 +204,32 @@ async fn download_track(id: &str) -> Result<bool> {
         warn!(%id, "Downloaded file not found after completion");
         Ok(false)
     }
+}
+
+/// Where `download_track` has yt-dlp write the info JSON, before the `.info.json`
+/// yt-dlp adds.
+fn info_json_stem(id: &str) -> String {
+    format!("{AUDIO_DIR}/{id}.part")
+}
+
+/// Moves the info JSON yt-dlp wrote alongside a re-download into the metadata archive.
+/// Tracks that already have an archive keep the original.
+async fn archive_downloaded_info(id: &str) {
+    let info_path = format!("{}.info.json", info_json_stem(id));
+
+    let Ok(content) = tokio::fs::read_to_string(&info_path).await else {
+        warn!(%id, path = %info_path, "No info JSON written by yt-dlp");
+        return;
+    };
+
+    if !has_info_json(id)
+        && let Err(e) = archive_info_json(id, &content)
+    {
+        warn!(%id, error = %e, "Failed to archive info JSON");
+    }
+    if !has_info_json(id) {
+        warn!(%id, "Info JSON is still missing from the metadata archive");
+    }
+
+    tokio::fs::remove_file(&info_path).await.ok();
 }
\ No newline at end of file
@@ -0,0 +1,655 @@
+use std::collections::{BTreeSet, HashMap};
+use std::num::NonZeroU64;
+use std::path::Path;
+
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::definitions::{Error, MetadataKind, TrackInfo, VideoId};
+use crate::db::repository::{
+    add_guild_track, fetch_library_metadata, fetch_library_records, fetch_library_tag_pairs,
+    get_or_insert_metadata_id, insert_base_track_tag, insert_new_track, insert_track_tag,
+    lookup_base_track, lookup_track, metadata_exists, track_exists, update_base_track,
+    update_track_artist, update_track_origin, update_track_title,
+};
+
+const DUMP_VERSION: u32 = 1;
+const AUDIO_DIR: &str = "audio";
+const CSV_TAG_SEPARATOR: char = ';';
+/// Escapes a separator or itself inside a CSV tag.
+const CSV_TAG_ESCAPE: char = '\\';
+const SUMMARY_CHANGES_LISTED: usize = 20;
+/// Discord rejects messages over 2000 characters.
+const SUMMARY_MAX_CHARS: usize = 1_900;
+/// Room kept for the "…and n more" line.
+const SUMMARY_MORE_CHARS: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum LibraryFormat {
+    #[name = "JSON"]
+    Json,
+    #[name = "CSV"]
+    Csv,
+    /// Plain list of audio paths
+    #[name = "M3U"]
+    M3u,
+    /// Extended playlist with titles, in UTF-8
+    #[name = "M3U8"]
+    M3u8,
+}
+
+impl LibraryFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LibraryFormat::Json => "json",
+            LibraryFormat::Csv  => "csv",
+            LibraryFormat::M3u  => "m3u",
+            LibraryFormat::M3u8 => "m3u8",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_lowercase().as_str() {
+            "json" => Some(LibraryFormat::Json),
+            "csv"  => Some(LibraryFormat::Csv),
+            "m3u"  => Some(LibraryFormat::M3u),
+            "m3u8" => Some(LibraryFormat::M3u8),
+            _      => None,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+}
+
+/// A complete, self-describing copy of a library. Artists, origins and tags are
+/// listed on their own as well, so names no track uses survive a round trip.
+/// Chester has no playlists, so there are none to include.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LibraryDump {
+    pub version: u32,
+    pub tracks: Vec<TrackRecord>,
+    #[serde(default)]
+    pub artists: Vec<String>,
+    #[serde(default)]
+    pub origins: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackRecord {
+    pub id: String,
+    pub upload_date: String,
+    pub yt_title: String,
+    pub track_title: String,
+    pub artist: String,
+    pub origin: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Flat CSV row; tags are joined into a single column, each `;` or `\\` inside a
+/// tag escaped with `\\`. CSV carries tracks only, without the standalone lists.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvTrackRow {
+    id: String,
+    upload_date: String,
+    yt_title: String,
+    track_title: String,
+    artist: String,
+    origin: String,
+    tags: String,
+}
+
+pub struct ExportFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// What an import changed, or would change on a dry run.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: usize,
+    pub tags_added: usize,
+    /// Artists, origins and tags that weren't stored yet
+    pub metadata_added: usize,
+}
+
+impl ImportReport {
+    pub fn summary(&self, dry_run: bool) -> String {
+        let verb = if dry_run { "Would import" } else { "Imported" };
+        let mut out = format!(
+            "{}: {} new tracks, {} updated, {} unchanged, {} tags, {} new artists, origins and tags.",
+            verb,
+            self.added.len(),
+            self.updated.len(),
+            self.unchanged,
+            self.tags_added,
+            self.metadata_added,
+        );
+        let mut listed = 0;
+        for change in self.updated.iter().take(SUMMARY_CHANGES_LISTED) {
+            let line = format!("\n- {}", change);
+            if out.chars().count() + line.chars().count() > SUMMARY_MAX_CHARS - SUMMARY_MORE_CHARS {
+                break;
+            }
+            out.push_str(&line);
+            listed += 1;
+        }
+        if self.updated.len() > listed {
+            out.push_str(&format!("\n…and {} more", self.updated.len() - listed));
+        }
+        out
+    }
+}
+
+// ─── export ──────────────────────────────────────────────────────────────────
+
+/// Collects the library for `guild_id`, or the base library when `None`.
+pub async fn build_dump(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+) -> Result<LibraryDump, Error> {
+    let mut tags_by_track: HashMap<String, Vec<String>> = HashMap::new();
+    for (track_id, tag) in fetch_library_tag_pairs(db_pool, guild_id).await? {
+        tags_by_track.entry(track_id).or_default().push(tag);
+    }
+
+    let tracks: Vec<TrackRecord> = fetch_library_records(db_pool, guild_id)
+        .await?
+        .into_iter()
+        .map(|(id, upload_date, yt_title, track_title, artist, origin)| TrackRecord {
+            tags: tags_by_track.remove(&id).unwrap_or_default(),
+            id,
+            upload_date,
+            yt_title,
+            track_title,
+            artist,
+            origin,
+        })
+        .collect();
+
+    Ok(LibraryDump {
+        version: DUMP_VERSION,
+        tracks,
+        artists: fetch_library_metadata(db_pool, guild_id, MetadataKind::Artist).await?,
+        origins: fetch_library_metadata(db_pool, guild_id, MetadataKind::Origin).await?,
+        tags: fetch_library_metadata(db_pool, guild_id, MetadataKind::Tag).await?,
+    })
+}
+
+/// Renders the library in the requested format. M3U formats produce one playlist
+/// of the whole library: M3U as bare paths, M3U8 with a title for each.
+pub async fn export_library(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+    format: LibraryFormat,
+) -> Result<Vec<ExportFile>, Error> {
+    let dump = build_dump(db_pool, guild_id).await?;
+    let ext = format.extension();
+
+    let files = match format {
+        LibraryFormat::Json => vec![ExportFile {
+            name: format!("library.{ext}"),
+            contents: serde_json::to_vec_pretty(&dump)?,
+        }],
+        LibraryFormat::Csv => vec![ExportFile {
+            name: format!("library.{ext}"),
+            contents: render_csv(&dump.tracks)?,
+        }],
+        LibraryFormat::M3u | LibraryFormat::M3u8 => {
+            let audio_dir = std::env::current_dir()?.join(AUDIO_DIR);
+            let extended = format == LibraryFormat::M3u8;
+            vec![ExportFile {
+                name: format!("library.{ext}"),
+                contents: render_m3u(&dump.tracks, &audio_dir, extended).into_bytes(),
+            }]
+        }
+    };
+
+    Ok(files)
+}
+
+fn render_csv(tracks: &[TrackRecord]) -> Result<Vec<u8>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for track in tracks {
+        writer.serialize(CsvTrackRow {
+            id: track.id.clone(),
+            upload_date: track.upload_date.clone(),
+            yt_title: track.yt_title.clone(),
+            track_title: track.track_title.clone(),
+            artist: track.artist.clone(),
+            origin: track.origin.clone(),
+            tags: join_tags(&track.tags),
+        })?;
+    }
+    writer.into_inner().map_err(|e| format!("Failed to write CSV: {}", e).into())
+}
+
+fn join_tags(tags: &[String]) -> String {
+    let escaped: Vec<String> = tags
+        .iter()
+        .map(|tag| {
+            let mut out = String::with_capacity(tag.len());
+            for c in tag.chars() {
+                if c == CSV_TAG_SEPARATOR || c == CSV_TAG_ESCAPE {
+                    out.push(CSV_TAG_ESCAPE);
+                }
+                out.push(c);
+            }
+            out
+        })
+        .collect();
+    escaped.join(&format!("{CSV_TAG_SEPARATOR} "))
+}
+
+fn split_tags(column: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut current = String::new();
+    let mut chars = column.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            CSV_TAG_ESCAPE => current.extend(chars.next()),
+            CSV_TAG_SEPARATOR => tags.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    tags.push(current);
+
+    tags.into_iter()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// Extended M3U carries a display title per track; players expect `.m3u8` files
+/// to be UTF-8, which Rust strings always are.
+fn render_m3u(tracks: &[TrackRecord], audio_dir: &Path, extended: bool) -> String {
+    let mut out = String::new();
+    if extended {
+        out.push_str("#EXTM3U\n");
+    }
+    for track in tracks {
+        if extended {
+            out.push_str(&format!("#EXTINF:-1,{} - {}\n", track.artist, track.track_title));
+        }
+        out.push_str(&format!("{}\n", audio_dir.join(format!("{}.mp3", track.id)).display()));
+    }
+    out
+}
+
+// ─── import ──────────────────────────────────────────────────────────────────
+
+pub fn parse_dump(format: LibraryFormat, bytes: &[u8]) -> Result<LibraryDump, Error> {
+    match format {
+        LibraryFormat::Json => {
+            let dump: LibraryDump = serde_json::from_slice(bytes)
+                .map_err(|e| format!("Failed to parse library JSON: {}", e))?;
+            if dump.version > DUMP_VERSION {
+                return Err(format!("Library dump version {} is newer than supported", dump.version).into());
+            }
+            Ok(dump)
+        }
+        LibraryFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(bytes);
+            let mut tracks = Vec::new();
+            for row in reader.deserialize::<CsvTrackRow>() {
+                let row = row.map_err(|e| format!("Failed to parse library CSV: {}", e))?;
+                tracks.push(TrackRecord {
+                    id: row.id,
+                    upload_date: row.upload_date,
+                    yt_title: row.yt_title,
+                    track_title: row.track_title,
+                    artist: row.artist,
+                    origin: row.origin,
+                    tags: split_tags(&row.tags),
+                });
+            }
+            Ok(LibraryDump { version: DUMP_VERSION, tracks, ..Default::default() })
+        }
+        LibraryFormat::M3u | LibraryFormat::M3u8 => {
+            Err("M3U playlists only reference audio files and can't be imported.".into())
+        }
+    }
+}
+
+/// Merges a dump into the library of `guild_id`, or the base library when `None`.
+///
+/// Tracks are added when missing and their metadata is brought in line when it differs;
+/// tags are only ever added. Standalone artists, origins and tags are created when
+/// missing. With `dry_run` nothing is written.
+pub async fn import_library(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+    dump: &LibraryDump,
+    dry_run: bool,
+) -> Result<ImportReport, Error> {
+    let mut report = ImportReport::default();
+
+    let standalone = [
+        (MetadataKind::Artist, &dump.artists),
+        (MetadataKind::Origin, &dump.origins),
+        (MetadataKind::Tag, &dump.tags),
+    ];
+    for (kind, names) in standalone {
+        for name in names {
+            if metadata_exists(db_pool, kind, name).await? {
+                continue;
+            }
+            report.metadata_added += 1;
+            if !dry_run {
+                get_or_insert_metadata_id(db_pool, kind, name).await?;
+            }
+        }
+    }
+
+    let mut existing_tags: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for (track_id, tag) in fetch_library_tag_pairs(db_pool, guild_id).await? {
+        existing_tags.entry(track_id).or_default().insert(tag);
+    }
+
+    for record in &dump.tracks {
+        let video_id = VideoId::from(record.id.as_str());
+        let current = match guild_id {
+            Some(guild_id) => lookup_track(db_pool, guild_id, &video_id).await?,
+            None => lookup_base_track(db_pool, &video_id).await?,
+        };
+
+        match current {
+            None => {
+                report.added.push(record.id.clone());
+                if !dry_run {
+                    insert_record(db_pool, guild_id, record).await?;
+                }
+            }
+            Some(current) => {
+                let changes = describe_changes(&current, record);
+                if changes.is_empty() {
+                    report.unchanged += 1;
+                } else {
+                    report.updated.push(format!("`{}`: {}", current.title, changes.join(", ")));
+                    if !dry_run {
+                        update_record(db_pool, guild_id, &current, record).await?;
+                    }
+                }
+            }
+        }
+
+        let known = existing_tags.get(&record.id);
+        for tag in record.tags.iter().filter(|t| known.is_none_or(|k| !k.contains(*t))) {
+            report.tags_added += 1;
+            if !dry_run {
+                let tag_id = get_or_insert_metadata_id(db_pool, MetadataKind::Tag, tag).await?;
+                match guild_id {
+                    Some(guild_id) => insert_track_tag(db_pool, guild_id, &video_id, tag_id).await?,
+                    None => insert_base_track_tag(db_pool, &video_id, tag_id).await?,
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+fn describe_changes(current: &TrackInfo, record: &TrackRecord) -> Vec<String> {
+    let mut changes = Vec::new();
+    if current.title != record.track_title {
+        changes.push(format!("title → `{}`", record.track_title));
+    }
+    if current.artist != record.artist {
+        changes.push(format!("artist → `{}`", record.artist));
+    }
+    if current.origin != record.origin {
+        changes.push(format!("origin → `{}`", record.origin));
+    }
+    changes
+}
+
+async fn insert_record(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+    record: &TrackRecord,
+) -> Result<(), Error> {
+    let video_id = VideoId::from(record.id.as_str());
+
+    // The track may exist in the base library but be invisible to this guild
+    if !track_exists(db_pool, &video_id).await? {
+        let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, &record.artist).await?;
+        let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, &record.origin).await?;
+        let slim = serde_json::json!({
+            "upload_date": record.upload_date,
+            "title": record.yt_title,
+        });
+        insert_new_track(
+            db_pool,
+            &video_id,
+            &slim,
+            &record.track_title,
+            artist_id,
+            origin_id,
+            guild_id,
+        )
+        .await?;
+    }
+
+    if let Some(guild_id) = guild_id {
+        add_guild_track(db_pool, guild_id, &video_id).await?;
+        if let Some(current) = lookup_track(db_pool, guild_id, &video_id).await? {
+            update_record(db_pool, Some(guild_id), &current, record).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn update_record(
+    db_pool: &SqlitePool,
+    guild_id: Option<GuildId>,
+    current: &TrackInfo,
+    record: &TrackRecord,
+) -> Result<(), Error> {
+    let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, &record.artist).await?;
+    let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, &record.origin).await?;
+
+    let Some(guild_id) = guild_id else {
+        return update_base_track(db_pool, &current.id, &record.track_title, artist_id, origin_id).await;
+    };
+
+    if current.title != record.track_title {
+        update_track_title(db_pool, guild_id, &current.id, &record.track_title).await?;
+    }
+    if current.artist != record.artist {
+        update_track_artist(db_pool, guild_id, &current.id, artist_id).await?;
+    }
+    if current.origin != record.origin {
+        update_track_origin(db_pool, guild_id, &current.id, origin_id).await?;
+    }
+    Ok(())
+}
+
+// ─── command line ────────────────────────────────────────────────────────────
+
+/// Handles `export <json|csv|m3u|m3u8> <dir> [guild_id]` and
+/// `import <file> [--dry-run] [guild_id]`. Returns `false` when the arguments
+/// aren't a transfer command, so the bot should start as usual.
+pub async fn run_cli(db_pool: &SqlitePool, args: &[String]) -> Result<bool, Error> {
+    let Some(command) = args.first() else {
+        return Ok(false);
+    };
+    // The guild is always the last argument, after any paths
+    let guild_after = |position: usize| -> Result<Option<GuildId>, Error> {
+        let Some(id) = args.iter().skip(position).find(|a| a.parse::<u64>().is_ok()) else {
+            return Ok(None);
+        };
+        let id = id.parse::<NonZeroU64>().map_err(|_| "The guild ID can't be 0")?;
+        Ok(Some(GuildId::from(id)))
+    };
+
+    match command.as_str() {
+        "export" => {
+            let format = args.get(1)
+                .and_then(|f| LibraryFormat::from_extension(f))
+                .ok_or("Usage: export <json|csv|m3u|m3u8> <dir> [guild_id]")?;
+            let out_dir = Path::new(args.get(2).map(String::as_str).unwrap_or("export"));
+            let guild_id = guild_after(3)?;
+            std::fs::create_dir_all(out_dir)?;
+
+            for file in export_library(db_pool, guild_id, format).await? {
+                let path = out_dir.join(&file.name);
+                std::fs::write(&path, &file.contents)?;
+                tracing::info!("Wrote {}", path.display());
+            }
+            Ok(true)
+        }
+        "import" => {
+            let path = args.get(1).ok_or("Usage: import <file> [--dry-run] [guild_id]")?;
+            let format = LibraryFormat::from_path(path)
+                .ok_or("Import files must end in .json or .csv")?;
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let guild_id = guild_after(2)?;
+
+            let dump = parse_dump(format, &std::fs::read(path)?)?;
+            let report = import_library(db_pool, guild_id, &dump, dry_run).await?;
+            tracing::info!("{}", report.summary(dry_run));
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::db::schema::ensure_schema;
+
+    /// A fresh database with the full schema. One connection, since every
+    /// connection to `:memory:` opens a database of its own.
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::raw_sql(include_str!("../database/jester/schema.sql"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        ensure_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn track(id: &str, title: &str, artist: &str, origin: &str, tags: &[&str]) -> TrackRecord {
+        TrackRecord {
+            id: id.to_string(),
+            upload_date: "20240101".to_string(),
+            yt_title: format!("{title} (official)"),
+            track_title: title.to_string(),
+            artist: artist.to_string(),
+            origin: origin.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    fn sample_dump() -> LibraryDump {
+        LibraryDump {
+            version: DUMP_VERSION,
+            tracks: vec![
+                track("aaaaaaaaaaa", "Battle Theme", "Uematsu", "Final Fantasy", &["boss", "combat"]),
+                track("bbbbbbbbbbb", "Tavern", "Kondo", "Zelda", &["calm"]),
+            ],
+            artists: vec!["Kondo".to_string(), "Mitsuda".to_string(), "Uematsu".to_string()],
+            origins: vec!["Final Fantasy".to_string(), "Zelda".to_string()],
+            tags: vec!["boss".to_string(), "calm".to_string(), "combat".to_string()],
+        }
+    }
+
+    #[test]
+    fn json_round_trips_tracks_and_standalone_lists() {
+        let dump = sample_dump();
+        let parsed = parse_dump(LibraryFormat::Json, &serde_json::to_vec(&dump).unwrap()).unwrap();
+        assert_eq!(parsed, dump);
+    }
+
+    #[test]
+    fn csv_round_trips_tags_containing_the_separator() {
+        let tracks = vec![track("aaaaaaaaaaa", "Song", "Artist", "Game", &["a;b", r"back\slash", "plain", r"end\"])];
+        let csv = render_csv(&tracks).unwrap();
+        let parsed = parse_dump(LibraryFormat::Csv, &csv).unwrap();
+        assert_eq!(parsed.tracks, tracks);
+    }
+
+    #[test]
+    fn csv_reads_hand_written_tag_lists() {
+        assert_eq!(split_tags("boss;  combat ;;"), vec!["boss", "combat"]);
+        assert!(split_tags("").is_empty());
+    }
+
+    #[test]
+    fn only_m3u8_is_extended() {
+        let tracks = sample_dump().tracks;
+        let plain = render_m3u(&tracks, Path::new("/srv/audio"), false);
+        let extended = render_m3u(&tracks, Path::new("/srv/audio"), true);
+
+        assert_eq!(plain, "/srv/audio/aaaaaaaaaaa.mp3\n/srv/audio/bbbbbbbbbbb.mp3\n");
+        assert!(extended.starts_with("#EXTM3U\n#EXTINF:-1,Uematsu - Battle Theme\n/srv/audio/aaaaaaaaaaa.mp3\n"));
+    }
+
+    #[tokio::test]
+    async fn import_then_export_round_trips_the_base_library() {
+        let pool = test_pool().await;
+        let dump = sample_dump();
+
+        let report = import_library(&pool, None, &dump, false).await.unwrap();
+        assert_eq!(report.added.len(), 2);
+        assert_eq!(report.tags_added, 3);
+        assert_eq!(report.metadata_added, 8);
+
+        let mut exported = build_dump(&pool, None).await.unwrap();
+        exported.tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(exported.tracks, dump.tracks);
+        // The schema seeds placeholder names, which are exported too
+        for (imported, stored) in [(&dump.artists, &exported.artists), (&dump.origins, &exported.origins), (&dump.tags, &exported.tags)] {
+            assert!(imported.iter().all(|name| stored.contains(name)), "{imported:?} missing from {stored:?}");
+        }
+
+        let again = import_library(&pool, None, &exported, false).await.unwrap();
+        assert!(again.added.is_empty() && again.updated.is_empty());
+        assert_eq!((again.unchanged, again.tags_added, again.metadata_added), (2, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn import_into_a_guild_round_trips_its_library() {
+        let pool = test_pool().await;
+        let guild_id = GuildId::new(42);
+        let mut dump = sample_dump();
+        // Unused names only leave a guild through its tracks
+        dump.artists.retain(|a| a != "Mitsuda");
+
+        import_library(&pool, Some(guild_id), &dump, false).await.unwrap();
+
+        let mut exported = build_dump(&pool, Some(guild_id)).await.unwrap();
+        exported.tracks.sort_by(|a, b| a.id.cmp(&b.id));
+        assert_eq!(exported, dump);
+    }
+
+    #[tokio::test]
+    async fn dry_run_writes_nothing() {
+        let pool = test_pool().await;
+        let before = build_dump(&pool, None).await.unwrap();
+
+        let report = import_library(&pool, None, &sample_dump(), true).await.unwrap();
+        assert_eq!(report.added.len(), 2);
+        assert_eq!(report.metadata_added, 8);
+
+        assert_eq!(build_dump(&pool, None).await.unwrap(), before);
+    }
+}
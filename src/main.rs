@@ -1,3 +1,5 @@
+mod ai;
+mod chronicle;
 mod constants;
 mod definitions;
 mod db;
@@ -5,6 +7,7 @@ mod discord;
 mod jester;
 mod utils;
 mod library_sync;
+mod library_transfer;
 
 ////////////////////////////////////////////////////////////////////////////////
 /// Imports
@@ -54,9 +57,16 @@ async fn main() -> Result<(), Error> {
     // Initialize the SQLite connection pool
     let database_url = "sqlite://database/jester/jester.sqlite3";
     let pool = SqlitePool::connect(database_url).await?;
+    db::schema::ensure_schema(&pool).await?;
 
     std::env::set_current_dir(env!("CARGO_MANIFEST_DIR")).expect("Encountered an error setting the CWD to top-level");
 
+    // `cargo run -- export ...` / `cargo run -- import ...` run once and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if library_transfer::run_cli(&pool, &args).await? {
+        return Ok(());
+    }
+
     let stats = library_sync::sync_audio_library(&pool).await?;
 
     info!(
@@ -66,11 +76,28 @@ async fn main() -> Result<(), Error> {
         "Library sync complete"
     );
 
+    let interrupted = db::chronicle::mark_interrupted_sessions(&pool, chronicle::session::unix_now()).await?;
+    if interrupted > 0 {
+        tracing::warn!(interrupted, "Marked chronicle sessions left recording as interrupted");
+    }
+
+    let backup_config = db::backup::BackupConfig::from_env();
+    tokio::spawn(db::backup::run_backup_schedule(pool.clone(), backup_config.clone()));
+
+    let chronicle_config = chronicle::config::ChronicleConfig::load()?;
+    info!(
+        enabled = chronicle_config.enabled,
+        storage_root = %chronicle_config.storage_root.display(),
+        "Loaded chronicle config"
+    );
+    tokio::spawn(chronicle::retention::run_retention_schedule(pool.clone(), chronicle_config.clone()));
+
     let token = std::env::var("DISCORD_TOKEN").expect("missing DISCORD_TOKEN in .env");
 
     let poise_commands = vec![
         discord::commands::admin::help(),
         discord::commands::admin::register(),
+        discord::commands::admin::backup(),
         discord::commands::controls::join(),
         discord::commands::controls::play(),
         discord::commands::controls::leave(),
@@ -82,7 +109,11 @@ async fn main() -> Result<(), Error> {
         discord::commands::management::add_tag(),
         discord::commands::management::set_metadata(),
         discord::commands::management::fix(),
+        discord::commands::management::sharing(),
         discord::commands::browse::library(),
+        discord::commands::transfer::export(),
+        discord::commands::transfer::import(),
+        discord::commands::chronicle::chronicle(),
     ];
 
     let poise_options = poise::FrameworkOptions {
@@ -106,13 +137,13 @@ async fn main() -> Result<(), Error> {
             })
         },
         skip_checks_for_owners: true,
-        event_handler: |_ctx, event, _framework, _data| {
+        event_handler: |ctx, event, _framework, data| {
             Box::pin(async move {
                 tracing::debug!(
                     "Got an event in event handler: {:?}",
                     event.snake_case_name()
                 );
-                Ok(())
+                discord::events::handle_event(ctx, event, data).await
             })
         },
         ..Default::default()
@@ -121,10 +152,16 @@ async fn main() -> Result<(), Error> {
     // 1) Build your Poise framework
     let framework = poise::Framework::builder()
         .options(poise_options)
-        .setup(|_ctx, _ready, _framework| {
+        .setup(|ctx, _ready, _framework| {
             Box::pin(async move {
                 // poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data::new(pool))
+                let data = Data::new(pool, backup_config, chronicle_config);
+                // Sessions whose processing a restart cut short carry on where they stopped
+                let resumed = data.pipeline.resume(&data.db_pool, &data.chronicle_config, ctx.http.clone()).await?;
+                if resumed > 0 {
+                    info!(resumed, "Resumed chronicle processing");
+                }
+                Ok(data)
             })
         })
         .build();
@@ -1,4 +1,6 @@
 use crate::definitions::{PoiseContext, Error};
+use crate::utils::metadata_archive::archive_info_json;
+use crate::utils::metadata_suggest::{suggest_metadata, MetadataSuggestion};
 
 use songbird::Call;
 use tokio::sync::Mutex;
@@ -10,9 +12,11 @@ use anyhow::{Context, Result};
 use serde_json::{json, Value};
 use std::fs;
 
+/// Reads yt-dlp's info JSON for a download, returning the slimmed record kept in the
+/// database and metadata suggestions drawn from the full document.
 pub fn process_ytdlp_json(
     file_id: String
-) -> Result<serde_json::Value> {
+) -> Result<(serde_json::Value, MetadataSuggestion)> {
     let path = format!("audio/{file_id}.info.json");
     let content = fs::read_to_string(&path)
         .with_context(|| format!("Failed to read {:?}", path))?;
@@ -29,9 +33,16 @@ pub fn process_ytdlp_json(
         "channel": v.get("channel").cloned().ok_or_else(|| anyhow::anyhow!("Missing 'channel' field in yt-dlp JSON"))?,
     });
 
+    let suggestion = suggest_metadata(&v);
+
+    // Keep the full document before the working copy is removed; the download itself
+    // succeeded, so a failure here only costs the archived copy
+    if let Err(e) = archive_info_json(&file_id, &content) {
+        tracing::warn!("Couldn't archive the info JSON of {}: {}", file_id, e);
+    }
     fs::remove_file(&path).ok();
 
-    Ok(slim)
+    Ok((slim, suggestion))
 }
 
 pub fn get_youtube_id(link: &str) -> Option<String> {
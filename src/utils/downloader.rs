@@ -1,27 +1,80 @@
 use std::process::Command;
+use poise::serenity_prelude::GuildId;
 use serde_json::Value;
 use sqlx::SqlitePool;
 
 use crate::utils::context::{get_youtube_id, process_ytdlp_json};
+use crate::utils::metadata_suggest::MetadataSuggestion;
 use crate::definitions::{Error, MetadataKind, TrackInfo, VideoId};
-use crate::db::repository::{get_or_insert_metadata_id, insert_new_track, lookup_track};
+use crate::db::repository::{
+    add_guild_track, get_or_insert_metadata_id, insert_new_track, lookup_track, require_track,
+    track_exists, update_track_artist, update_track_origin, update_track_title,
+};
 
+pub struct DownloadOutcome {
+    pub track: TrackInfo,
+    /// Suggestions for fields the caller left blank that weren't applied
+    pub pending: MetadataSuggestion,
+}
+
+/// Uses the caller's value if given, otherwise the suggestion when accepted, otherwise
+/// the fallback (recording the unused suggestion in `pending`).
+fn choose_field(
+    provided: Option<String>,
+    suggested: Option<String>,
+    accept_suggestions: bool,
+    fallback: String,
+    pending: &mut Option<String>,
+) -> String {
+    if let Some(value) = provided {
+        return value;
+    }
+    match suggested {
+        Some(s) if accept_suggestions => s,
+        Some(s) if s != fallback => {
+            *pending = Some(s);
+            fallback
+        }
+        _ => fallback,
+    }
+}
 
 pub async fn download_track(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     yt_link: String,
     track_artist: Option<String>,
     track_origin: Option<String>,
     track_title: Option<String>,
-) -> Result<TrackInfo, Error> {
+    accept_suggestions: bool,
+) -> Result<DownloadOutcome, Error> {
     let video_id = VideoId::from(
         get_youtube_id(&yt_link)
             .ok_or("Invalid YouTube link")?
     );
 
     // Guard against duplicate downloads
-    if let Some(track) = lookup_track(db_pool, &video_id).await? {
-        return Ok(track);
+    if let Some(track) = lookup_track(db_pool, guild_id, &video_id).await? {
+        return Ok(DownloadOutcome { track, pending: MetadataSuggestion::default() });
+    }
+
+    // Another guild already has the audio; just bring it into this guild's library,
+    // keeping whatever the caller gave as this guild's own metadata
+    if track_exists(db_pool, &video_id).await? {
+        add_guild_track(db_pool, guild_id, &video_id).await?;
+        if let Some(title) = &track_title {
+            update_track_title(db_pool, guild_id, &video_id, title).await?;
+        }
+        if let Some(artist) = &track_artist {
+            let artist_id = get_or_insert_metadata_id(db_pool, MetadataKind::Artist, artist).await?;
+            update_track_artist(db_pool, guild_id, &video_id, artist_id).await?;
+        }
+        if let Some(origin) = &track_origin {
+            let origin_id = get_or_insert_metadata_id(db_pool, MetadataKind::Origin, origin).await?;
+            update_track_origin(db_pool, guild_id, &video_id, origin_id).await?;
+        }
+        let track = require_track(db_pool, guild_id, &video_id).await?;
+        return Ok(DownloadOutcome { track, pending: MetadataSuggestion::default() });
     }
 
     let output = Command::new("./yt-dlp")
@@ -46,7 +99,7 @@ pub async fn download_track(
         .into());
     }
 
-    let slim = process_ytdlp_json(video_id.as_str().to_string())
+    let (slim, suggestion) = process_ytdlp_json(video_id.as_str().to_string())
         .map_err(|e| {
             format!(
                 "Failed to process metadata JSON for video ID `{}`: {}",
@@ -55,20 +108,26 @@ pub async fn download_track(
             )
         })?;
 
-    let title = track_title.unwrap_or_else(|| {
-        slim.get("title")
-            .and_then(Value::as_str)
-            .unwrap_or("Unknown Title")
-            .to_string()
-    });
+    let mut pending = MetadataSuggestion::default();
 
-    let artist = track_artist.unwrap_or_else(|| {
-        "No artist provided".to_string()
-    });
+    let yt_title = slim.get("title")
+        .and_then(Value::as_str)
+        .unwrap_or("Unknown Title")
+        .to_string();
 
-    let origin = track_origin.unwrap_or_else(|| {
-        "No origin provided".to_string()
-    });
+    let title = choose_field(
+        track_title, suggestion.title, accept_suggestions, yt_title, &mut pending.title,
+    );
+
+    let artist = choose_field(
+        track_artist, suggestion.artist, accept_suggestions,
+        "No artist provided".to_string(), &mut pending.artist,
+    );
+
+    let origin = choose_field(
+        track_origin, suggestion.origin, accept_suggestions,
+        "No origin provided".to_string(), &mut pending.origin,
+    );
 
     let artist_id =
         get_or_insert_metadata_id(db_pool, MetadataKind::Artist, &artist).await?;
@@ -76,12 +135,17 @@ pub async fn download_track(
     let origin_id =
         get_or_insert_metadata_id(db_pool, MetadataKind::Origin, &origin).await?;
     
-    insert_new_track(db_pool, &video_id, &slim, &title, artist_id, origin_id).await?;
+    // New downloads stay private to the guild until someone shares them
+    insert_new_track(db_pool, &video_id, &slim, &title, artist_id, origin_id, Some(guild_id)).await?;
+    add_guild_track(db_pool, guild_id, &video_id).await?;
 
-    Ok(TrackInfo {
-        id: video_id,
-        title,
-        artist,
-        origin,
+    Ok(DownloadOutcome {
+        track: TrackInfo {
+            id: video_id,
+            title,
+            artist,
+            origin,
+        },
+        pending,
     })
 }
\ No newline at end of file
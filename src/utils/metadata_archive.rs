@@ -0,0 +1,62 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+
+const METADATA_DIR: &str = "metadata";
+
+fn archive_path(id: &str) -> PathBuf {
+    PathBuf::from(METADATA_DIR).join(format!("{id}.info.json.gz"))
+}
+
+/// Stores yt-dlp's full info JSON for a track, gzipped, so fields we don't use yet
+/// (thumbnails, chapters, description, duration) never have to be fetched again.
+pub fn archive_info_json(id: &str, raw: &str) -> Result<PathBuf> {
+    fs::create_dir_all(METADATA_DIR)
+        .context("Failed to create metadata directory")?;
+
+    let path = archive_path(id);
+    let tmp_path = path.with_extension("gz.part");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(raw.as_bytes())
+        .with_context(|| format!("Failed to compress metadata for {id}"))?;
+    let compressed = encoder.finish()
+        .with_context(|| format!("Failed to compress metadata for {id}"))?;
+
+    // Write then rename so a crash never leaves a truncated archive behind
+    fs::write(&tmp_path, compressed)
+        .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+    fs::rename(&tmp_path, &path)
+        .with_context(|| format!("Failed to finalise {:?}", path))?;
+
+    Ok(path)
+}
+
+pub fn has_info_json(id: &str) -> bool {
+    archive_path(id).exists()
+}
+
+/// Loads a track's archived info JSON, or `None` if it was never archived.
+pub fn load_info_json(id: &str) -> Result<Option<Value>> {
+    let path = archive_path(id);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let file = fs::File::open(&path)
+        .with_context(|| format!("Failed to open {:?}", path))?;
+    let mut content = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut content)
+        .with_context(|| format!("Failed to decompress {:?}", path))?;
+
+    let v = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse JSON from {:?}", path))?;
+    Ok(Some(v))
+}
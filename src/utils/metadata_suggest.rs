@@ -0,0 +1,263 @@
+use serde_json::Value;
+
+/// Bracketed suffixes that describe the upload rather than the track.
+const TITLE_NOISE: &[&str] = &[
+    "official video", "official music video", "official audio", "official lyric video",
+    "official", "audio", "lyrics", "lyric video", "hd", "hq", "4k", "visualizer",
+    "music video", "full version", "extended",
+];
+
+/// Words that mark one side of a "X - Y" title as a soundtrack name.
+const SOUNDTRACK_MARKERS: &[&str] = &[
+    "original soundtrack", "original score", "soundtrack", "ost", "o.s.t.", "bgm", "music",
+];
+
+const TOPIC_SUFFIX: &str = " - Topic";
+const VEVO_SUFFIX: &str = "VEVO";
+const YOUTUBE_MUSIC_MARKER: &str = "Provided to YouTube by";
+
+/// Metadata guessed from a yt-dlp info JSON. `None` fields had nothing usable.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataSuggestion {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub origin: Option<String>,
+}
+
+impl MetadataSuggestion {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.artist.is_none() && self.origin.is_none()
+    }
+
+    /// Fills any empty field from `other`, so earlier sources take priority.
+    fn or(self, other: MetadataSuggestion) -> Self {
+        Self {
+            title: self.title.or(other.title),
+            artist: self.artist.or(other.artist),
+            origin: self.origin.or(other.origin),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(title) = &self.title {
+            parts.push(format!("title → `{}`", title));
+        }
+        if let Some(artist) = &self.artist {
+            parts.push(format!("artist → `{}`", artist));
+        }
+        if let Some(origin) = &self.origin {
+            parts.push(format!("origin → `{}`", origin));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Proposes a title, artist and origin from a full yt-dlp info JSON.
+///
+/// Sources are tried from most to least reliable: the music fields yt-dlp fills in
+/// for licensed tracks, the auto-generated YouTube Music description, common title
+/// layouts, and finally the uploading channel.
+pub fn suggest_metadata(info: &Value) -> MetadataSuggestion {
+    let title = info.get("title").and_then(Value::as_str).unwrap_or_default();
+    let chapters = info.get("chapters").and_then(Value::as_array).map_or(0, Vec::len);
+
+    let from_fields = MetadataSuggestion {
+        title: string_field(info, "track"),
+        artist: string_field(info, "artist")
+            .or_else(|| first_of(info, "artists"))
+            .or_else(|| string_field(info, "creator"))
+            .or_else(|| first_of(info, "creators")),
+        origin: string_field(info, "album"),
+    };
+
+    let from_description = info
+        .get("description")
+        .and_then(Value::as_str)
+        .map(parse_youtube_music_description)
+        .unwrap_or_default();
+
+    // A video with several chapters is a compilation, so its title names the collection
+    let from_title = if chapters > 1 {
+        MetadataSuggestion { origin: non_empty(&strip_title_noise(title)), ..Default::default() }
+    } else {
+        parse_title(title)
+    };
+
+    let from_channel = MetadataSuggestion {
+        artist: info
+            .get("channel")
+            .and_then(Value::as_str)
+            .and_then(artist_from_channel),
+        ..Default::default()
+    };
+
+    from_fields
+        .or(from_description)
+        .or(from_title)
+        .or(from_channel)
+}
+
+fn string_field(info: &Value, key: &str) -> Option<String> {
+    info.get(key).and_then(Value::as_str).and_then(|s| non_empty(s.trim()))
+}
+
+fn first_of(info: &Value, key: &str) -> Option<String> {
+    info.get(key)
+        .and_then(Value::as_array)
+        .and_then(|a| a.first())
+        .and_then(Value::as_str)
+        .and_then(|s| non_empty(s.trim()))
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() { None } else { Some(s.to_string()) }
+}
+
+/// Auto-generated YouTube Music uploads describe themselves as:
+///
+/// ```text
+/// Provided to YouTube by Label
+///
+/// Title · Artist · Other Artist
+///
+/// Album
+/// ```
+fn parse_youtube_music_description(description: &str) -> MetadataSuggestion {
+    if !description.contains(YOUTUBE_MUSIC_MARKER) {
+        return MetadataSuggestion::default();
+    }
+
+    let mut lines = description
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .skip_while(|l| !l.starts_with(YOUTUBE_MUSIC_MARKER))
+        .skip(1);
+
+    let Some(credits) = lines.next() else {
+        return MetadataSuggestion::default();
+    };
+    let mut credits = credits.split(" · ").map(str::trim);
+
+    MetadataSuggestion {
+        title: credits.next().and_then(non_empty),
+        artist: credits.next().and_then(non_empty),
+        origin: lines
+            .next()
+            .filter(|l| !l.starts_with('℗') && !l.starts_with("Released on"))
+            .and_then(non_empty),
+    }
+}
+
+/// Recognises "Game OST - Track", "Track - Game OST", "Artist - Title",
+/// "Title | Game OST" and "Title (from \"Movie\")".
+fn parse_title(raw: &str) -> MetadataSuggestion {
+    let title = strip_title_noise(raw);
+
+    if let Some(result) = parse_from_clause(&title) {
+        return result;
+    }
+
+    for separator in [" - ", " – ", " — ", " | "] {
+        let Some((left, right)) = title.split_once(separator) else {
+            continue;
+        };
+        let (left, right) = (left.trim(), right.trim());
+        if left.is_empty() || right.is_empty() {
+            continue;
+        }
+
+        if let Some(origin) = strip_soundtrack_marker(left) {
+            return MetadataSuggestion {
+                title: non_empty(right),
+                origin: non_empty(&origin),
+                ..Default::default()
+            };
+        }
+        if let Some(origin) = strip_soundtrack_marker(right) {
+            return MetadataSuggestion {
+                title: non_empty(left),
+                origin: non_empty(&origin),
+                ..Default::default()
+            };
+        }
+        // A pipe usually separates a title from a channel slogan, not an artist
+        if separator == " | " {
+            return MetadataSuggestion { title: non_empty(left), ..Default::default() };
+        }
+        return MetadataSuggestion {
+            title: non_empty(right),
+            artist: non_empty(left),
+            ..Default::default()
+        };
+    }
+
+    MetadataSuggestion::default()
+}
+
+/// `Title (from "Movie")` / `Title [From "Game"]`
+fn parse_from_clause(title: &str) -> Option<MetadataSuggestion> {
+    let start = find_ignore_case(title, "(from ").or_else(|| find_ignore_case(title, "[from "))?;
+    let inner = title.get(start + 6..)?;
+    let end = inner.find([')', ']'])?;
+    let origin = inner[..end].trim().trim_matches(['"', '“', '”', '\'']);
+
+    Some(MetadataSuggestion {
+        title: non_empty(title.get(..start)?.trim()),
+        origin: non_empty(origin.trim()),
+        ..Default::default()
+    })
+}
+
+/// Drops bracketed upload noise such as "(Official Video)" or "[HD]".
+fn strip_title_noise(title: &str) -> String {
+    let mut out = title.to_string();
+    for (open, close) in [('(', ')'), ('[', ']'), ('【', '】')] {
+        let mut from = 0;
+        while let Some(offset) = out[from..].find(open) {
+            let start = from + offset;
+            let Some(len) = out[start..].find(close) else { break };
+            let end = start + len + close.len_utf8();
+            let inner = out[start + open.len_utf8()..start + len].trim().to_lowercase();
+            if TITLE_NOISE.iter().any(|n| inner == *n) {
+                out.replace_range(start..end, "");
+                from = start;
+            } else {
+                from = end;
+            }
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the name with its soundtrack marker removed, if it had one.
+fn strip_soundtrack_marker(part: &str) -> Option<String> {
+    SOUNDTRACK_MARKERS.iter().find_map(|marker| {
+        let stripped = part.get(..part.len().checked_sub(marker.len())?)?;
+        if !part[stripped.len()..].eq_ignore_ascii_case(marker) {
+            return None;
+        }
+        if !stripped.is_empty() && !stripped.ends_with(' ') {
+            return None;
+        }
+        non_empty(stripped.trim().trim_end_matches([':', '-']).trim())
+    })
+}
+
+/// Byte offset of the first match of an ASCII `needle`, ignoring case. Lowercasing
+/// the whole string instead can shift offsets, since some characters change length.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| haystack.get(i..i + needle.len()).is_some_and(|s| s.eq_ignore_ascii_case(needle)))
+}
+
+/// "Artist - Topic" and "ArtistVEVO" channels are run on the artist's behalf.
+fn artist_from_channel(channel: &str) -> Option<String> {
+    channel
+        .strip_suffix(TOPIC_SUFFIX)
+        .or_else(|| channel.strip_suffix(VEVO_SUFFIX))
+        .and_then(|a| non_empty(a.trim()))
+}
@@ -1,4 +1,6 @@
 pub mod context;
 pub mod downloader;
 pub mod format;
+pub mod metadata_archive;
+pub mod metadata_suggest;
 pub mod track_resolver;
\ No newline at end of file
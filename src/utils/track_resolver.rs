@@ -2,6 +2,7 @@ use crate::definitions::{Error, TrackInfo, VideoId};
 use crate::utils::context::get_youtube_id;
 use crate::utils::downloader::download_track;
 use crate::db::repository::lookup_track;
+use poise::serenity_prelude::GuildId;
 use sqlx::SqlitePool;
 
 pub fn normalise_track_input(input: &str) -> VideoId {
@@ -13,13 +14,16 @@ pub fn normalise_track_input(input: &str) -> VideoId {
 
 pub async fn resolve_track(
     db_pool: &SqlitePool,
+    guild_id: GuildId,
     input: String,
 ) -> Result<TrackInfo, Error> {
     let video_id = normalise_track_input(&input);
 
-    if let Some(track) = lookup_track(db_pool, &video_id).await? {
+    if let Some(track) = lookup_track(db_pool, guild_id, &video_id).await? {
         return Ok(track);
     }
 
-    download_track(db_pool, input, None, None, None).await
+    // Nobody is around to review suggestions when playing a link, so take them as-is
+    let outcome = download_track(db_pool, guild_id, input, None, None, None, true).await?;
+    Ok(outcome.track)
 }
\ No newline at end of file